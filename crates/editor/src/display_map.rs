@@ -682,7 +682,12 @@ impl DisplaySnapshot {
         .map(|chunk| {
             let mut highlight_style = chunk
                 .syntax_highlight_id
-                .and_then(|id| id.style(&editor_style.syntax));
+                .and_then(|id| id.style(&editor_style.syntax))
+                .map(|style| {
+                    editor_style
+                        .syntax
+                        .injected_style(style, chunk.syntax_depth)
+                });
 
             if let Some(chunk_highlight) = chunk.highlight_style {
                 if let Some(highlight_style) = highlight_style.as_mut() {
@@ -7993,10 +7993,7 @@ impl Editor {
 
                 // If the language has line comments, toggle those.
                 let full_comment_prefixes = language.line_comment_prefixes();
-                if !full_comment_prefixes.is_empty() {
-                    let first_prefix = full_comment_prefixes
-                        .first()
-                        .expect("prefixes is non-empty");
+                if let Some(first_prefix) = language.toggle_comment_prefix() {
                     let prefix_trimmed_lengths = full_comment_prefixes
                         .iter()
                         .map(|p| p.trim_end_matches(' ').len())
@@ -4636,12 +4636,14 @@ async fn test_autoindent_selections(cx: &mut gpui::TestAppContext) {
                             end: "}".to_string(),
                             close: false,
                             newline: true,
+                            surround: false,
                         },
                         BracketPair {
                             start: "(".to_string(),
                             end: ")".to_string(),
                             close: false,
                             newline: true,
+                            surround: false,
                         },
                     ],
                     ..Default::default()
@@ -4698,30 +4700,35 @@ async fn test_autoclose_pairs(cx: &mut gpui::TestAppContext) {
                         end: "}".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "(".to_string(),
                         end: ")".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "/*".to_string(),
                         end: " */".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "[".to_string(),
                         end: "]".to_string(),
                         close: false,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "\"".to_string(),
                         end: "\"".to_string(),
                         close: true,
                         newline: false,
+                        surround: false,
                     },
                 ],
                 ..Default::default()
@@ -4869,18 +4876,21 @@ async fn test_always_treat_brackets_as_autoclosed_skip_over(cx: &mut gpui::TestA
                         end: "}".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "(".to_string(),
                         end: ")".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "[".to_string(),
                         end: "]".to_string(),
                         close: false,
                         newline: true,
+                        surround: false,
                     },
                 ],
                 ..Default::default()
@@ -5294,6 +5304,7 @@ async fn test_surround_with_pair(cx: &mut gpui::TestAppContext) {
                         end: "}".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "/* ".to_string(),
@@ -5448,6 +5459,7 @@ async fn test_delete_autoclose_pair(cx: &mut gpui::TestAppContext) {
                     end: "}".to_string(),
                     close: true,
                     newline: true,
+                    surround: false,
                 }],
                 ..Default::default()
             },
@@ -5559,18 +5571,21 @@ async fn test_always_treat_brackets_as_autoclosed_delete(cx: &mut gpui::TestAppC
                         end: "}".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "(".to_string(),
                         end: ")".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     },
                     BracketPair {
                         start: "[".to_string(),
                         end: "]".to_string(),
                         close: false,
                         newline: true,
+                        surround: false,
                     },
                 ],
                 ..Default::default()
@@ -7538,12 +7553,14 @@ async fn test_extra_newline_insertion(cx: &mut gpui::TestAppContext) {
                             end: "}".to_string(),
                             close: true,
                             newline: true,
+                            surround: false,
                         },
                         BracketPair {
                             start: "/* ".to_string(),
                             end: " */".to_string(),
                             close: true,
                             newline: true,
+                            surround: false,
                         },
                     ],
                     ..Default::default()
@@ -8345,6 +8362,7 @@ async fn test_on_type_formatting_not_triggered(cx: &mut gpui::TestAppContext) {
                     end: "}".to_string(),
                     close: true,
                     newline: true,
+                    surround: false,
                 }],
                 disabled_scopes_by_bracket_ix: Vec::new(),
             },
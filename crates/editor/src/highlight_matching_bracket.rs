@@ -56,12 +56,14 @@ mod tests {
                                 end: "}".to_string(),
                                 close: false,
                                 newline: true,
+                                surround: false,
                             },
                             BracketPair {
                                 start: "(".to_string(),
                                 end: ")".to_string(),
                                 close: false,
                                 newline: true,
+                                surround: false,
                             },
                         ],
                         ..Default::default()
@@ -182,6 +182,7 @@ impl EditorLspTestContext {
                         end: "}".to_string(),
                         close: true,
                         newline: true,
+                        surround: false,
                     }],
                     disabled_scopes_by_bracket_ix: Default::default(),
                 },
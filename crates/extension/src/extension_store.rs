@@ -33,7 +33,8 @@ use gpui::{
 };
 use http::{AsyncBody, HttpClient, HttpClientWithUrl};
 use language::{
-    LanguageConfig, LanguageMatcher, LanguageQueries, LanguageRegistry, QUERY_FILENAME_PREFIXES,
+    decode_source, LanguageConfig, LanguageMatcher, LanguageQueries, LanguageRegistry,
+    QUERY_FILENAME_PREFIXES,
 };
 use node_runtime::NodeRuntime;
 use project::ContextProviderWithTasks;
@@ -161,6 +162,8 @@ pub struct ExtensionIndexThemeEntry {
 pub struct ExtensionIndexLanguageEntry {
     extension: Arc<str>,
     path: PathBuf,
+    #[serde(default)]
+    id: Option<String>,
     matcher: LanguageMatcher,
     grammar: Option<Arc<str>>,
 }
@@ -1068,12 +1071,13 @@ impl ExtensionStore {
             ]);
             self.language_registry.register_language(
                 language_name.clone(),
+                language.id.clone(),
                 language.grammar.clone(),
                 language.matcher.clone(),
                 move || {
                     let config = std::fs::read_to_string(language_path.join("config.toml"))?;
                     let config: LanguageConfig = ::toml::from_str(&config)?;
-                    let queries = load_plugin_queries(&language_path);
+                    let queries = load_plugin_queries(&language_path, &config);
                     let tasks = std::fs::read_to_string(language_path.join("tasks.json"))
                         .ok()
                         .and_then(|contents| {
@@ -1285,6 +1289,7 @@ impl ExtensionStore {
                     ExtensionIndexLanguageEntry {
                         extension: extension_id.clone(),
                         path: relative_path,
+                        id: config.id,
                         matcher: config.matcher,
                         grammar: config.grammar,
                     },
@@ -1343,7 +1348,14 @@ impl ExtensionStore {
     }
 }
 
-fn load_plugin_queries(root_path: &Path) -> LanguageQueries {
+fn read_query(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+    let query =
+        decode_source(&bytes).with_context(|| format!("failed to decode query {path:?}"))?;
+    Ok(query.into_owned())
+}
+
+fn load_plugin_queries(root_path: &Path, config: &LanguageConfig) -> LanguageQueries {
     let mut result = LanguageQueries::default();
     if let Some(entries) = std::fs::read_dir(root_path).log_err() {
         for entry in entries {
@@ -1356,8 +1368,11 @@ fn load_plugin_queries(root_path: &Path) -> LanguageQueries {
                     continue;
                 }
                 for (name, query) in QUERY_FILENAME_PREFIXES {
+                    if *name == "highlights" && !config.highlight_queries.is_empty() {
+                        continue;
+                    }
                     if remainder.starts_with(name) {
-                        if let Some(contents) = std::fs::read_to_string(&path).log_err() {
+                        if let Some(contents) = read_query(&path).log_err() {
                             match query(&mut result) {
                                 None => *query(&mut result) = Some(contents.into()),
                                 Some(r) => r.to_mut().push_str(contents.as_ref()),
@@ -1369,5 +1384,18 @@ fn load_plugin_queries(root_path: &Path) -> LanguageQueries {
             }
         }
     }
+
+    for file_name in &config.highlight_queries {
+        if let Some(contents) = read_query(&root_path.join(file_name)).log_err() {
+            match &mut result.highlights {
+                None => result.highlights = Some(contents.into()),
+                Some(highlights) => {
+                    let highlights = highlights.to_mut();
+                    highlights.push('\n');
+                    highlights.push_str(&contents);
+                }
+            }
+        }
+    }
     result
 }
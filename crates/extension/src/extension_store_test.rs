@@ -195,10 +195,14 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 ExtensionIndexLanguageEntry {
                     extension: "zed-ruby".into(),
                     path: "languages/erb".into(),
+                    id: None,
                     grammar: Some("embedded_template".into()),
                     matcher: LanguageMatcher {
                         path_suffixes: vec!["erb".into()],
+                        path_globs: Vec::new(),
                         first_line_pattern: None,
+                        content_patterns: Vec::new(),
+                        priority: 0,
                     },
                 },
             ),
@@ -207,10 +211,14 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 ExtensionIndexLanguageEntry {
                     extension: "zed-ruby".into(),
                     path: "languages/ruby".into(),
+                    id: None,
                     grammar: Some("ruby".into()),
                     matcher: LanguageMatcher {
                         path_suffixes: vec!["rb".into()],
+                        path_globs: Vec::new(),
                         first_line_pattern: None,
+                        content_patterns: Vec::new(),
+                        priority: 0,
                     },
                 },
             ),
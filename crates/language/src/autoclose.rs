@@ -0,0 +1,135 @@
+use crate::{
+    syntax_map::{QueryCursorHandle, TextProvider},
+    BracketPair, Language,
+};
+use text::Rope;
+use tree_sitter::Tree;
+
+impl Language {
+    /// Returns whether typing the start of the given bracket pair at `offset` should
+    /// also insert its end, where `tree` is the parsed syntax tree of `source`.
+    ///
+    /// Pairs whose start and end are the same, such as quotes, aren't closed within
+    /// the nodes captured as `@string` or `@comment` in the language's overrides query,
+    /// nor when they're typed right before their end, as the end is skipped over instead.
+    pub fn should_autoclose(
+        &self,
+        tree: &Tree,
+        source: &Rope,
+        offset: usize,
+        pair: &BracketPair,
+    ) -> bool {
+        if !pair.close {
+            return false;
+        }
+        if pair.start != pair.end {
+            return true;
+        }
+
+        let next_text = source.chunks_in_range(offset..source.len());
+        if next_text
+            .flat_map(str::chars)
+            .take(pair.end.chars().count())
+            .eq(pair.end.chars())
+        {
+            return false;
+        }
+
+        let Some(override_config) = self
+            .grammar
+            .as_ref()
+            .and_then(|grammar| grammar.override_config.as_ref())
+        else {
+            return true;
+        };
+
+        // Only the captures around the innermost node at the offset can enclose it.
+        let root = tree.root_node();
+        let node = root
+            .descendant_for_byte_range(offset.saturating_sub(1), offset)
+            .unwrap_or(root);
+        let mut cursor = QueryCursorHandle::new();
+        cursor.set_byte_range(node.byte_range());
+        for (mat, capture_ix) in cursor.captures(&override_config.query, root, TextProvider(source))
+        {
+            let capture = mat.captures[capture_ix];
+            let Some((name, _)) = override_config.values.get(&capture.index) else {
+                continue;
+            };
+            // A string's range ends with its closing quote, so an offset at its end is
+            // outside of it, whereas an offset at the end of a line comment is within it.
+            let range = capture.node.byte_range();
+            let encloses_offset = match name.as_str() {
+                "string" => range.start < offset && offset < range.end,
+                "comment" => range.start < offset && offset <= range.end,
+                _ => false,
+            };
+            if encloses_offset {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+    use tree_sitter::Parser;
+
+    #[test]
+    fn test_should_autoclose() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_override_query(
+            r#"
+            [(line_comment) (block_comment)] @comment
+            (string_literal) @string
+            "#,
+        )
+        .unwrap();
+        let quote = BracketPair {
+            start: "\"".into(),
+            end: "\"".into(),
+            close: true,
+            ..Default::default()
+        };
+        let paren = BracketPair {
+            start: "(".into(),
+            end: ")".into(),
+            close: true,
+            ..Default::default()
+        };
+
+        let source = "let a = \"bc\"; // d";
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let source = Rope::from(source);
+        let should_autoclose =
+            |offset, pair| language.should_autoclose(&tree, &source, offset, pair);
+
+        // Outside of strings.
+        assert!(should_autoclose("let a = ".len(), &quote));
+        assert!(should_autoclose("let a = \"bc\";".len(), &quote));
+        // Inside of a string, and before its closing quote, which is skipped over.
+        assert!(!should_autoclose("let a = \"b".len(), &quote));
+        assert!(!should_autoclose("let a = \"bc".len(), &quote));
+        // Inside of a comment, including at its end.
+        assert!(!should_autoclose("let a = \"bc\"; // ".len(), &quote));
+        assert!(!should_autoclose(source.len(), &quote));
+
+        // Other brackets are closed regardless of the scope.
+        assert!(should_autoclose("let a = \"b".len(), &paren));
+        assert!(!should_autoclose(
+            "let a = ".len(),
+            &BracketPair {
+                close: false,
+                ..quote.clone()
+            }
+        ));
+    }
+}
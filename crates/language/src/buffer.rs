@@ -1,5 +1,6 @@
 pub use crate::{
     diagnostic_set::DiagnosticSet,
+    error_highlights::error_highlights,
     highlight_map::{HighlightId, HighlightMap},
     markdown::ParsedMarkdown,
     proto, Grammar, Language, LanguageRegistry,
@@ -14,10 +15,11 @@ use crate::{
         SyntaxSnapshot, ToTreeSitterPoint,
     },
     task_context::RunnableRange,
-    LanguageScope, Outline, RunnableCapture, RunnableTag,
+    LanguageScope, Outline, ParseOutcome, RunnableCapture, RunnableTag,
 };
 use anyhow::{anyhow, Context, Result};
 pub use clock::ReplicaId;
+use collections::HashSet;
 use futures::channel::oneshot;
 use gpui::{
     AnyElement, AppContext, EventEmitter, HighlightStyle, ModelContext, Task, TaskLabel,
@@ -104,6 +106,7 @@ pub struct Buffer {
     syntax_map: Mutex<SyntaxMap>,
     parsing_in_background: bool,
     parse_count: usize,
+    parse_outcome: ParseOutcome,
     diagnostics: SmallVec<[(LanguageServerId, DiagnosticSet); 2]>,
     remote_selections: TreeMap<ReplicaId, SelectionSet>,
     selections_update_count: usize,
@@ -430,13 +433,35 @@ struct IndentSuggestion {
     basis_row: u32,
     delta: Ordering,
     within_error: bool,
+    /// The number of columns by which the line is aligned past the indentation of
+    /// the basis row, in place of the delta.
+    align_offset: Option<u32>,
+}
+
+impl IndentSuggestion {
+    /// Returns the suggested indentation, given the indentation of the basis row.
+    fn indent_size(&self, basis_indent: IndentSize, single_indent_size: IndentSize) -> IndentSize {
+        match self.align_offset {
+            Some(offset) if basis_indent.kind == IndentKind::Space || basis_indent.len == 0 => {
+                IndentSize::spaces(basis_indent.len + offset)
+            }
+            // Alignment can't be expressed past indentation that uses tabs.
+            Some(_) => basis_indent.with_delta(Ordering::Greater, single_indent_size),
+            None => basis_indent.with_delta(self.delta, single_indent_size),
+        }
+    }
 }
 
 struct BufferChunkHighlights<'a> {
     captures: SyntaxMapCaptures<'a>,
     next_capture: Option<SyntaxMapCapture<'a>>,
-    stack: Vec<(usize, HighlightId)>,
+    /// The end offset, highlight id and syntax layer depth of each enclosing capture.
+    stack: Vec<(usize, HighlightId, usize)>,
     highlight_maps: Vec<HighlightMap>,
+    /// Lexical highlights for the text that failed to parse, which only apply where
+    /// the highlights query doesn't capture anything.
+    error_highlights: Vec<(Range<usize>, HighlightId)>,
+    next_error_highlight_ix: usize,
 }
 
 /// An iterator that yields chunks of a buffer's text, along with their
@@ -461,6 +486,9 @@ pub struct Chunk<'a> {
     pub text: &'a str,
     /// The syntax highlighting style of the chunk.
     pub syntax_highlight_id: Option<HighlightId>,
+    /// The depth of the syntax layer that the syntax highlighting came from, which is
+    /// zero for the buffer's language and greater within injected languages.
+    pub syntax_depth: usize,
     /// The highlight style that has been applied to this chunk in
     /// the editor.
     pub highlight_style: Option<HighlightStyle>,
@@ -712,6 +740,7 @@ impl Buffer {
             syntax_map: Mutex::new(SyntaxMap::new()),
             parsing_in_background: false,
             parse_count: 0,
+            parse_outcome: ParseOutcome::Parsed,
             sync_parse_timeout: Duration::from_millis(1),
             autoindent_requests: Default::default(),
             pending_autoindent: Default::default(),
@@ -1023,6 +1052,12 @@ impl Buffer {
         self.parsing_in_background
     }
 
+    /// The outcome of the most recent parse, indicating whether the buffer was
+    /// too large to be parsed.
+    pub fn parse_outcome(&self) -> ParseOutcome {
+        self.parse_outcome
+    }
+
     /// Indicates whether the buffer contains any regions that may be
     /// written in a language that hasn't been loaded yet.
     pub fn contains_unknown_injections(&self) -> bool {
@@ -1071,8 +1106,20 @@ impl Buffer {
         let parsed_version = self.version();
 
         let mut syntax_map = self.syntax_map.lock();
-        syntax_map.interpolate(&text);
         let language_registry = syntax_map.language_registry();
+        let max_parse_bytes = language_registry
+            .as_ref()
+            .and_then(|registry| registry.max_parse_bytes());
+        self.parse_outcome = language.parse_outcome(text.len(), max_parse_bytes);
+        if let ParseOutcome::Skipped { .. } = self.parse_outcome {
+            syntax_map.clear();
+            drop(syntax_map);
+            self.parse_count += 1;
+            cx.emit(Event::Reparsed);
+            cx.notify();
+            return;
+        }
+        syntax_map.interpolate(&text);
         let mut syntax_snapshot = syntax_map.snapshot();
         drop(syntax_map);
 
@@ -1227,7 +1274,7 @@ impl Buffer {
                                 language_indent_sizes.next();
                             }
 
-                            let suggested_indent = old_to_new_rows
+                            let basis_indent = old_to_new_rows
                                 .get(&suggestion.basis_row)
                                 .and_then(|from_row| {
                                     Some(old_suggestions.get(from_row).copied()?.0)
@@ -1236,8 +1283,9 @@ impl Buffer {
                                     request
                                         .before_edit
                                         .indent_size_for_line(suggestion.basis_row)
-                                })
-                                .with_delta(suggestion.delta, language_indent_size);
+                                });
+                            let suggested_indent =
+                                suggestion.indent_size(basis_indent, language_indent_size);
                             old_suggestions
                                 .insert(new_row, (suggested_indent, suggestion.within_error));
                         }
@@ -1278,13 +1326,14 @@ impl Buffer {
                                 language_indent_sizes.next();
                             }
 
-                            let suggested_indent = indent_sizes
+                            let basis_indent = indent_sizes
                                 .get(&suggestion.basis_row)
                                 .copied()
                                 .unwrap_or_else(|| {
                                     snapshot.indent_size_for_line(suggestion.basis_row)
-                                })
-                                .with_delta(suggestion.delta, language_indent_size);
+                                });
+                            let suggested_indent =
+                                suggestion.indent_size(basis_indent, language_indent_size);
                             if old_suggestions.get(&new_row).map_or(
                                 true,
                                 |(old_indentation, was_within_error)| {
@@ -2231,11 +2280,11 @@ impl BufferSnapshot {
 
             for (row, suggestion) in row_range.zip(suggestions) {
                 let indent_size = if let Some(suggestion) = suggestion {
-                    result
+                    let basis_indent = result
                         .get(&suggestion.basis_row)
                         .copied()
-                        .unwrap_or_else(|| self.indent_size_for_line(suggestion.basis_row))
-                        .with_delta(suggestion.delta, single_indent_size)
+                        .unwrap_or_else(|| self.indent_size_for_line(suggestion.basis_row));
+                    suggestion.indent_size(basis_indent, single_indent_size)
                 } else {
                     self.indent_size_for_line(row)
                 };
@@ -2268,6 +2317,7 @@ impl BufferSnapshot {
             .collect::<Vec<_>>();
 
         let mut indent_ranges = Vec::<Range<Point>>::new();
+        let mut align_ranges = Vec::<Range<Point>>::new();
         let mut outdent_positions = Vec::<Point>::new();
         while let Some(mat) = matches.peek() {
             let mut start: Option<Point> = None;
@@ -2275,7 +2325,7 @@ impl BufferSnapshot {
 
             let config = &indent_configs[mat.grammar_index];
             for capture in mat.captures {
-                if capture.index == config.indent_capture_ix {
+                if Some(capture.index) == config.indent_capture_ix {
                     start.get_or_insert(Point::from_ts_point(capture.node.start_position()));
                     end.get_or_insert(Point::from_ts_point(capture.node.end_position()));
                 } else if Some(capture.index) == config.start_capture_ix {
@@ -2284,6 +2334,12 @@ impl BufferSnapshot {
                     end = Some(Point::from_ts_point(capture.node.start_position()));
                 } else if Some(capture.index) == config.outdent_capture_ix {
                     outdent_positions.push(Point::from_ts_point(capture.node.start_position()));
+                } else if Some(capture.index) == config.align_capture_ix {
+                    let start = Point::from_ts_point(capture.node.start_position());
+                    let end = Point::from_ts_point(capture.node.end_position());
+                    if start.row < end.row {
+                        align_ranges.push(start..end);
+                    }
                 }
             }
 
@@ -2328,6 +2384,7 @@ impl BufferSnapshot {
             matches.advance();
         }
 
+        align_ranges.sort_by_key(|range| range.start);
         outdent_positions.sort();
         for outdent_position in outdent_positions {
             // find the innermost indent range containing this outdent_position
@@ -2405,35 +2462,74 @@ impl BufferSnapshot {
                 }
             }
 
+            // Lines that begin within an aligned node are aligned with the start of the
+            // innermost such node, unless they're within an indented node nested inside it.
+            let mut align_range = None;
+            for range in &align_ranges {
+                if range.start.row >= row {
+                    break;
+                }
+                if range.end > prev_row_start && range.end <= row_start {
+                    outdent_to_row = outdent_to_row.min(range.start.row);
+                }
+                if range.end > row_start {
+                    align_range = Some(range);
+                }
+            }
+            let align_range = align_range.filter(|align_range| {
+                !indent_ranges.iter().any(|indent_range| {
+                    indent_range.start > align_range.start
+                        && indent_range.start.row < row
+                        && indent_range.end >= row_start
+                })
+            });
+
             let within_error = error_ranges
                 .iter()
                 .any(|e| e.start.row < row && e.end > row_start);
 
-            let suggestion = if outdent_to_row == prev_row
-                || (outdent_from_prev_row && indent_from_prev_row)
+            let suggestion = if let Some(align_range) = align_range {
+                let basis_row = align_range.start.row;
+                let basis_row_start =
+                    Point::new(basis_row, self.indent_size_for_line(basis_row).len);
+                let align_offset = self
+                    .text_for_range(basis_row_start.min(align_range.start)..align_range.start)
+                    .flat_map(str::chars)
+                    .count() as u32;
+                Some(IndentSuggestion {
+                    basis_row,
+                    delta: Ordering::Equal,
+                    within_error,
+                    align_offset: Some(align_offset),
+                })
+            } else if outdent_to_row == prev_row || (outdent_from_prev_row && indent_from_prev_row)
             {
                 Some(IndentSuggestion {
                     basis_row: prev_row,
                     delta: Ordering::Equal,
                     within_error,
+                    align_offset: None,
                 })
             } else if indent_from_prev_row {
                 Some(IndentSuggestion {
                     basis_row: prev_row,
                     delta: Ordering::Greater,
                     within_error,
+                    align_offset: None,
                 })
             } else if outdent_to_row < prev_row {
                 Some(IndentSuggestion {
                     basis_row: outdent_to_row,
                     delta: Ordering::Equal,
                     within_error,
+                    align_offset: None,
                 })
             } else if outdent_from_prev_row {
                 Some(IndentSuggestion {
                     basis_row: prev_row,
                     delta: Ordering::Less,
                     within_error,
+                    align_offset: None,
                 })
             } else if config.auto_indent_using_last_non_empty_line || !self.is_line_blank(prev_row)
             {
@@ -2441,6 +2537,7 @@ impl BufferSnapshot {
                     basis_row: prev_row,
                     delta: Ordering::Equal,
                     within_error,
+                    align_offset: None,
                 })
             } else {
                 None
@@ -2470,16 +2567,30 @@ impl BufferSnapshot {
         let range = range.start.to_offset(self)..range.end.to_offset(self);
 
         let mut syntax = None;
+        let mut error_highlights_in_range = Vec::new();
         let mut diagnostic_endpoints = Vec::new();
         if language_aware {
             let captures = self.syntax.captures(range.clone(), &self.text, |grammar| {
-                grammar.highlights_query.as_ref()
+                grammar.highlights_query.as_deref()
             });
             let highlight_maps = captures
                 .grammars()
                 .into_iter()
                 .map(|grammar| grammar.highlight_map())
                 .collect();
+            if captures
+                .grammars()
+                .iter()
+                .any(|grammar| grammar.highlights_errors_leniently())
+            {
+                error_highlights_in_range = error_highlights(
+                    self.syntax
+                        .layers_for_range(range.clone(), &self.text)
+                        .map(|layer| (layer.language, layer.node())),
+                    self.text.as_rope(),
+                    range.clone(),
+                );
+            }
             syntax = Some((captures, highlight_maps));
             for entry in self.diagnostics_in_range::<_, usize>(range.clone(), false) {
                 diagnostic_endpoints.push(DiagnosticEndpoint {
@@ -2499,7 +2610,10 @@ impl BufferSnapshot {
                 .sort_unstable_by_key(|endpoint| (endpoint.offset, !endpoint.is_start));
         }
 
-        BufferChunks::new(self.text.as_rope(), range, syntax, diagnostic_endpoints)
+        let mut chunks =
+            BufferChunks::new(self.text.as_rope(), range, syntax, diagnostic_endpoints);
+        chunks.set_error_highlights(error_highlights_in_range);
+        chunks
     }
 
     /// Invokes the given callback for each line of text in the given range of the buffer.
@@ -2714,6 +2828,19 @@ impl BufferSnapshot {
             .map(Outline::new)
     }
 
+    /// Returns the outline for the buffer, leaving out the items for which the given
+    /// predicate returns false, such as private items.
+    ///
+    /// The items within an item that is left out are kept, at the depth the item was at.
+    pub fn outline_with_filter(
+        &self,
+        theme: Option<&SyntaxTheme>,
+        predicate: impl Fn(&OutlineItem<Anchor>) -> bool,
+    ) -> Option<Outline<Anchor>> {
+        self.filtered_outline_items_containing(0..self.len(), true, theme, &predicate)
+            .map(Outline::new)
+    }
+
     /// Returns all the symbols that contain the given position.
     ///
     /// This method allows passing an optional [SyntaxTheme] to
@@ -2738,13 +2865,26 @@ impl BufferSnapshot {
         Some(items)
     }
 
+    /// Returns the outline items that intersect the given range, leaving out those that
+    /// the language's `outline_ignore` setting excludes.
     pub fn outline_items_containing<T: ToOffset>(
         &self,
         range: Range<T>,
         include_extra_context: bool,
         theme: Option<&SyntaxTheme>,
+    ) -> Option<Vec<OutlineItem<Anchor>>> {
+        self.filtered_outline_items_containing(range, include_extra_context, theme, &|_| true)
+    }
+
+    fn filtered_outline_items_containing<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        include_extra_context: bool,
+        theme: Option<&SyntaxTheme>,
+        predicate: &dyn Fn(&OutlineItem<Anchor>) -> bool,
     ) -> Option<Vec<OutlineItem<Anchor>>> {
         let range = range.to_offset(self);
+        let ignored_items = self.ignored_outline_items(range.clone());
         let mut matches = self.syntax.matches(range.clone(), &self.text, |grammar| {
             grammar.outline_config.as_ref().map(|c| &c.query)
         });
@@ -2767,11 +2907,22 @@ impl BufferSnapshot {
             })?;
 
             let item_range = item_node.byte_range();
+            let item_kind = item_node.kind();
+            let item_start_point = Point::from_ts_point(item_node.start_position());
+            let item_end_point = Point::from_ts_point(item_node.end_position());
             if item_range.end < range.start || item_range.start > range.end {
                 matches.advance();
                 continue;
             }
 
+            let ignored_kinds = &mat.language.config.outline_ignore;
+            if ignored_kinds.iter().any(|kind| kind == item_kind)
+                || ignored_items.contains(&(mat.depth, item_range.clone()))
+            {
+                matches.advance();
+                continue;
+            }
+
             let mut buffer_ranges = Vec::new();
             for capture in mat.captures {
                 let node_is_name;
@@ -2860,19 +3011,61 @@ impl BufferSnapshot {
             }) {
                 stack.pop();
             }
-            stack.push(item_range.clone());
 
-            items.push(OutlineItem {
-                depth: stack.len() - 1,
+            let item = OutlineItem {
+                depth: stack.len(),
                 range: self.anchor_after(item_range.start)..self.anchor_before(item_range.end),
                 text,
                 highlight_ranges,
                 name_ranges,
-            })
+                kind: item_kind,
+                start_point: item_start_point,
+                end_point: item_end_point,
+            };
+            if predicate(&item) {
+                stack.push(item_range);
+                items.push(item);
+            }
         }
         Some(items)
     }
 
+    /// Returns the syntax layer depths and ranges of the outline items that are captured
+    /// along with any of the captures named in their language's `outline_ignore`, so that
+    /// they're left out of the outline even if other patterns match them too.
+    fn ignored_outline_items(&self, range: Range<usize>) -> HashSet<(usize, Range<usize>)> {
+        let mut ignored_items = HashSet::default();
+        let mut matches = self.syntax.matches(range, &self.text, |grammar| {
+            grammar
+                .outline_config
+                .as_ref()
+                .filter(|config| !config.ignored_capture_ixs.is_empty())
+                .map(|config| &config.query)
+        });
+        let configs = matches
+            .grammars()
+            .iter()
+            .map(|g| g.outline_config.as_ref().unwrap())
+            .collect::<Vec<_>>();
+        while let Some(mat) = matches.peek() {
+            let config = &configs[mat.grammar_index];
+            if mat
+                .captures
+                .iter()
+                .any(|capture| config.ignored_capture_ixs.contains(&capture.index))
+            {
+                ignored_items.extend(
+                    mat.captures
+                        .iter()
+                        .filter(|capture| capture.index == config.item_capture_ix)
+                        .map(|capture| (mat.depth, capture.node.byte_range())),
+                );
+            }
+            matches.advance();
+        }
+        ignored_items
+    }
+
     /// For each grammar in the language, runs the provided
     /// [tree_sitter::Query] against the given range.
     pub fn matches(
@@ -3626,6 +3819,8 @@ impl<'a> BufferChunks<'a> {
                 next_capture: None,
                 stack: Default::default(),
                 highlight_maps,
+                error_highlights: Vec::new(),
+                next_error_highlight_ix: 0,
             })
         }
 
@@ -3645,6 +3840,19 @@ impl<'a> BufferChunks<'a> {
         }
     }
 
+    /// Sets the lexical highlights for the text that failed to parse, sorted by
+    /// range, which are applied wherever the highlights query captures nothing.
+    pub(crate) fn set_error_highlights(
+        &mut self,
+        error_highlights: Vec<(Range<usize>, HighlightId)>,
+    ) {
+        if let Some(highlights) = self.highlights.as_mut() {
+            highlights.next_error_highlight_ix =
+                error_highlights.partition_point(|(range, _)| range.end <= self.range.start);
+            highlights.error_highlights = error_highlights;
+        }
+    }
+
     /// Seeks to the given byte offset in the buffer.
     pub fn seek(&mut self, offset: usize) {
         self.range.start = offset;
@@ -3652,7 +3860,7 @@ impl<'a> BufferChunks<'a> {
         if let Some(highlights) = self.highlights.as_mut() {
             highlights
                 .stack
-                .retain(|(end_offset, _)| *end_offset > offset);
+                .retain(|(end_offset, _, _)| *end_offset > offset);
             if let Some(capture) = &highlights.next_capture {
                 if offset >= capture.node.start_byte() {
                     let next_capture_end = capture.node.end_byte();
@@ -3660,11 +3868,15 @@ impl<'a> BufferChunks<'a> {
                         highlights.stack.push((
                             next_capture_end,
                             highlights.highlight_maps[capture.grammar_index].get(capture.index),
+                            capture.depth,
                         ));
                     }
                     highlights.next_capture.take();
                 }
             }
+            highlights.next_error_highlight_ix = highlights
+                .error_highlights
+                .partition_point(|(range, _)| range.end <= offset);
             highlights.captures.set_byte_range(self.range.clone());
         }
     }
@@ -3724,7 +3936,7 @@ impl<'a> Iterator for BufferChunks<'a> {
         let mut next_diagnostic_endpoint = usize::MAX;
 
         if let Some(highlights) = self.highlights.as_mut() {
-            while let Some((parent_capture_end, _)) = highlights.stack.last() {
+            while let Some((parent_capture_end, _, _)) = highlights.stack.last() {
                 if *parent_capture_end <= self.range.start {
                     highlights.stack.pop();
                 } else {
@@ -3745,10 +3957,24 @@ impl<'a> Iterator for BufferChunks<'a> {
                         highlights.highlight_maps[capture.grammar_index].get(capture.index);
                     highlights
                         .stack
-                        .push((capture.node.end_byte(), highlight_id));
+                        .push((capture.node.end_byte(), highlight_id, capture.depth));
                     highlights.next_capture = highlights.captures.next();
                 }
             }
+
+            while let Some((range, highlight_id)) = highlights
+                .error_highlights
+                .get(highlights.next_error_highlight_ix)
+            {
+                if self.range.start < range.start {
+                    next_capture_start = next_capture_start.min(range.start);
+                    break;
+                }
+                if self.range.start < range.end && highlights.stack.is_empty() {
+                    highlights.stack.push((range.end, *highlight_id, 0));
+                }
+                highlights.next_error_highlight_ix += 1;
+            }
         }
 
         while let Some(endpoint) = self.diagnostic_endpoints.peek().copied() {
@@ -3767,10 +3993,14 @@ impl<'a> Iterator for BufferChunks<'a> {
                 .min(next_capture_start)
                 .min(next_diagnostic_endpoint);
             let mut highlight_id = None;
+            let mut syntax_depth = 0;
             if let Some(highlights) = self.highlights.as_ref() {
-                if let Some((parent_capture_end, parent_highlight_id)) = highlights.stack.last() {
+                if let Some((parent_capture_end, parent_highlight_id, parent_depth)) =
+                    highlights.stack.last()
+                {
                     chunk_end = chunk_end.min(*parent_capture_end);
                     highlight_id = Some(*parent_highlight_id);
+                    syntax_depth = *parent_depth;
                 }
             }
 
@@ -3784,6 +4014,7 @@ impl<'a> Iterator for BufferChunks<'a> {
             Some(Chunk {
                 text: slice,
                 syntax_highlight_id: highlight_id,
+                syntax_depth,
                 diagnostic_severity: self.current_diagnostic_severity(),
                 is_unnecessary: self.current_code_is_unnecessary(),
                 ..Default::default()
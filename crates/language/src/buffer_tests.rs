@@ -6,7 +6,7 @@ use crate::Buffer;
 use clock::ReplicaId;
 use collections::BTreeMap;
 use futures::FutureExt as _;
-use gpui::{AppContext, BorrowAppContext, Model};
+use gpui::{AppContext, BorrowAppContext, HighlightStyle, Hsla, Model};
 use gpui::{Context, TestAppContext};
 use indoc::indoc;
 use proto::deserialize_operation;
@@ -152,7 +152,10 @@ async fn test_first_line_pattern(cx: &mut TestAppContext) {
         name: "JavaScript".into(),
         matcher: LanguageMatcher {
             path_suffixes: vec!["js".into()],
+            path_globs: Vec::new(),
             first_line_pattern: Some(Regex::new(r"\bnode\b").unwrap()),
+            content_patterns: Vec::new(),
+            priority: 0,
         },
         ..Default::default()
     });
@@ -175,6 +178,640 @@ async fn test_first_line_pattern(cx: &mut TestAppContext) {
         .as_ref(),
         "JavaScript"
     );
+    assert_eq!(
+        cx.read(|cx| languages.language_for_file(
+            &file("the/script"),
+            Some(&"\n// runs with node\n".into()),
+            cx
+        ))
+        .await
+        .unwrap()
+        .name()
+        .as_ref(),
+        "JavaScript"
+    );
+
+    languages.set_content_detection_lines(1);
+    cx.read(|cx| {
+        languages.language_for_file(
+            &file("the/script"),
+            Some(&"\n// runs with node\n".into()),
+            cx,
+        )
+    })
+    .await
+    .unwrap_err();
+}
+
+#[gpui::test]
+async fn test_language_for_file_with_path_globs(cx: &mut TestAppContext) {
+    cx.update(|cx| init_settings(cx, |_| {}));
+
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    languages.register_test_language(LanguageConfig {
+        name: "Dockerfile".into(),
+        matcher: LanguageMatcher {
+            path_suffixes: vec!["Dockerfile".into()],
+            path_globs: vec!["Dockerfile.*".into()],
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    for path in ["the/Dockerfile", "the/Dockerfile.prod"] {
+        assert_eq!(
+            cx.read(|cx| languages.language_for_file(&file(path), None, cx))
+                .await
+                .unwrap()
+                .name()
+                .as_ref(),
+            "Dockerfile"
+        );
+    }
+    cx.read(|cx| languages.language_for_file(&file("the/Dockerfiles"), None, cx))
+        .await
+        .unwrap_err();
+}
+
+#[gpui::test]
+async fn test_language_for_file_with_many_languages(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    for ix in 0..50 {
+        languages.register_test_language(LanguageConfig {
+            name: format!("Language {ix}").into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec![format!("ext{ix}"), format!("shared{}", ix % 5)],
+                path_globs: vec![format!("*.glob{ix}.*")],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    for file_ix in 0..1000 {
+        let ix = file_ix % 50;
+        for path in [
+            format!("dir/file{file_ix}.ext{ix}"),
+            format!("dir/file{file_ix}.glob{ix}.txt"),
+        ] {
+            let language = languages
+                .language_for_file_path(Path::new(&path))
+                .await
+                .unwrap();
+            assert_eq!(language.name().as_ref(), format!("Language {ix}"));
+        }
+    }
+
+    // When several languages match equally well, the last one registered wins.
+    let language = languages
+        .language_for_file_path(Path::new("dir/file.shared3"))
+        .await
+        .unwrap();
+    assert_eq!(language.name().as_ref(), "Language 48");
+
+    languages
+        .language_for_file_path(Path::new("dir/file.ext50"))
+        .await
+        .unwrap_err();
+}
+
+#[gpui::test]
+async fn test_parse_files(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    languages.add(Arc::new(rust_lang()));
+    languages.register_test_language(LanguageConfig {
+        name: "Text".into(),
+        matcher: LanguageMatcher {
+            path_suffixes: vec!["txt".into()],
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let files = [
+        ("a.rs", b"fn a() {}".to_vec()),
+        ("b.txt", b"fn b() {}".to_vec()),
+        ("c.md", b"# C".to_vec()),
+        ("d.rs", b"fn d() { let e = 1; }".to_vec()),
+        ("e.rs", b"caf\xE9".to_vec()),
+    ]
+    .map(|(path, content)| (PathBuf::from(path), content));
+    let trees = languages.parse_files(&files).await;
+
+    assert_eq!(
+        trees
+            .iter()
+            .map(|(path, tree)| (
+                path.to_str().unwrap(),
+                tree.as_ref().map(|tree| tree.root_node().kind())
+            ))
+            .collect::<Vec<_>>(),
+        &[
+            ("a.rs", Some("source_file")),
+            // The text language has no grammar.
+            ("b.txt", None),
+            ("c.md", None),
+            ("d.rs", Some("source_file")),
+            // Invalid text isn't parsed.
+            ("e.rs", None),
+        ]
+    );
+    assert!(!trees[3].1.as_ref().unwrap().root_node().has_error());
+
+    // Files larger than the registry's limit aren't parsed.
+    languages.set_max_parse_bytes(Some(10));
+    let trees = languages.parse_files(&files[..4]).await;
+    assert_eq!(
+        trees
+            .iter()
+            .map(|(_, tree)| tree.is_some())
+            .collect::<Vec<_>>(),
+        &[true, false, false, false]
+    );
+}
+
+#[gpui::test]
+async fn test_prewarm_languages(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    for name in ["Rust", "Python", "Ruby"] {
+        languages.register_test_language(LanguageConfig {
+            name: name.into(),
+            ..Default::default()
+        });
+    }
+    let loaded_language_names = || {
+        let mut names = languages
+            .to_vec()
+            .iter()
+            .map(|language| language.name().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+
+    languages.prewarm(Some(&["rust", "Unknown"])).await;
+    assert_eq!(loaded_language_names(), &["Plain Text", "Rust"]);
+
+    languages.set_language_enabled("Ruby", false);
+    languages.prewarm(None).await;
+    assert_eq!(loaded_language_names(), &["Plain Text", "Python", "Rust"]);
+}
+
+#[gpui::test]
+fn test_reset_theme(cx: &mut AppContext) {
+    let languages = LanguageRegistry::test(cx.background_executor().clone());
+    let keyword_language = || {
+        Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(r#""fn" @keyword"#)
+            .unwrap(),
+        )
+    };
+    let keyword_style = |language: &Arc<Language>, theme: &theme::Theme| {
+        language
+            .grammar()
+            .unwrap()
+            .highlight_map()
+            .get(0)
+            .style(theme.syntax())
+    };
+
+    let theme = Arc::new(theme::Theme::default());
+    let language = keyword_language();
+    languages.add(language.clone());
+    languages.set_theme(theme.clone());
+    assert!(keyword_style(&language, &theme).is_some());
+
+    languages.reset_theme();
+    assert_eq!(keyword_style(&language, &theme), None);
+
+    // Languages added after the reset aren't styled either.
+    let language = keyword_language();
+    languages.add(language.clone());
+    assert_eq!(keyword_style(&language, &theme), None);
+
+    languages.set_theme(theme.clone());
+    assert!(keyword_style(&language, &theme).is_some());
+}
+
+#[gpui::test]
+async fn test_registry_snapshot(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    for (name, suffix) in [("Rust", "rs"), ("Python", "py")] {
+        languages.register_test_language(LanguageConfig {
+            name: name.into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec![suffix.into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+    let language_name = |language: Option<Arc<Language>>| language.map(|language| language.name());
+
+    // Languages that aren't loaded aren't in the snapshot.
+    let snapshot = languages.snapshot();
+    assert_eq!(
+        language_name(snapshot.language_for_file_path(Path::new("a.rs"))),
+        None
+    );
+    assert_eq!(
+        language_name(snapshot.language_for_name("plain text")).as_deref(),
+        Some("Plain Text")
+    );
+
+    languages.prewarm(None).await;
+    assert_ne!(snapshot.version(), languages.version());
+    let snapshot = languages.snapshot();
+    assert_eq!(snapshot.version(), languages.version());
+    assert_eq!(
+        language_name(snapshot.language_for_file_path(Path::new("src/a.rs"))).as_deref(),
+        Some("Rust")
+    );
+    assert_eq!(
+        language_name(snapshot.language_for_name("python")).as_deref(),
+        Some("Python")
+    );
+    assert_eq!(
+        language_name(snapshot.language_for_file_path(Path::new("a.rb"))),
+        None
+    );
+
+    // The snapshot is unaffected by later changes to the registry.
+    languages.set_language_enabled("Rust", false);
+    languages.reload();
+    assert_ne!(snapshot.version(), languages.version());
+    assert_eq!(
+        language_name(snapshot.language_for_file_path(Path::new("a.rs"))).as_deref(),
+        Some("Rust")
+    );
+    assert_eq!(
+        language_name(languages.snapshot().language_for_name("Rust")),
+        None
+    );
+}
+
+#[gpui::test]
+async fn test_registry_snapshot_language_for_file_path(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    for (name, suffixes, first_line, priority) in [
+        ("TypeScript Declarations", &["d.ts"][..], None, 0),
+        ("TypeScript", &["ts"][..], None, 0),
+        ("Python", &["py"][..], Some(r"^#!.*\bpython"), 0),
+        ("Other Python", &["py"][..], None, 0),
+        ("Ruby", &["rb"][..], None, 1),
+        ("Other Ruby", &["rb"][..], None, 0),
+    ] {
+        languages.register_test_language(LanguageConfig {
+            name: name.into(),
+            matcher: LanguageMatcher {
+                path_suffixes: suffixes.iter().map(|suffix| suffix.to_string()).collect(),
+                first_line_pattern: first_line.map(|pattern| Regex::new(pattern).unwrap()),
+                priority,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+    languages.prewarm(None).await;
+    let snapshot = languages.snapshot();
+    let language_name = |path: &str| {
+        snapshot
+            .language_for_file_path(Path::new(path))
+            .map(|language| language.name())
+    };
+
+    // Compound suffixes are favored over extensions, as in the registry.
+    assert_eq!(
+        language_name("a.d.ts").as_deref(),
+        Some("TypeScript Declarations")
+    );
+    assert_eq!(language_name("a.ts").as_deref(), Some("TypeScript"));
+
+    // Among equally good matches, the last registered language wins, unless another has
+    // a higher priority.
+    assert_eq!(language_name("a.py").as_deref(), Some("Other Python"));
+    assert_eq!(language_name("a.rb").as_deref(), Some("Ruby"));
+    assert_eq!(
+        languages
+            .language_for_file_path(Path::new("a.rb"))
+            .await
+            .unwrap()
+            .name()
+            .as_ref(),
+        "Ruby"
+    );
+
+    // First lines aren't matched, as there's no content.
+    assert_eq!(language_name("script"), None);
+}
+
+#[gpui::test]
+async fn test_language_without_grammar(cx: &mut TestAppContext) {
+    let config: LanguageConfig = serde_json::from_value(serde_json::json!({
+        "name": "Env",
+        "grammar": "none",
+        "path_suffixes": ["env"],
+    }))
+    .unwrap();
+    assert_eq!(config.grammar, None);
+
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    languages.register_test_language(config);
+    let language = languages
+        .language_for_file_path(Path::new("app/.env"))
+        .await
+        .unwrap();
+    assert_eq!(language.name().as_ref(), "Env");
+    assert!(language.grammar().is_none());
+
+    // The whole text is unhighlighted.
+    let text = Rope::from("A=1\nB=\"two\"\n");
+    assert_eq!(language.highlight_text(&text, 0..text.len()), &[]);
+}
+
+#[gpui::test]
+async fn test_languages_sharing_a_grammar(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    let wasm_path = env::temp_dir().join(format!("shared-grammar-{}.wasm", std::process::id()));
+    languages.register_native_grammars([("rust", tree_sitter_rust::language())]);
+    languages.register_wasm_grammars([("shared", wasm_path.clone())]);
+    for (name, grammar, grammar_name) in [
+        ("A", "shared", Some("a")),
+        ("B", "shared", Some("b")),
+        ("C", "shared", Some("a")),
+        ("D", "rust", Some("ignored")),
+        ("E", "rust", None),
+    ] {
+        languages.register_test_language(LanguageConfig {
+            name: name.into(),
+            grammar: Some(grammar.into()),
+            grammar_name: grammar_name.map(Into::into),
+            ..Default::default()
+        });
+    }
+    let load_error = |name: &'static str| {
+        let languages = languages.clone();
+        async move {
+            let error = languages.language_for_name(name).await.unwrap_err();
+            let error = error.to_string();
+            error.split_once(": ").unwrap().1.to_string()
+        }
+    };
+
+    // Each language in a WASM grammar is loaded separately, so the grammar file is
+    // read again for B after it has been created, but not for C.
+    let a_error = load_error("A").await;
+    std::fs::write(&wasm_path, b"not a wasm file").unwrap();
+    let b_error = load_error("B").await;
+    let c_error = load_error("C").await;
+    std::fs::remove_file(&wasm_path).ok();
+    assert_ne!(a_error, b_error);
+    assert_eq!(a_error, c_error);
+
+    // Native grammars ignore the language name.
+    for name in ["D", "E"] {
+        let language = languages.language_for_name(name).await.unwrap();
+        assert!(language.grammar().is_some());
+    }
+}
+
+#[gpui::test]
+async fn test_disabled_languages_are_not_detected(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    for name in ["Plain Rust", "Rust"] {
+        languages.register_test_language(LanguageConfig {
+            name: name.into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["rs".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+    let language_name = |path: &'static str| {
+        let languages = languages.clone();
+        async move {
+            languages
+                .language_for_file_path(Path::new(path))
+                .await
+                .ok()
+                .map(|language| language.name())
+        }
+    };
+
+    assert_eq!(language_name("main.rs").await.as_deref(), Some("Rust"));
+
+    languages.set_language_enabled("Rust", false);
+    assert!(!languages.is_language_enabled("Rust"));
+    assert_eq!(
+        language_name("main.rs").await.as_deref(),
+        Some("Plain Rust")
+    );
+    assert!(languages.language_names().contains(&"Rust".to_string()));
+
+    languages.set_language_enabled("Plain Rust", false);
+    assert_eq!(language_name("main.rs").await, None);
+
+    languages.set_language_enabled("Rust", true);
+    assert_eq!(language_name("main.rs").await.as_deref(), Some("Rust"));
+}
+
+#[gpui::test]
+async fn test_explain_detection(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    for (name, path_suffixes, path_globs, first_line_pattern) in [
+        ("Rust", vec!["rs"], vec![], None),
+        ("Make", vec!["Makefile", "mk"], vec![], None),
+        ("Dockerfile", vec![], vec!["Dockerfile.*"], None),
+        ("Python", vec!["py"], vec![], Some(r"^#!.*\bpython")),
+        ("TypeScript", vec!["ts"], vec![], None),
+        ("TypeScript Declarations", vec!["d.ts"], vec![], None),
+        ("XML", vec!["xml"], vec![], Some(r"^<\?xml")),
+    ] {
+        languages.register_test_language(LanguageConfig {
+            name: name.into(),
+            matcher: LanguageMatcher {
+                path_suffixes: path_suffixes.into_iter().map(Into::into).collect(),
+                path_globs: path_globs.into_iter().map(Into::into).collect(),
+                first_line_pattern: first_line_pattern.map(|pattern| Regex::new(pattern).unwrap()),
+                content_patterns: Vec::new(),
+                priority: 0,
+            },
+            ..Default::default()
+        });
+    }
+
+    let script = Rope::from("#!/usr/bin/env python3\nprint()\n");
+    let xml = Rope::from("<?xml version=\"1.0\"?>\n<a/>\n");
+    let emacs_modeline = Rope::from("// -*- mode: python; coding: utf-8 -*-\n");
+    let vim_modeline = Rope::from("# vim: set ts=4 ft=xml:\n");
+    for (path, content, expected_language, expected_reason) in [
+        (
+            "src/main.rs",
+            None,
+            Some("Rust"),
+            DetectionReason::Extension("rs".into()),
+        ),
+        (
+            "Makefile",
+            None,
+            Some("Make"),
+            DetectionReason::ExactFileName("Makefile".into()),
+        ),
+        (
+            "Dockerfile.dev",
+            None,
+            Some("Dockerfile"),
+            DetectionReason::Glob("Dockerfile.*".into()),
+        ),
+        (
+            "src/index.d.ts",
+            None,
+            Some("TypeScript Declarations"),
+            DetectionReason::CompoundSuffix("d.ts".into()),
+        ),
+        (
+            "src/index.ts",
+            None,
+            Some("TypeScript"),
+            DetectionReason::Extension("ts".into()),
+        ),
+        (
+            "bin/script",
+            Some(&script),
+            Some("Python"),
+            DetectionReason::Shebang("python3".into()),
+        ),
+        (
+            "notes.txt",
+            Some(&script),
+            Some("Python"),
+            DetectionReason::Shebang("python3".into()),
+        ),
+        ("data", Some(&xml), Some("XML"), DetectionReason::FirstLine),
+        // Modelines only name the language of files that don't match otherwise.
+        (
+            "src/main.rs",
+            Some(&emacs_modeline),
+            Some("Rust"),
+            DetectionReason::Extension("rs".into()),
+        ),
+        (
+            "bin/script",
+            Some(&emacs_modeline),
+            Some("Python"),
+            DetectionReason::Modeline("python".into()),
+        ),
+        (
+            "notes.txt",
+            Some(&vim_modeline),
+            Some("XML"),
+            DetectionReason::Modeline("xml".into()),
+        ),
+        ("notes.txt", None, None, DetectionReason::NoMatch),
+    ] {
+        let detection = languages
+            .explain_detection(Path::new(path), content)
+            .await
+            .unwrap();
+        assert_eq!(
+            detection
+                .language
+                .map(|language| language.name())
+                .as_deref(),
+            expected_language,
+            "language for {path}"
+        );
+        assert_eq!(detection.reason, expected_reason, "reason for {path}");
+    }
+}
+
+#[gpui::test]
+fn test_find_injection_language(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    languages.register_test_language(LanguageConfig {
+        name: "JavaScript".into(),
+        matcher: LanguageMatcher {
+            path_suffixes: vec!["js".into()],
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    // Looking up a language that isn't loaded yet starts loading it.
+    assert!(languages.find_injection_language("js").is_none());
+    cx.executor().run_until_parked();
+    for name in ["js", "JS", "javascript", "JavaScript"] {
+        assert_eq!(
+            languages
+                .find_injection_language(name)
+                .map(|language| language.name()),
+            Some("JavaScript".into()),
+            "language for {name:?}"
+        );
+    }
+
+    // Unknown names fall back to plain text, as when highlighting.
+    assert_eq!(
+        languages
+            .find_injection_language("ts")
+            .map(|language| language.name()),
+        Some("Plain Text".into())
+    );
+}
+
+#[gpui::test]
+async fn test_language_for_file_name_parts(cx: &mut TestAppContext) {
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    for (name, path_suffixes, path_globs) in [
+        ("Rust", vec!["rs"], vec![]),
+        ("Make", vec!["Makefile"], vec![]),
+        ("Shell Script", vec!["bashrc"], vec![]),
+        ("Dockerfile", vec![], vec!["Dockerfile.*"]),
+    ] {
+        languages.register_test_language(LanguageConfig {
+            name: name.into(),
+            matcher: LanguageMatcher {
+                path_suffixes: path_suffixes.into_iter().map(Into::into).collect(),
+                path_globs: path_globs.into_iter().map(Into::into).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    for (file_name, extension, expected_language) in [
+        (Some("main.rs"), Some("rs"), Some("Rust")),
+        (None, Some("rs"), Some("Rust")),
+        (Some("Makefile"), None, Some("Make")),
+        (Some(".bashrc"), Some("bashrc"), Some("Shell Script")),
+        (Some("Dockerfile.dev"), Some("dev"), Some("Dockerfile")),
+        (Some("notes.txt"), Some("txt"), None),
+        (None, None, None),
+    ] {
+        let language = languages
+            .language_for_file_name_parts(file_name, extension)
+            .await
+            .ok();
+        assert_eq!(
+            language.as_ref().map(|language| language.name()).as_deref(),
+            expected_language,
+            "language for {file_name:?} and {extension:?}"
+        );
+
+        // Splitting the path gives the same language as detecting it from the path.
+        if let Some(file_name) = file_name {
+            let path_language = languages.language_for_file_path(Path::new(file_name)).await;
+            assert_eq!(
+                path_language.ok().map(|language| language.name()),
+                language.map(|language| language.name())
+            );
+        }
+    }
 }
 
 #[gpui::test]
@@ -703,6 +1340,35 @@ async fn test_outline(cx: &mut gpui::TestAppContext) {
     }
 }
 
+#[gpui::test]
+async fn test_outline_item_points(cx: &mut gpui::TestAppContext) {
+    let text = r#"
+        mod module {
+            enum LoginState {
+                LoggedOut,
+            }
+        }
+    "#
+    .unindent();
+
+    let buffer =
+        cx.new_model(|cx| Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx));
+    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    let outline = snapshot.outline(None).unwrap();
+    let item = outline
+        .items
+        .iter()
+        .find(|item| item.text == "enum LoginState")
+        .unwrap();
+    assert_eq!(item.start_point, Point::new(1, 4));
+    assert_eq!(item.end_point, Point::new(3, 5));
+
+    for item in &outline.items {
+        assert_eq!(item.range.start.to_point(&snapshot), item.start_point);
+        assert_eq!(item.range.end.to_point(&snapshot), item.end_point);
+    }
+}
+
 #[gpui::test]
 async fn test_outline_nodes_with_newlines(cx: &mut gpui::TestAppContext) {
     let text = r#"
@@ -764,14 +1430,83 @@ async fn test_outline_with_extra_context(cx: &mut gpui::TestAppContext) {
         &[("function a()", 0), ("function b( )", 0),]
     );
 
-    // extra context nodes do not appear in breadcrumbs.
-    let symbols = snapshot.symbols_containing(3, None).unwrap();
+    // extra context nodes do not appear in breadcrumbs.
+    let symbols = snapshot.symbols_containing(3, None).unwrap();
+    assert_eq!(
+        symbols
+            .iter()
+            .map(|item| (item.text.as_str(), item.depth))
+            .collect::<Vec<_>>(),
+        &[("function a", 0)]
+    );
+}
+
+#[gpui::test]
+async fn test_outline_ignore(cx: &mut gpui::TestAppContext) {
+    let language = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            outline_ignore: vec!["definition.test".into(), "const_item".into()],
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    )
+    .with_outline_query(
+        r#"
+        (mod_item "mod" @context name: (_) @name) @item
+        (function_item "fn" @context name: (_) @name) @item
+        (const_item "const" @context name: (_) @name) @item
+        (
+            (attribute_item (attribute (identifier) @_attribute (#eq? @_attribute "test")))
+            .
+            (function_item) @item @definition.test
+        )
+        "#,
+    )
+    .unwrap();
+
+    let text = r#"
+        const A: usize = 1;
+
+        fn b() {}
+
+        mod tests {
+            #[test]
+            fn test_b() {}
+
+            fn c() {}
+        }
+    "#
+    .unindent();
+
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx).with_language(Arc::new(language), cx));
+    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    let items = |outline: Outline<Anchor>| {
+        outline
+            .items
+            .iter()
+            .map(|item| (item.text.clone(), item.depth))
+            .collect::<Vec<_>>()
+    };
+
+    // Test functions and constants are left out.
+    assert_eq!(
+        items(snapshot.outline(None).unwrap()),
+        &[
+            ("fn b".to_string(), 0),
+            ("mod tests".to_string(), 0),
+            ("fn c".to_string(), 1),
+        ]
+    );
+
+    // Items within filtered items take their place.
     assert_eq!(
-        symbols
-            .iter()
-            .map(|item| (item.text.as_str(), item.depth))
-            .collect::<Vec<_>>(),
-        &[("function a", 0)]
+        items(
+            snapshot
+                .outline_with_filter(None, |item| item.kind != "mod_item")
+                .unwrap()
+        ),
+        &[("fn b".to_string(), 0), ("fn c".to_string(), 0)]
     );
 }
 
@@ -1767,6 +2502,63 @@ fn test_autoindent_query_with_outdent_captures(cx: &mut AppContext) {
     });
 }
 
+#[gpui::test]
+fn test_autoindent_query_with_align_captures(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let language = Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_indents_query(
+            r#"
+            (_ "{" "}" @end) @indent
+            (let_declaration value: (_) @indent.align)
+            "#,
+        )
+        .unwrap();
+        let mut buffer = Buffer::local("", cx).with_language(Arc::new(language), cx);
+
+        let text = r#"
+            fn a() {
+            let b = c
+            .d()
+            .e(|f| {
+            f
+            })
+            .g();
+            h();
+            }
+        "#
+        .unindent();
+        buffer.edit([(0..0, text)], Some(AutoindentMode::EachLine), cx);
+
+        // The chained calls are aligned with the receiver, while the closure
+        // body is indented relative to the line where it starts.
+        assert_eq!(
+            buffer.text(),
+            r#"
+                fn a() {
+                    let b = c
+                            .d()
+                            .e(|f| {
+                                f
+                            })
+                            .g();
+                    h();
+                }
+            "#
+            .unindent()
+        );
+
+        buffer
+    });
+}
+
 #[gpui::test]
 fn test_language_scope_at_with_javascript(cx: &mut AppContext) {
     init_settings(cx, |_| {});
@@ -1783,12 +2575,14 @@ fn test_language_scope_at_with_javascript(cx: &mut AppContext) {
                             end: "}".into(),
                             close: true,
                             newline: false,
+                            surround: false,
                         },
                         BracketPair {
                             start: "'".into(),
                             end: "'".into(),
                             close: true,
                             newline: false,
+                            surround: false,
                         },
                     ],
                     disabled_scopes_by_bracket_ix: vec![
@@ -1911,12 +2705,14 @@ fn test_language_scope_at_with_rust(cx: &mut AppContext) {
                             end: "}".into(),
                             close: true,
                             newline: false,
+                            surround: false,
                         },
                         BracketPair {
                             start: "'".into(),
                             end: "'".into(),
                             close: true,
                             newline: false,
+                            surround: false,
                         },
                     ],
                     disabled_scopes_by_bracket_ix: vec![
@@ -1963,6 +2759,423 @@ fn test_language_scope_at_with_rust(cx: &mut AppContext) {
     });
 }
 
+#[gpui::test]
+fn test_highlighting_injected_language(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    let html_language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "HTML".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_html::language()),
+        )
+        .with_highlights_query(
+            r#"
+            (tag_name) @tag
+            (raw_text) @string
+            "#,
+        )
+        .unwrap()
+        .with_injection_query(
+            r#"
+            (style_element
+                (raw_text) @content
+                (#set! "language" "css"))
+            "#,
+        )
+        .unwrap(),
+    );
+    let css_language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "CSS".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_css::language()),
+        )
+        .with_highlights_query("(property_name) @property")
+        .unwrap(),
+    );
+
+    // The first capture of each language's query has the same index, so the
+    // injected text is only highlighted correctly if it uses the CSS highlight map.
+    let theme = SyntaxTheme {
+        highlights: vec![
+            ("tag".into(), gpui::blue().into()),
+            ("property".into(), gpui::red().into()),
+            ("string".into(), gpui::green().into()),
+        ],
+        ..Default::default()
+    };
+    html_language.set_theme(&theme);
+    css_language.set_theme(&theme);
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    language_registry.add(html_language.clone());
+    language_registry.add(css_language);
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("<style>a { color: red; }</style>", cx);
+        buffer.set_language_registry(language_registry);
+        buffer.set_language(Some(html_language), cx);
+
+        let snapshot = buffer.snapshot();
+        let mut chunks = Vec::<(String, Option<Hsla>)>::new();
+        for chunk in snapshot.chunks(0..snapshot.len(), true) {
+            let color = chunk
+                .syntax_highlight_id
+                .and_then(|id| id.style(&theme))
+                .and_then(|style| style.color);
+            match chunks.last_mut() {
+                Some((text, last_color)) if *last_color == color => text.push_str(chunk.text),
+                _ => chunks.push((chunk.text.to_string(), color)),
+            }
+        }
+
+        // Text in the injection that the CSS query doesn't capture falls back to
+        // the HTML highlighting.
+        assert_eq!(
+            chunks,
+            [
+                ("<".to_string(), None),
+                ("style".to_string(), Some(gpui::blue())),
+                (">".to_string(), None),
+                ("a { ".to_string(), Some(gpui::green())),
+                ("color".to_string(), Some(gpui::red())),
+                (": red; }".to_string(), Some(gpui::green())),
+                ("</".to_string(), None),
+                ("style".to_string(), Some(gpui::blue())),
+                (">".to_string(), None),
+            ]
+        );
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_injected_highlight_opacity(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    let html_language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "HTML".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_html::language()),
+        )
+        .with_highlights_query("(tag_name) @property")
+        .unwrap()
+        .with_injection_query(
+            r#"
+            (style_element
+                (raw_text) @content
+                (#set! "language" "css"))
+            "#,
+        )
+        .unwrap(),
+    );
+    let css_language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "CSS".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_css::language()),
+        )
+        .with_highlights_query("(property_name) @property")
+        .unwrap(),
+    );
+    let theme = SyntaxTheme {
+        highlights: vec![("property".into(), gpui::red().into())],
+        injected_opacity: Some(0.5),
+        ..Default::default()
+    };
+    html_language.set_theme(&theme);
+    css_language.set_theme(&theme);
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    language_registry.add(html_language.clone());
+    language_registry.add(css_language);
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("<style>a { color: red; }</style>", cx);
+        buffer.set_language_registry(language_registry);
+        buffer.set_language(Some(html_language), cx);
+
+        let snapshot = buffer.snapshot();
+        let colors = snapshot
+            .chunks(0..snapshot.len(), true)
+            .filter_map(|chunk| {
+                let style = chunk.syntax_highlight_id?.style(&theme)?;
+                let style = theme.injected_style(style, chunk.syntax_depth);
+                Some((chunk.text.to_string(), chunk.syntax_depth, style.color?))
+            })
+            .collect::<Vec<_>>();
+
+        // The same capture is faded within the injected CSS.
+        let mut faded = gpui::red();
+        faded.a = 0.5;
+        assert_eq!(
+            colors,
+            [
+                ("style".to_string(), 0, gpui::red()),
+                ("color".to_string(), 1, faded),
+                ("style".to_string(), 0, gpui::red()),
+            ]
+        );
+
+        buffer
+    });
+
+    // By default, injected highlights are unchanged.
+    let theme = SyntaxTheme::default();
+    let style = HighlightStyle::from(gpui::red());
+    assert_eq!(theme.injected_style(style, 1), style);
+}
+
+#[gpui::test]
+fn test_highlighting_errors_leniently(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    // Keywords are only captured in well-formed functions.
+    let language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                line_comments: vec!["// ".into()],
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query(r#"(function_item "fn" @keyword)"#)
+        .unwrap(),
+    );
+    let theme = SyntaxTheme {
+        highlights: vec![
+            ("keyword".into(), gpui::blue().into()),
+            ("string".into(), gpui::green().into()),
+            ("comment".into(), gpui::red().into()),
+        ],
+        ..Default::default()
+    };
+    language.set_theme(&theme);
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    language_registry.set_highlight_errors_leniently(true);
+    language_registry.add(language.clone());
+
+    let text = "fn a() {}\nstruct ) enum ) impl )\n";
+    let buffer = cx.new_model(|cx| {
+        let mut buffer = Buffer::local(text, cx);
+        buffer.set_language_registry(language_registry.clone());
+        buffer.set_language(Some(language.clone()), cx);
+        buffer
+    });
+    let snapshot = buffer.read(cx).snapshot();
+    let colors = |snapshot: &BufferSnapshot, range: Range<usize>| {
+        let mut colors = Vec::new();
+        for chunk in snapshot.chunks(range, true) {
+            let color = chunk
+                .syntax_highlight_id
+                .and_then(|id| id.style(&theme))
+                .and_then(|style| style.color);
+            colors.extend(chunk.text.bytes().map(|_| color));
+        }
+        colors
+    };
+
+    let layer = snapshot.syntax_layers().next().unwrap();
+    let error_ranges = NodeWalker::new(layer.node().walk())
+        .filter_kind(&["ERROR"])
+        .map(|node| node.range)
+        .collect::<Vec<_>>();
+    let keywords_in_errors = ["struct", "enum", "impl"]
+        .into_iter()
+        .map(|keyword| text.find(keyword).unwrap())
+        .filter(|offset| error_ranges.iter().any(|range| range.contains(offset)))
+        .collect::<Vec<_>>();
+    assert!(!keywords_in_errors.is_empty());
+
+    let lenient_colors = colors(&snapshot, 0..snapshot.len());
+    assert_eq!(lenient_colors[0], Some(gpui::blue()));
+    assert_eq!(lenient_colors[3], None);
+    for offset in &keywords_in_errors {
+        assert_eq!(lenient_colors[*offset], Some(gpui::blue()));
+        // Highlighting from within a keyword still finds it.
+        assert_eq!(
+            colors(&snapshot, offset + 1..offset + 3),
+            [Some(gpui::blue()); 2]
+        );
+    }
+
+    language_registry.set_highlight_errors_leniently(false);
+    let strict_colors = colors(&snapshot, 0..snapshot.len());
+    assert_eq!(strict_colors[0], Some(gpui::blue()));
+    for offset in &keywords_in_errors {
+        assert_eq!(strict_colors[*offset], None);
+    }
+}
+
+#[gpui::test]
+fn test_highlight_cache(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    let language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query(
+            r#"
+            "fn" @keyword
+            (block_comment) @comment
+            "#,
+        )
+        .unwrap(),
+    );
+    let theme = SyntaxTheme {
+        highlights: vec![
+            ("keyword".into(), gpui::red().into()),
+            ("comment".into(), gpui::green().into()),
+        ],
+        ..Default::default()
+    };
+    language.set_theme(&theme);
+    let keyword = HighlightId(0);
+    let comment = HighlightId(1);
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("fn a() {}\nfn b() {}\nfn c() {}", cx);
+        buffer.set_language(Some(language), cx);
+
+        let mut cache = HighlightCache::new();
+        let snapshot = buffer.snapshot();
+        for row in 0..3 {
+            assert_eq!(cache.line_highlights(&snapshot, row), &[(0..2, keyword)]);
+        }
+        assert_eq!(cache.line_highlights(&snapshot, 3), &[]);
+
+        // Editing a line only invalidates that line.
+        buffer.edit([(Point::new(1, 3)..Point::new(1, 4), "bb")], None, cx);
+        let snapshot = buffer.snapshot();
+        assert_eq!(cache.line_highlights(&snapshot, 0), &[(0..2, keyword)]);
+        assert!(!cache.is_cached(1));
+        assert!(cache.is_cached(2));
+        assert_eq!(cache.line_highlights(&snapshot, 1), &[(0..2, keyword)]);
+
+        // Lines whose syntax changed are invalidated, even if they weren't edited.
+        buffer.edit(
+            [
+                (Point::new(0, 0)..Point::new(0, 0), "/*"),
+                (Point::new(2, 9)..Point::new(2, 9), "*/"),
+            ],
+            None,
+            cx,
+        );
+        let snapshot = buffer.snapshot();
+        assert_eq!(cache.line_highlights(&snapshot, 0), &[(0..11, comment)]);
+        assert_eq!(cache.line_highlights(&snapshot, 1), &[(0..10, comment)]);
+        assert_eq!(cache.line_highlights(&snapshot, 2), &[(0..11, comment)]);
+
+        // Inserting a line invalidates all of the lines after it.
+        buffer.edit([(Point::new(1, 0)..Point::new(1, 0), "\n")], None, cx);
+        let snapshot = buffer.snapshot();
+        assert_eq!(cache.line_highlights(&snapshot, 1), &[]);
+        assert!(cache.is_cached(0));
+        assert!(!cache.is_cached(2));
+        assert!(!cache.is_cached(3));
+        assert_eq!(cache.line_highlights(&snapshot, 3), &[(0..11, comment)]);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_highlight_cache_with_changed_injections(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    let theme = SyntaxTheme {
+        highlights: vec![("keyword".into(), gpui::red().into())],
+        ..Default::default()
+    };
+    let language = |name: &str, grammar, highlights_query| {
+        let language = Language::new(
+            LanguageConfig {
+                name: name.into(),
+                ..Default::default()
+            },
+            Some(grammar),
+        )
+        .with_highlights_query(highlights_query)
+        .unwrap();
+        language.set_theme(&theme);
+        Arc::new(language)
+    };
+    let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    registry.add(language(
+        "Rust",
+        tree_sitter_rust::language(),
+        r#""fn" @keyword"#,
+    ));
+    registry.add(language(
+        "Ruby",
+        tree_sitter_ruby::language(),
+        r#""def" @keyword"#,
+    ));
+    let markdown = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "Markdown".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_markdown::language()),
+        )
+        .with_injection_query(
+            r#"
+            (fenced_code_block
+                (info_string
+                    (language) @language)
+                (code_fence_content) @content)
+            "#,
+        )
+        .unwrap(),
+    );
+    let keyword = HighlightId(0);
+
+    cx.new_model(|cx| {
+        let text = "# A\n\n```rust\nfn b() {}\n```\n\nc\n";
+        let mut buffer = Buffer::local(text, cx);
+        buffer.set_language_registry(registry.clone());
+        buffer.set_language(Some(markdown), cx);
+
+        let mut cache = HighlightCache::new();
+        let snapshot = buffer.snapshot();
+        assert_eq!(cache.line_highlights(&snapshot, 3), &[(0..2, keyword)]);
+        for row in 0..7 {
+            cache.line_highlights(&snapshot, row);
+        }
+
+        // Changing the code block's language invalidates its lines, but not the
+        // lines outside of the block.
+        buffer.edit([(Point::new(2, 3)..Point::new(2, 7), "ruby")], None, cx);
+        let snapshot = buffer.snapshot();
+        cache.line_highlights(&snapshot, 2);
+        assert!(!cache.is_cached(3));
+        assert!(cache.is_cached(0));
+        assert!(cache.is_cached(6));
+        assert_eq!(cache.line_highlights(&snapshot, 3), &[]);
+
+        buffer
+    });
+}
+
 #[gpui::test]
 fn test_language_scope_at_with_combined_injections(cx: &mut AppContext) {
     init_settings(cx, |_| {});
@@ -2660,6 +3873,52 @@ fn javascript_lang() -> Language {
     .unwrap()
 }
 
+#[gpui::test]
+fn test_max_parse_bytes(cx: &mut AppContext) {
+    let language_registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    language_registry.set_max_parse_bytes(Some(16));
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("fn a() {}", cx);
+        buffer.set_language_registry(language_registry.clone());
+        buffer.set_language(Some(Arc::new(rust_lang())), cx);
+        assert_eq!(buffer.parse_outcome(), ParseOutcome::Parsed);
+        assert_eq!(buffer.snapshot().syntax_layers().count(), 1);
+
+        buffer.edit([(9..9, " fn b() {}")], None, cx);
+        assert_eq!(buffer.parse_outcome(), ParseOutcome::Skipped { len: 19 });
+        assert_eq!(buffer.snapshot().syntax_layers().count(), 0);
+
+        buffer.edit([(9..19, "")], None, cx);
+        assert_eq!(buffer.parse_outcome(), ParseOutcome::Parsed);
+        assert_eq!(buffer.snapshot().syntax_layers().count(), 1);
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_max_parse_bytes_when_highlighting_text(cx: &mut AppContext) {
+    let language_registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    language_registry.set_max_parse_bytes(Some(4));
+    let language = Arc::new(rust_lang());
+    language_registry.add(language.clone());
+    let text = Rope::from("fn a() {}");
+
+    // The registry's limit doesn't apply to highlighting text outside of a buffer.
+    assert!(language.parse_and_highlight(&text).is_some());
+
+    // The language's own limit does.
+    let limited_language = Arc::new(Language::new(
+        LanguageConfig {
+            max_parse_bytes: Some(4),
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    ));
+    assert!(limited_language.parse_and_highlight(&text).is_none());
+    assert_eq!(limited_language.highlight_text(&text, 0..text.len()), &[]);
+}
+
 fn get_tree_sexp(buffer: &Model<Buffer>, cx: &mut gpui::TestAppContext) -> String {
     buffer.update(cx, |buffer, _| {
         let snapshot = buffer.snapshot();
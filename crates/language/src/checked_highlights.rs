@@ -0,0 +1,128 @@
+use crate::Language;
+use gpui::HighlightStyle;
+use std::{
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+use text::Rope;
+use theme::SyntaxTheme;
+
+/// The error returned by [`Language::highlight_checked`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HighlightError {
+    /// The source isn't valid UTF-8. It is valid up to the given offset.
+    InvalidUtf8 { valid_up_to: usize },
+    /// Highlighting produced a range that isn't within the source, or that doesn't
+    /// start and end on character boundaries.
+    InvalidRange(Range<usize>),
+    /// Highlighting panicked with the given message.
+    Panicked(String),
+}
+
+impl std::fmt::Display for HighlightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUtf8 { valid_up_to } => {
+                write!(f, "invalid UTF-8 after byte {valid_up_to}")
+            }
+            Self::InvalidRange(range) => write!(f, "invalid highlight range {range:?}"),
+            Self::Panicked(message) => write!(f, "highlighting panicked: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for HighlightError {}
+
+impl Language {
+    /// Highlights the given bytes in the same way as [`Language::highlight_styles`], but
+    /// returns an error instead of panicking, so that it can be run over arbitrary input,
+    /// such as when fuzzing.
+    ///
+    /// The source is validated as UTF-8 before it's parsed, and the resulting ranges are
+    /// validated against it. Any panic while parsing or running the highlights query is
+    /// caught and reported as [`HighlightError::Panicked`].
+    pub fn highlight_checked(
+        self: &Arc<Self>,
+        source: &[u8],
+        theme: &SyntaxTheme,
+    ) -> Result<Vec<(Range<usize>, HighlightStyle)>, HighlightError> {
+        let text = std::str::from_utf8(source).map_err(|error| HighlightError::InvalidUtf8 {
+            valid_up_to: error.valid_up_to(),
+        })?;
+
+        let highlights = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.highlight_styles(&Rope::from(text), theme)
+        }))
+        .map_err(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            HighlightError::Panicked(message)
+        })?;
+
+        if let Some((range, _)) = highlights
+            .iter()
+            .find(|(range, _)| text.get(range.clone()).is_none())
+        {
+            return Err(HighlightError::InvalidRange(range.clone()));
+        }
+        Ok(highlights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+
+    #[test]
+    fn test_highlight_checked() {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(
+                r#"
+                "fn" @keyword
+                (string_literal) @string
+                "#,
+            )
+            .unwrap(),
+        );
+        let theme = SyntaxTheme {
+            highlights: vec![
+                ("keyword".into(), gpui::red().into()),
+                ("string".into(), gpui::blue().into()),
+            ],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        assert_eq!(
+            language.highlight_checked("fn a() { \"é\" }".as_bytes(), &theme),
+            Ok(vec![
+                (0..2, gpui::red().into()),
+                (9..13, gpui::blue().into())
+            ])
+        );
+
+        // Latin-1 text, and UTF-8 text truncated within a character.
+        assert_eq!(
+            language.highlight_checked(b"fn a() { \"\xE9\" }", &theme),
+            Err(HighlightError::InvalidUtf8 { valid_up_to: 10 })
+        );
+        assert_eq!(
+            language.highlight_checked(&"fn a() { \"é".as_bytes()[..11], &theme),
+            Err(HighlightError::InvalidUtf8 { valid_up_to: 10 })
+        );
+
+        // Text that doesn't parse is still highlighted where possible.
+        assert!(language
+            .highlight_checked(b"fn fn \" {{ \xC2\xA0", &theme)
+            .is_ok());
+    }
+}
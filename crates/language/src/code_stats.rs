@@ -0,0 +1,165 @@
+use crate::{syntax_map::QueryCursorHandle, Language};
+use std::ops::{AddAssign, Range};
+use text::Rope;
+use tree_sitter::Tree;
+
+/// Line counts for a piece of source code, broken down by kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CodeStats {
+    /// Lines containing anything other than whitespace and comments.
+    pub code_lines: usize,
+    /// Lines consisting only of comments and whitespace.
+    pub comment_lines: usize,
+    /// Lines consisting only of whitespace.
+    pub blank_lines: usize,
+}
+
+impl CodeStats {
+    pub fn total_lines(&self) -> usize {
+        self.code_lines + self.comment_lines + self.blank_lines
+    }
+}
+
+impl AddAssign for CodeStats {
+    fn add_assign(&mut self, other: Self) {
+        self.code_lines += other.code_lines;
+        self.comment_lines += other.comment_lines;
+        self.blank_lines += other.blank_lines;
+    }
+}
+
+impl Language {
+    /// Counts the code, comment and blank lines in the given source, which must
+    /// have been parsed into `tree` with this language's grammar.
+    ///
+    /// Comments are the nodes captured as `@comment` in the language's overrides
+    /// query. A line counts as a comment line if it is covered by comments and
+    /// contains no other code.
+    pub fn stats(&self, tree: &Tree, source: &Rope) -> CodeStats {
+        let text = source.to_string();
+        let comment_ranges = self.scope_ranges(tree, &text, &["comment"]);
+
+        let mut stats = CodeStats::default();
+        let mut line_start = 0;
+        let mut lines = text.split('\n').peekable();
+        while let Some(line) = lines.next() {
+            // Don't count the empty line following a trailing newline.
+            if line.is_empty() && lines.peek().is_none() {
+                break;
+            }
+
+            let mut non_whitespace = line
+                .char_indices()
+                .filter(|(_, c)| !c.is_whitespace())
+                .map(|(ix, _)| line_start + ix)
+                .peekable();
+            if non_whitespace.peek().is_none() {
+                stats.blank_lines += 1;
+            } else if non_whitespace.all(|offset| range_contains(&comment_ranges, offset)) {
+                stats.comment_lines += 1;
+            } else {
+                stats.code_lines += 1;
+            }
+
+            line_start += line.len() + 1;
+        }
+        stats
+    }
+
+    /// Returns the sorted, non-overlapping byte ranges of the nodes in the given text
+    /// that the language's overrides query captures with any of the given names, such
+    /// as `comment` or `string`.
+    pub(crate) fn scope_ranges(
+        &self,
+        tree: &Tree,
+        text: &str,
+        scope_names: &[&str],
+    ) -> Vec<Range<usize>> {
+        let Some(override_config) = self
+            .grammar
+            .as_ref()
+            .and_then(|grammar| grammar.override_config.as_ref())
+        else {
+            return Vec::new();
+        };
+        let scope_capture_ixs = override_config
+            .values
+            .iter()
+            .filter(|(_, (name, _))| scope_names.contains(&name.as_str()))
+            .map(|(ix, _)| *ix)
+            .collect::<Vec<_>>();
+        if scope_capture_ixs.is_empty() {
+            return Vec::new();
+        }
+
+        let mut ranges = Vec::<Range<usize>>::new();
+        let mut cursor = QueryCursorHandle::new();
+        for (mat, capture_ix) in
+            cursor.captures(&override_config.query, tree.root_node(), text.as_bytes())
+        {
+            let capture = mat.captures[capture_ix];
+            if scope_capture_ixs.contains(&capture.index) {
+                ranges.push(capture.node.byte_range());
+            }
+        }
+
+        ranges.sort_unstable_by_key(|range| range.start);
+        let mut merged = Vec::<Range<usize>>::with_capacity(ranges.len());
+        for range in ranges {
+            if let Some(last) = merged.last_mut() {
+                if range.start <= last.end {
+                    last.end = last.end.max(range.end);
+                    continue;
+                }
+            }
+            merged.push(range);
+        }
+        merged
+    }
+}
+
+pub(crate) fn range_contains(ranges: &[Range<usize>], offset: usize) -> bool {
+    let ix = ranges.partition_point(|range| range.end <= offset);
+    ranges.get(ix).map_or(false, |range| range.start <= offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LanguageConfig, LanguageMatcher};
+
+    #[test]
+    fn test_code_stats() {
+        let language = Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                matcher: LanguageMatcher {
+                    path_suffixes: vec!["rs".to_string()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_override_query("[(line_comment) (block_comment)] @comment")
+        .unwrap();
+
+        let source = Rope::from(
+            "// A comment\n\nfn main() { // trailing\n    /* block\n       comment */\n    let x = 1; /* inline */ let y = 2;\n}\n",
+        );
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source.to_string(), None).unwrap();
+
+        let stats = language.stats(&tree, &source);
+        assert_eq!(
+            stats,
+            CodeStats {
+                code_lines: 3,
+                comment_lines: 3,
+                blank_lines: 1,
+            }
+        );
+        assert_eq!(stats.total_lines(), 7);
+    }
+}
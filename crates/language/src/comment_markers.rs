@@ -0,0 +1,129 @@
+use crate::Language;
+use std::ops::Range;
+use text::Rope;
+use tree_sitter::Tree;
+
+/// The keywords that [`Language::comment_markers`] is usually called with.
+pub const DEFAULT_COMMENT_MARKERS: &[&str] = &["TODO", "FIXME", "HACK", "XXX"];
+
+/// A keyword such as `TODO` found in a comment by [`Language::comment_markers`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommentMarker {
+    pub keyword: String,
+    /// The rest of the comment's line after the keyword, without the colon that
+    /// usually separates them.
+    pub text: String,
+    /// The range of the keyword and its text.
+    pub range: Range<usize>,
+}
+
+impl Language {
+    /// Finds the given keywords within the comments in the given source, which must
+    /// have been parsed into `tree` with this language's grammar.
+    ///
+    /// Comments are the nodes captured as `@comment` in the language's overrides
+    /// query. Keywords only match whole words, so `TODOS` isn't a `TODO` marker.
+    pub fn comment_markers(
+        &self,
+        tree: &Tree,
+        source: &Rope,
+        keywords: &[&str],
+    ) -> Vec<CommentMarker> {
+        let text = source.to_string();
+        let block_comment_end = self
+            .config
+            .block_comment
+            .as_ref()
+            .map(|(_, end)| end.as_ref());
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+
+        let mut markers = Vec::new();
+        for comment_range in self.scope_ranges(tree, &text, &["comment"]) {
+            let comment = &text[comment_range.clone()];
+            for keyword in keywords.iter().filter(|keyword| !keyword.is_empty()) {
+                for (ix, _) in comment.match_indices(keyword) {
+                    let end_ix = ix + keyword.len();
+                    if comment[..ix]
+                        .chars()
+                        .next_back()
+                        .map_or(false, is_word_char)
+                        || comment[end_ix..].chars().next().map_or(false, is_word_char)
+                    {
+                        continue;
+                    }
+
+                    let rest = &comment[end_ix..];
+                    let mut line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+                    if line.len() == rest.len() {
+                        if let Some(end) = block_comment_end {
+                            line = line.trim_end().strip_suffix(end).unwrap_or(line);
+                        }
+                    }
+                    let line = line.trim_end();
+                    let marker_text = line.strip_prefix(':').unwrap_or(line).trim_start();
+                    markers.push(CommentMarker {
+                        keyword: keyword.to_string(),
+                        text: marker_text.to_string(),
+                        range: comment_range.start + ix..comment_range.start + end_ix + line.len(),
+                    });
+                }
+            }
+        }
+        markers.sort_by_key(|marker| marker.range.start);
+        markers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+    use tree_sitter::Parser;
+
+    #[test]
+    fn test_comment_markers() {
+        let language = Language::new(
+            LanguageConfig {
+                block_comment: Some(("/* ".into(), " */".into())),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_override_query(
+            r#"
+            [(line_comment) (block_comment)] @comment
+            (string_literal) @string
+            "#,
+        )
+        .unwrap();
+
+        let source = concat!(
+            "// TODO: x\n",
+            "fn a() {\n",
+            "    let b = \"TODO: not a marker\";\n",
+            "    /* FIXME y */\n",
+            "    // TODOS aren't markers either\n",
+            "}\n",
+        );
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let fixme_start = source.find("FIXME").unwrap();
+        assert_eq!(
+            language.comment_markers(&tree, &Rope::from(source), DEFAULT_COMMENT_MARKERS),
+            &[
+                CommentMarker {
+                    keyword: "TODO".into(),
+                    text: "x".into(),
+                    range: 3..10,
+                },
+                CommentMarker {
+                    keyword: "FIXME".into(),
+                    text: "y".into(),
+                    range: fixme_start..fixme_start + "FIXME y".len(),
+                },
+            ]
+        );
+    }
+}
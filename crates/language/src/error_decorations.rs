@@ -0,0 +1,110 @@
+use crate::Language;
+use gpui::UnderlineStyle;
+use std::ops::Range;
+use text::{Bias, Rope};
+use theme::SyntaxTheme;
+use tree_sitter::Tree;
+
+impl Language {
+    /// Returns the underlines to draw beneath the syntax errors in the given tree,
+    /// which must have been parsed from `source` with this language's grammar, styled
+    /// with the theme's diagnostic style.
+    ///
+    /// `ERROR` nodes, where the parser skipped text that it couldn't make sense of, are
+    /// underlined as errors, and nested errors are covered by the outermost one. `MISSING`
+    /// nodes, where the parser assumed a missing token such as a semicolon, are underlined
+    /// as warnings. As they are empty, the character before them is underlined instead.
+    pub fn error_decorations(
+        &self,
+        tree: &Tree,
+        source: &Rope,
+        theme: &SyntaxTheme,
+    ) -> Vec<(Range<usize>, UnderlineStyle)> {
+        let error_underline = theme.diagnostic_style.error_underline();
+        let warning_underline = theme.diagnostic_style.warning_underline();
+
+        let mut result = Vec::new();
+        let mut cursor = tree.walk();
+        loop {
+            let node = cursor.node();
+            let mut descend = node.has_error();
+            if node.is_error() {
+                descend = false;
+                if let Some(underline) = error_underline {
+                    result.push((node.byte_range(), underline));
+                }
+            } else if node.is_missing() {
+                let mut range = node.byte_range();
+                if range.is_empty() {
+                    range.start = source.clip_offset(range.start.saturating_sub(1), Bias::Left);
+                }
+                if let Some(underline) = warning_underline.filter(|_| !range.is_empty()) {
+                    result.push((range, underline));
+                }
+            }
+
+            if descend && cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+    use theme::DiagnosticStyle;
+    use tree_sitter::Parser;
+
+    #[test]
+    fn test_error_decorations() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        );
+        let theme = SyntaxTheme {
+            diagnostic_style: DiagnosticStyle {
+                error: Some(gpui::red()),
+                warning: Some(gpui::blue()),
+            },
+            ..Default::default()
+        };
+        let error_underline = theme.diagnostic_style.error_underline().unwrap();
+        let parse = |source: &str| {
+            let mut parser = Parser::new();
+            parser.set_language(&tree_sitter_rust::language()).unwrap();
+            parser.parse(source, None).unwrap()
+        };
+
+        let source = "fn a() {}\nfn b() { c @ d; }\n";
+        let decorations = language.error_decorations(&parse(source), &Rope::from(source), &theme);
+        let at_ix = source.find('@').unwrap();
+        assert!(decorations
+            .iter()
+            .any(|(range, underline)| range.contains(&at_ix) && *underline == error_underline));
+        // The valid function isn't underlined.
+        assert!(decorations
+            .iter()
+            .all(|(range, _)| range.start >= "fn a() {}\n".len()));
+
+        // Without diagnostic colors in the theme, nothing is underlined.
+        let source = "fn a() { b @ c; }";
+        let tree = parse(source);
+        assert_eq!(
+            language.error_decorations(&tree, &Rope::from(source), &SyntaxTheme::default()),
+            &[]
+        );
+
+        let source = "fn a() {}";
+        assert_eq!(
+            language.error_decorations(&parse(source), &Rope::from(source), &theme),
+            &[]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{syntax_map::QueryCursorHandle, syntax_map::TextProvider, HighlightId, Language};
+use std::{ops::Range, sync::Arc};
+use text::{Point, Rope};
+use tree_sitter::Node;
+
+/// The highlight names used to color the keywords, strings and comments in regions of
+/// text that failed to parse, in the order of the [`LexicalToken`] variants.
+pub(crate) const ERROR_HIGHLIGHT_NAMES: &[&str] = &["keyword", "string", "comment"];
+
+#[derive(Clone, Copy)]
+enum LexicalToken {
+    Keyword,
+    String,
+    Comment,
+}
+
+/// Returns lexical highlights for the `ERROR` nodes of the given syntax layers that
+/// intersect the given range, for the layers whose languages highlight errors leniently.
+///
+/// Within errors, the highlights query often captures nothing, so keywords, strings and
+/// comments are instead found by scanning the text. The keywords are the grammar's
+/// anonymous, word-like tokens, and the comments are delimited by the language's
+/// comment prefixes. Only the lines that the range touches are scanned, so block comments
+/// that start on an earlier line aren't recognized.
+pub(crate) fn error_highlights<'a>(
+    layers: impl IntoIterator<Item = (&'a Arc<Language>, Node<'a>)>,
+    text: &Rope,
+    range: Range<usize>,
+) -> Vec<(Range<usize>, HighlightId)> {
+    let mut result = Vec::new();
+    for (language, node) in layers {
+        let Some(grammar) = language.grammar.as_ref() else {
+            continue;
+        };
+        if !grammar.highlights_errors_leniently() {
+            continue;
+        }
+        let highlight_map = grammar.error_highlight_map.lock().clone();
+
+        let mut error_ranges = Vec::<Range<usize>>::new();
+        let mut cursor = QueryCursorHandle::new();
+        cursor.set_byte_range(range.clone());
+        for (mat, capture_ix) in cursor.captures(&grammar.error_query, node, TextProvider(text)) {
+            let error_range = mat.captures[capture_ix].node.byte_range();
+            // The cursor can yield captures that only touch the range.
+            if error_range.end <= range.start || error_range.start >= range.end {
+                continue;
+            }
+            // Nested errors are covered by the outermost one.
+            if error_ranges
+                .last()
+                .map_or(true, |last| last.end <= error_range.start)
+            {
+                error_ranges.push(error_range);
+            }
+        }
+
+        for error_range in error_ranges {
+            // An error can span most of a file, so only the lines that the range touches
+            // are lexed. Other than block comments, tokens don't span lines, so lexing
+            // can start at the beginning of a line without losing track of them.
+            let lex_start = if error_range.start < range.start {
+                let row = text.offset_to_point(range.start).row;
+                text.point_to_offset(Point::new(row, 0))
+                    .max(error_range.start)
+            } else {
+                error_range.start
+            };
+            let lex_end = if error_range.end > range.end {
+                let row = text.offset_to_point(range.end).row;
+                let line_end = text.point_to_offset(Point::new(row, text.line_len(row)));
+                line_end.min(error_range.end)
+            } else {
+                error_range.end
+            };
+            let error_text = text.chunks_in_range(lex_start..lex_end).collect::<String>();
+            for (token_range, token) in lex(language, &error_text) {
+                let highlight_id = highlight_map.get(token as u32);
+                let start = (lex_start + token_range.start).max(range.start);
+                let end = (lex_start + token_range.end).min(range.end);
+                if !highlight_id.is_default() && start < end {
+                    result.push((start..end, highlight_id));
+                }
+            }
+        }
+    }
+
+    // Injected layers can overlap their parent layers' errors.
+    result.sort_by_key(|(range, _)| range.start);
+    let mut previous_end = 0;
+    result.retain(|(range, _)| {
+        let is_disjoint = range.start >= previous_end;
+        if is_disjoint {
+            previous_end = range.end;
+        }
+        is_disjoint
+    });
+    result
+}
+
+fn lex(language: &Language, text: &str) -> Vec<(Range<usize>, LexicalToken)> {
+    let line_comments = language
+        .config
+        .line_comments
+        .iter()
+        .map(|prefix| prefix.trim())
+        .filter(|prefix| !prefix.is_empty())
+        .collect::<Vec<_>>();
+    let block_comment = language
+        .config
+        .block_comment
+        .as_ref()
+        .map(|(start, end)| (start.trim(), end.trim()))
+        .filter(|(start, end)| !start.is_empty() && !end.is_empty());
+    let keywords = language
+        .grammar
+        .as_ref()
+        .map(|grammar| &grammar.keyword_kinds);
+
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    while let Some(c) = text[offset..].chars().next() {
+        let rest = &text[offset..];
+        let start = offset;
+        if line_comments.iter().any(|prefix| rest.starts_with(prefix)) {
+            offset += rest.find('\n').unwrap_or(rest.len());
+            tokens.push((start..offset, LexicalToken::Comment));
+        } else if let Some((block_start, block_end)) =
+            block_comment.filter(|(block_start, _)| rest.starts_with(block_start))
+        {
+            let body = &rest[block_start.len()..];
+            offset += block_start.len()
+                + body
+                    .find(block_end)
+                    .map_or(body.len(), |ix| ix + block_end.len());
+            tokens.push((start..offset, LexicalToken::Comment));
+        } else if c == '"' {
+            offset += 1;
+            let mut chars = text[offset..].chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        offset += 1 + chars.next().map_or(0, char::len_utf8);
+                        continue;
+                    }
+                    '\n' => break,
+                    _ => offset += c.len_utf8(),
+                }
+                if c == '"' {
+                    break;
+                }
+            }
+            tokens.push((start..offset, LexicalToken::String));
+        } else if c.is_alphabetic() || c == '_' {
+            offset += rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            if keywords.map_or(false, |keywords| keywords.contains(&text[start..offset])) {
+                tokens.push((start..offset, LexicalToken::Keyword));
+            }
+        } else {
+            offset += c.len_utf8();
+        }
+    }
+    tokens
+}
@@ -0,0 +1,174 @@
+use crate::{BufferSnapshot, HighlightId, LanguageId};
+use collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use text::{Anchor, Point, ToOffset as _, ToPoint as _};
+
+/// A cache of the syntax highlights of each line of a buffer.
+///
+/// Each line's highlights are computed the first time they are requested. When the
+/// cache is next used with a newer snapshot of the buffer, only the lines that were
+/// edited, whose syntax changed when the buffer was reparsed, or whose injected
+/// language changed, are invalidated.
+#[derive(Default)]
+pub struct HighlightCache {
+    version: clock::Global,
+    parse_count: usize,
+    injections: Vec<InjectionLayer>,
+    lines: BTreeMap<u32, Vec<(Range<u32>, HighlightId)>>,
+}
+
+/// The range of an injected syntax layer and the language injected there.
+#[derive(Clone, Debug)]
+struct InjectionLayer {
+    depth: usize,
+    range: Range<Anchor>,
+    language_id: LanguageId,
+}
+
+impl InjectionLayer {
+    /// Identifies the layer by where it starts, so that a layer that moved or grew
+    /// since it was added is still the same layer.
+    fn key(&self, snapshot: &BufferSnapshot) -> (usize, usize, LanguageId) {
+        (
+            self.depth,
+            self.range.start.to_offset(snapshot),
+            self.language_id,
+        )
+    }
+
+    fn rows(&self, snapshot: &BufferSnapshot) -> Range<u32> {
+        self.range.start.to_point(snapshot).row..self.range.end.to_point(snapshot).row + 1
+    }
+}
+
+impl HighlightCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the highlighted column ranges of the given row of the buffer.
+    pub fn line_highlights(
+        &mut self,
+        snapshot: &BufferSnapshot,
+        row: u32,
+    ) -> &[(Range<u32>, HighlightId)] {
+        self.sync(snapshot);
+        self.lines
+            .entry(row)
+            .or_insert_with(|| compute_line_highlights(snapshot, row))
+    }
+
+    /// Discards all of the cached highlights.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    fn sync(&mut self, snapshot: &BufferSnapshot) {
+        if snapshot.version() != &self.version {
+            for edit in snapshot.edits_since::<Point>(&self.version) {
+                // Lines after an edit that inserts or removes lines have moved.
+                if edit.old.end.row - edit.old.start.row != edit.new.end.row - edit.new.start.row {
+                    self.lines.split_off(&edit.new.start.row);
+                    break;
+                }
+                self.invalidate_rows(edit.new.start.row..edit.new.end.row + 1);
+            }
+            self.version = snapshot.version().clone();
+        }
+
+        if snapshot.parse_count() != self.parse_count {
+            // The changed ranges only describe the most recent reparse.
+            if snapshot.parse_count() == self.parse_count + 1 {
+                for range in snapshot.syntax.changed_ranges() {
+                    let start = range.start.to_point(snapshot).row;
+                    let end = range.end.to_point(snapshot).row;
+                    self.invalidate_rows(start..end + 1);
+                }
+            } else {
+                self.lines.clear();
+            }
+            self.parse_count = snapshot.parse_count();
+
+            // Changing the language that's injected into a region, such as that of a
+            // Markdown code block, barely changes the syntax tree around the region, so
+            // the layers that were added or removed are invalidated as a whole.
+            let injections = injection_layers(snapshot);
+            let old_keys = self
+                .injections
+                .iter()
+                .map(|layer| layer.key(snapshot))
+                .collect::<HashSet<_>>();
+            let new_keys = injections
+                .iter()
+                .map(|layer| layer.key(snapshot))
+                .collect::<HashSet<_>>();
+            let changed_rows = self
+                .injections
+                .iter()
+                .filter(|layer| !new_keys.contains(&layer.key(snapshot)))
+                .chain(
+                    injections
+                        .iter()
+                        .filter(|layer| !old_keys.contains(&layer.key(snapshot))),
+                )
+                .map(|layer| layer.rows(snapshot))
+                .collect::<Vec<_>>();
+            for rows in changed_rows {
+                self.invalidate_rows(rows);
+            }
+            self.injections = injections;
+        }
+    }
+
+    fn invalidate_rows(&mut self, rows: Range<u32>) {
+        let invalidated = self
+            .lines
+            .range(rows)
+            .map(|(row, _)| *row)
+            .collect::<Vec<_>>();
+        for row in invalidated {
+            self.lines.remove(&row);
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_cached(&self, row: u32) -> bool {
+        self.lines.contains_key(&row)
+    }
+}
+
+fn injection_layers(snapshot: &BufferSnapshot) -> Vec<InjectionLayer> {
+    snapshot
+        .syntax_layers()
+        .filter(|layer| layer.depth() > 0)
+        .map(|layer| {
+            let node = layer.node();
+            InjectionLayer {
+                depth: layer.depth(),
+                range: snapshot.anchor_after(node.start_byte())
+                    ..snapshot.anchor_before(node.end_byte()),
+                language_id: layer.language.id,
+            }
+        })
+        .collect()
+}
+
+fn compute_line_highlights(snapshot: &BufferSnapshot, row: u32) -> Vec<(Range<u32>, HighlightId)> {
+    let mut result = Vec::new();
+    if row > snapshot.max_point().row {
+        return result;
+    }
+
+    let mut column = 0;
+    let line = Point::new(row, 0)..Point::new(row, snapshot.line_len(row));
+    for chunk in snapshot.chunks(line, true) {
+        let end_column = column + chunk.text.len() as u32;
+        if let Some(highlight_id) = chunk.syntax_highlight_id {
+            if !highlight_id.is_default() {
+                result.push((column..end_column, highlight_id));
+            }
+        }
+        column = end_column;
+    }
+    result
+}
@@ -0,0 +1,421 @@
+use crate::{HighlightId, Language};
+use collections::BTreeSet;
+use gpui::{FontStyle, FontWeight, HighlightStyle, Hsla};
+use std::{fmt::Write as _, ops::Range, sync::Arc};
+use text::Rope;
+use theme::SyntaxTheme;
+
+const ANSI_RESET: &str = "\x1b[0m";
+const HTML_CLASS_PREFIX: &str = "hl-";
+
+/// The range of colors supported by the terminal that ANSI output is destined for.
+///
+/// Colors are downsampled to the nearest entry of the target palette. The sequences
+/// listed below are those of foreground colors, and background colors use the
+/// corresponding `48`, `40`-`47` and `100`-`107` sequences.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// 24-bit colors, emitted as `38;2;r;g;b` sequences.
+    #[default]
+    TrueColor,
+    /// The xterm 256-color palette, emitted as `38;5;n` sequences.
+    Ansi256,
+    /// The 16 basic ANSI colors, emitted as `30`-`37` and `90`-`97` sequences.
+    Ansi16,
+}
+
+/// Options controlling how [`Language::highlight_to_html`] renders its output.
+#[derive(Clone, Debug, Default)]
+pub struct HtmlOptions {
+    /// When set, spans reference CSS classes named after the theme's highlight names,
+    /// and a stylesheet defining those classes is emitted before the code, instead of
+    /// inlining a `style` attribute on every span.
+    pub use_classes: bool,
+}
+
+impl Language {
+    /// Highlights the given text and renders it with ANSI color escape sequences,
+    /// suitable for printing to a terminal supporting the given color depth.
+    ///
+    /// Highlights whose styles have background colors, such as the added and removed
+    /// lines of a diff, are rendered with those backgrounds.
+    ///
+    /// The `theme` must be the one that was last passed to [`Language::set_theme`], as
+    /// the highlight ids produced by this language's grammar index into it.
+    pub fn highlight_to_ansi(
+        self: &Arc<Self>,
+        text: &str,
+        theme: &SyntaxTheme,
+        depth: ColorDepth,
+    ) -> String {
+        let mut result = String::with_capacity(text.len());
+        for (range, highlight_id) in self.highlight_runs(text) {
+            let chunk = &text[range];
+            match highlight_id
+                .and_then(|id| id.style(theme))
+                .filter(|style| style.color.is_some() || style.background_color.is_some())
+            {
+                Some(style) => {
+                    if let Some(color) = style.color {
+                        push_ansi_color(&mut result, color, depth, false);
+                    }
+                    if let Some(color) = style.background_color {
+                        push_ansi_color(&mut result, color, depth, true);
+                    }
+                    result.push_str(chunk);
+                    result.push_str(ANSI_RESET);
+                }
+                None => result.push_str(chunk),
+            }
+        }
+        result
+    }
+
+    /// Highlights the given text and renders it as HTML, with each highlighted run
+    /// wrapped in a `<span>` and the text itself HTML-escaped.
+    ///
+    /// As with [`Language::highlight_to_ansi`], `theme` must be the one that was last
+    /// passed to [`Language::set_theme`].
+    pub fn highlight_to_html(
+        self: &Arc<Self>,
+        text: &str,
+        theme: &SyntaxTheme,
+        options: HtmlOptions,
+    ) -> String {
+        let runs = self.highlight_runs(text);
+        let mut result = String::with_capacity(text.len());
+
+        if options.use_classes {
+            let used_ids = runs
+                .iter()
+                .filter_map(|(_, id)| Some(id.as_ref()?.0))
+                .collect::<BTreeSet<_>>();
+            result.push_str("<style>\n");
+            for id in used_ids {
+                let id = HighlightId(id);
+                if let Some((name, style)) = id.name(theme).zip(id.style(theme)) {
+                    writeln!(
+                        result,
+                        ".{} {{ {} }}",
+                        html_class_name(name),
+                        css_declarations(&style)
+                    )
+                    .unwrap();
+                }
+            }
+            result.push_str("</style>\n");
+        }
+
+        result.push_str("<pre><code>");
+        for (range, highlight_id) in runs {
+            let chunk = &text[range];
+            let name_and_style = highlight_id.and_then(|id| id.name(theme).zip(id.style(theme)));
+            match name_and_style {
+                Some((name, _)) if options.use_classes => {
+                    write!(result, "<span class=\"{}\">", html_class_name(name)).unwrap();
+                    push_html_escaped(&mut result, chunk);
+                    result.push_str("</span>");
+                }
+                Some((_, style)) => {
+                    write!(result, "<span style=\"{}\">", css_declarations(&style)).unwrap();
+                    push_html_escaped(&mut result, chunk);
+                    result.push_str("</span>");
+                }
+                None => push_html_escaped(&mut result, chunk),
+            }
+        }
+        result.push_str("</code></pre>");
+        result
+    }
+
+    /// Splits the given text into contiguous ranges covering all of it, each paired
+    /// with its highlight, if any.
+    fn highlight_runs(self: &Arc<Self>, text: &str) -> Vec<(Range<usize>, Option<HighlightId>)> {
+        let rope = Rope::from(text);
+        let mut result = Vec::new();
+        let mut offset = 0;
+        for (range, highlight_id) in self.highlight_text(&rope, 0..rope.len()) {
+            if offset < range.start {
+                result.push((offset..range.start, None));
+            }
+            offset = range.end;
+            result.push((range, Some(highlight_id)));
+        }
+        if offset < text.len() {
+            result.push((offset..text.len(), None));
+        }
+        result
+    }
+}
+
+fn push_ansi_color(output: &mut String, color: Hsla, depth: ColorDepth, background: bool) {
+    let (r, g, b) = rgb_components(color);
+    // Background color codes are offset by 10 from the foreground ones.
+    let offset = if background { 10 } else { 0 };
+    match depth {
+        ColorDepth::TrueColor => write!(output, "\x1b[{};2;{r};{g};{b}m", 38 + offset),
+        ColorDepth::Ansi256 => {
+            let index = ansi_256_index(r, g, b);
+            write!(output, "\x1b[{};5;{index}m", 38 + offset)
+        }
+        ColorDepth::Ansi16 => {
+            let index = ansi_16_index(r, g, b);
+            let code = if index < 8 {
+                30 + offset + index
+            } else {
+                90 + offset + index - 8
+            };
+            write!(output, "\x1b[{code}m")
+        }
+    }
+    .unwrap();
+}
+
+/// The channel levels of the 6x6x6 color cube occupying indices 16-231 of the
+/// xterm 256-color palette.
+const ANSI_CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The xterm defaults for the 16 basic ANSI colors.
+const ANSI_16_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Returns the index of the xterm 256-color palette entry closest to the given color,
+/// considering both the color cube and the grayscale ramp.
+fn ansi_256_index(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |value: u8| {
+        ANSI_CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| (**level as i32 - value as i32).abs())
+            .map_or(0, |(ix, _)| ix as u8)
+    };
+    let (cube_r, cube_g, cube_b) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube_index = 16 + 36 * cube_r + 6 * cube_g + cube_b;
+    let cube_color = (
+        ANSI_CUBE_LEVELS[cube_r as usize],
+        ANSI_CUBE_LEVELS[cube_g as usize],
+        ANSI_CUBE_LEVELS[cube_b as usize],
+    );
+
+    // The grayscale ramp spans indices 232-255, with levels 8, 18, ..., 238.
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + 10 * gray_step;
+    let gray_index = 232 + gray_step;
+
+    if color_distance((r, g, b), (gray_level, gray_level, gray_level))
+        < color_distance((r, g, b), cube_color)
+    {
+        gray_index
+    } else {
+        cube_index
+    }
+}
+
+/// Returns the index of the basic ANSI color closest to the given color.
+fn ansi_16_index(r: u8, g: u8, b: u8) -> u8 {
+    ANSI_16_PALETTE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| color_distance((r, g, b), **color))
+        .map_or(0, |(ix, _)| ix as u8)
+}
+
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0 as i32 - b.0 as i32;
+    let dg = a.1 as i32 - b.1 as i32;
+    let db = a.2 as i32 - b.2 as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+fn html_class_name(highlight_name: &str) -> String {
+    format!("{HTML_CLASS_PREFIX}{}", highlight_name.replace('.', "-"))
+}
+
+fn css_declarations(style: &HighlightStyle) -> String {
+    let mut declarations = String::new();
+    if let Some(color) = style.color {
+        let (r, g, b) = rgb_components(color);
+        write!(declarations, "color:#{r:02x}{g:02x}{b:02x};").unwrap();
+    }
+    if let Some(color) = style.background_color {
+        let (r, g, b) = rgb_components(color);
+        write!(declarations, "background-color:#{r:02x}{g:02x}{b:02x};").unwrap();
+    }
+    if style
+        .font_weight
+        .map_or(false, |weight| weight >= FontWeight::BOLD)
+    {
+        declarations.push_str("font-weight:bold;");
+    }
+    if style.font_style == Some(FontStyle::Italic) {
+        declarations.push_str("font-style:italic;");
+    }
+    declarations
+}
+
+fn push_html_escaped(output: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '&' => output.push_str("&amp;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+fn rgb_components(color: Hsla) -> (u8, u8, u8) {
+    let rgba = color.to_rgb();
+    let component = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    (component(rgba.r), component(rgba.g), component(rgba.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+    use gpui::rgba;
+
+    #[test]
+    fn test_highlight_to_ansi() {
+        let (language, theme) = rust_lang_with_keyword_theme();
+        assert_eq!(
+            language.highlight_to_ansi("fn main() {}", &theme, ColorDepth::TrueColor),
+            "\x1b[38;2;255;0;0mfn\x1b[0m main() {}"
+        );
+        assert_eq!(
+            language.highlight_to_ansi("fn main() {}", &theme, ColorDepth::Ansi256),
+            "\x1b[38;5;196mfn\x1b[0m main() {}"
+        );
+        assert_eq!(
+            language.highlight_to_ansi("fn main() {}", &theme, ColorDepth::Ansi16),
+            "\x1b[91mfn\x1b[0m main() {}"
+        );
+    }
+
+    #[test]
+    fn test_highlight_to_ansi_with_backgrounds() {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(r#"(line_comment) @diff.plus "fn" @keyword"#)
+            .unwrap(),
+        );
+        let theme = SyntaxTheme {
+            highlights: vec![
+                (
+                    "diff.plus".to_string(),
+                    HighlightStyle {
+                        background_color: Some(rgba(0x00ff00ff).into()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "keyword".to_string(),
+                    HighlightStyle {
+                        color: Some(rgba(0xff0000ff).into()),
+                        ..Default::default()
+                    },
+                ),
+            ],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        let text = "fn a() {} // b";
+        let styles = language.highlight_styles(&Rope::from(text), &theme);
+        assert_eq!(styles.len(), 2);
+        assert_eq!(styles[0].1.background_color, None);
+        assert_eq!(styles[1].1.background_color, Some(rgba(0x00ff00ff).into()));
+        assert_eq!(
+            language.highlight_to_ansi(text, &theme, ColorDepth::TrueColor),
+            "\x1b[38;2;255;0;0mfn\x1b[0m a() {} \x1b[48;2;0;255;0m// b\x1b[0m"
+        );
+        assert_eq!(
+            language.highlight_to_ansi(text, &theme, ColorDepth::Ansi16),
+            "\x1b[91mfn\x1b[0m a() {} \x1b[102m// b\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_ansi_color_downsampling() {
+        assert_eq!(ansi_256_index(0, 0, 0), 16);
+        assert_eq!(ansi_256_index(255, 255, 255), 231);
+        assert_eq!(ansi_256_index(255, 0, 0), 196);
+        assert_eq!(ansi_256_index(0, 135, 255), 33);
+        assert_eq!(ansi_256_index(128, 128, 128), 244);
+        assert_eq!(ansi_256_index(250, 100, 10), 202);
+
+        assert_eq!(ansi_16_index(0, 0, 0), 0);
+        assert_eq!(ansi_16_index(200, 10, 10), 1);
+        assert_eq!(ansi_16_index(250, 250, 250), 15);
+    }
+
+    #[test]
+    fn test_highlight_to_html() {
+        let (language, theme) = rust_lang_with_keyword_theme();
+        let text = "fn lt() -> bool { 1 < 2 }";
+
+        let html = language.highlight_to_html(text, &theme, HtmlOptions::default());
+        assert_eq!(
+            html,
+            "<pre><code><span style=\"color:#ff0000;\">fn</span> lt() -&gt; bool { 1 &lt; 2 }</code></pre>"
+        );
+
+        let html = language.highlight_to_html(text, &theme, HtmlOptions { use_classes: true });
+        assert_eq!(
+            html,
+            concat!(
+                "<style>\n.hl-keyword { color:#ff0000; }\n</style>\n",
+                "<pre><code><span class=\"hl-keyword\">fn</span> lt() -&gt; bool { 1 &lt; 2 }</code></pre>"
+            )
+        );
+    }
+
+    fn rust_lang_with_keyword_theme() -> (Arc<Language>, SyntaxTheme) {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig {
+                    name: "Rust".into(),
+                    ..Default::default()
+                },
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(r#""fn" @keyword"#)
+            .unwrap(),
+        );
+        let theme = SyntaxTheme {
+            highlights: vec![(
+                "keyword".to_string(),
+                HighlightStyle {
+                    color: Some(rgba(0xff0000ff).into()),
+                    ..Default::default()
+                },
+            )],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+        (language, theme)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::Language;
 use gpui::HighlightStyle;
 use std::sync::Arc;
 use theme::SyntaxTheme;
@@ -8,32 +9,62 @@ pub struct HighlightMap(Arc<[HighlightId]>);
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct HighlightId(pub u32);
 
+/// How the capture names of a highlight query are styled by a theme.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub captures: Vec<(String, CaptureCoverage)>,
+}
+
+/// How a single capture name is styled by a theme.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureCoverage {
+    /// The theme has a highlight with the same name as the capture.
+    Exact,
+    /// The capture uses the theme's highlight with the given, less specific name,
+    /// such as `function` for `function.method`.
+    Fallback(String),
+    /// No highlight in the theme matches the capture, so it is unstyled.
+    Default,
+}
+
+impl Coverage {
+    /// Returns the capture names that the theme doesn't style.
+    pub fn unstyled(&self) -> impl Iterator<Item = &str> {
+        self.captures
+            .iter()
+            .filter(|(_, coverage)| *coverage == CaptureCoverage::Default)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
 const DEFAULT_SYNTAX_HIGHLIGHT_ID: HighlightId = HighlightId(u32::MAX);
 
 impl HighlightMap {
     pub(crate) fn new(capture_names: &[&str], theme: &SyntaxTheme) -> Self {
         // For each capture name in the highlight query, find the longest
         // key in the theme's syntax styles that matches all of the
-        // dot-separated components of the capture name.
+        // dot-separated components of the capture name. Empty components,
+        // as in `function..method`, are ignored, and keys without any
+        // components don't match anything.
         HighlightMap(
             capture_names
                 .iter()
                 .map(|capture_name| {
+                    let capture_parts = Self::name_parts(capture_name);
                     theme
                         .highlights
                         .iter()
                         .enumerate()
                         .filter_map(|(i, (key, _))| {
                             let mut len = 0;
-                            let capture_parts = capture_name.split('.');
-                            for key_part in key.split('.') {
+                            for key_part in Self::name_parts(key) {
                                 if capture_parts.clone().any(|part| part == key_part) {
                                     len += 1;
                                 } else {
                                     return None;
                                 }
                             }
-                            Some((i, len))
+                            (len > 0).then_some((i, len))
                         })
                         .max_by_key(|(_, len)| *len)
                         .map_or(DEFAULT_SYNTAX_HIGHLIGHT_ID, |(i, _)| HighlightId(i as u32))
@@ -42,12 +73,75 @@ impl HighlightMap {
         )
     }
 
+    /// Normalizes a capture or theme highlight name, so that names written as
+    /// `@function`, ` function ` or `function[0]` are all treated as `function`.
+    ///
+    /// This strips surrounding whitespace, a leading `@`, and any trailing quantifier
+    /// (`?`, `*`, `+`) or array index artifacts.
+    pub fn normalize_name(name: &str) -> &str {
+        let mut name = name.trim().trim_start_matches('@');
+        loop {
+            let trimmed = name.trim_end_matches(['?', '*', '+']).trim_end();
+            let trimmed = match trimmed.strip_suffix(']') {
+                Some(prefix) => match prefix.rfind('[') {
+                    Some(ix) if prefix[ix + 1..].bytes().all(|b| b.is_ascii_digit()) => {
+                        &prefix[..ix]
+                    }
+                    _ => trimmed,
+                },
+                None => trimmed,
+            };
+            if trimmed.len() == name.len() {
+                return name;
+            }
+            name = trimmed;
+        }
+    }
+
+    fn name_parts(name: &str) -> impl Clone + Iterator<Item = &str> {
+        Self::normalize_name(name)
+            .split('.')
+            .filter(|part| !part.is_empty())
+    }
+
     pub fn get(&self, capture_id: u32) -> HighlightId {
         self.0
             .get(capture_id as usize)
             .copied()
             .unwrap_or(DEFAULT_SYNTAX_HIGHLIGHT_ID)
     }
+
+    /// Reports how each of the given capture names, which must be the ones this
+    /// map was built from, is styled by the given theme.
+    pub fn coverage(&self, capture_names: &[&str], theme: &SyntaxTheme) -> Coverage {
+        let captures = capture_names
+            .iter()
+            .enumerate()
+            .map(|(ix, capture_name)| {
+                let coverage = match self.get(ix as u32).name(theme) {
+                    Some(key)
+                        if Self::normalize_name(key) == Self::normalize_name(capture_name) =>
+                    {
+                        CaptureCoverage::Exact
+                    }
+                    Some(key) => CaptureCoverage::Fallback(key.to_string()),
+                    None => CaptureCoverage::Default,
+                };
+                (capture_name.to_string(), coverage)
+            })
+            .collect();
+        Coverage { captures }
+    }
+}
+
+impl Language {
+    /// Reports how each capture name in this language's highlights query is
+    /// styled by the given theme, so that unstyled captures can be flagged.
+    pub fn highlight_coverage(&self, theme: &SyntaxTheme) -> Option<Coverage> {
+        let query = self.grammar.as_ref()?.highlights_query.as_deref()?;
+        let capture_names = query.capture_names();
+        Some(HighlightMap::new(capture_names, theme).coverage(capture_names, theme))
+    }
 }
 
 impl HighlightId {
@@ -95,6 +189,7 @@ mod tests {
             .iter()
             .map(|(name, color)| (name.to_string(), (*color).into()))
             .collect(),
+            ..Default::default()
         };
 
         let capture_names = &[
@@ -108,4 +203,125 @@ mod tests {
         assert_eq!(map.get(1).name(&theme), Some("function.async"));
         assert_eq!(map.get(2).name(&theme), Some("variable.builtin"));
     }
+
+    #[test]
+    fn test_highlight_map_normalizes_names() {
+        let theme = SyntaxTheme {
+            highlights: [
+                ("keyword", rgba(0x100000ff)),
+                ("@function", rgba(0x200000ff)),
+            ]
+            .iter()
+            .map(|(name, color)| (name.to_string(), (*color).into()))
+            .collect(),
+            ..Default::default()
+        };
+
+        let capture_names = &["@keyword.control", " function ", "keyword[0]", "@"];
+        let map = HighlightMap::new(capture_names, &theme);
+        assert_eq!(map.get(0).name(&theme), Some("keyword"));
+        assert_eq!(map.get(1).name(&theme), Some("@function"));
+        assert_eq!(map.get(2).name(&theme), Some("keyword"));
+        assert_eq!(map.get(3).name(&theme), None);
+
+        assert_eq!(HighlightMap::normalize_name("@keyword"), "keyword");
+        assert_eq!(
+            HighlightMap::normalize_name("  string.escape? "),
+            "string.escape"
+        );
+        assert_eq!(HighlightMap::normalize_name("type[12]*"), "type");
+        assert_eq!(HighlightMap::normalize_name("odd[name]"), "odd[name]");
+    }
+
+    #[test]
+    fn test_highlight_map_with_unusual_names() {
+        let theme = SyntaxTheme {
+            highlights: [
+                ("", rgba(0x100000ff)),
+                (".", rgba(0x200000ff)),
+                ("function.method", rgba(0x300000ff)),
+                ("string.special-symbol", rgba(0x400000ff)),
+                ("string", rgba(0x500000ff)),
+            ]
+            .iter()
+            .map(|(name, color)| (name.to_string(), (*color).into()))
+            .collect(),
+            ..Default::default()
+        };
+
+        let map = HighlightMap::new(&[], &theme);
+        assert!(map.get(0).is_default());
+        assert_eq!(map.coverage(&[], &theme), Coverage::default());
+
+        let capture_names = &[
+            "function..method",
+            ".string.",
+            "string.special-symbol.rust",
+            "",
+            "..",
+            "keyword",
+        ];
+        let map = HighlightMap::new(capture_names, &theme);
+        assert_eq!(map.get(0).name(&theme), Some("function.method"));
+        assert_eq!(map.get(1).name(&theme), Some("string"));
+        assert_eq!(map.get(2).name(&theme), Some("string.special-symbol"));
+        assert_eq!(map.get(3).name(&theme), None);
+        assert_eq!(map.get(4).name(&theme), None);
+        assert_eq!(map.get(5).name(&theme), None);
+
+        // A language with an empty highlights query has an empty map.
+        let language = Language::new(
+            crate::LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query("")
+        .unwrap();
+        language.set_theme(&theme);
+        let grammar = language.grammar.as_ref().unwrap();
+        assert!(grammar.highlight_map().get(0).is_default());
+    }
+
+    #[test]
+    fn test_highlight_coverage() {
+        let theme = SyntaxTheme {
+            highlights: [
+                ("function", rgba(0x100000ff)),
+                ("@keyword", rgba(0x200000ff)),
+            ]
+            .iter()
+            .map(|(name, color)| (name.to_string(), (*color).into()))
+            .collect(),
+            ..Default::default()
+        };
+
+        let language = Language::new(
+            crate::LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query(
+            r#"
+            "fn" @keyword
+            (function_item name: (identifier) @function.definition)
+            (string_literal) @string
+            "#,
+        )
+        .unwrap();
+
+        let coverage = language.highlight_coverage(&theme).unwrap();
+        assert_eq!(
+            coverage.captures,
+            &[
+                ("keyword".to_string(), CaptureCoverage::Exact),
+                (
+                    "function.definition".to_string(),
+                    CaptureCoverage::Fallback("function".to_string())
+                ),
+                ("string".to_string(), CaptureCoverage::Default),
+            ]
+        );
+        assert_eq!(coverage.unstyled().collect::<Vec<_>>(), &["string"]);
+
+        let language = Language::new(crate::LanguageConfig::default(), None);
+        assert_eq!(language.highlight_coverage(&theme), None);
+    }
 }
@@ -6,15 +6,30 @@
 //! - Exposes [`LanguageConfig`] that describes how constructs (like brackets or line comments) should be handled by the editor for a source file of a particular language.
 //!
 //! Notably we do *not* assign a single language to a single file; in real world a single file can consist of multiple programming languages - HTML is a good example of that - and `language` crate tends to reflect that status quo in its API.
+mod autoclose;
 mod buffer;
+mod checked_highlights;
+mod code_stats;
+mod comment_markers;
 mod diagnostic_set;
+mod error_decorations;
+mod error_highlights;
+mod highlight_cache;
+mod highlight_export;
 mod highlight_map;
 mod language_registry;
 pub mod language_settings;
+mod locals;
+mod node_kind_highlights;
+mod node_walker;
 mod outline;
 pub mod proto;
+mod selector_highlights;
+mod semantic_tokens;
+mod source_encoding;
 mod syntax_map;
 mod task_context;
+mod whitespace;
 
 #[cfg(test)]
 mod buffer_tests;
@@ -23,12 +38,19 @@ pub mod markdown;
 use crate::language_settings::SoftWrap;
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+pub use checked_highlights::HighlightError;
+pub use code_stats::CodeStats;
 use collections::{HashMap, HashSet};
+pub use comment_markers::{CommentMarker, DEFAULT_COMMENT_MARKERS};
+use error_highlights::{error_highlights, ERROR_HIGHLIGHT_NAMES};
 use futures::Future;
 use gpui::{AppContext, AsyncAppContext, Model, SharedString, Task};
-pub use highlight_map::HighlightMap;
+pub use highlight_cache::HighlightCache;
+pub use highlight_export::{ColorDepth, HtmlOptions};
+pub use highlight_map::{CaptureCoverage, Coverage, HighlightMap};
 use http::HttpClient;
 use lazy_static::lazy_static;
+use locals::LocalsConfig;
 use lsp::{CodeActionKind, LanguageServerBinary};
 use parking_lot::Mutex;
 use regex::Regex;
@@ -43,6 +65,8 @@ use smol::future::FutureExt as _;
 use std::num::NonZeroU32;
 use std::{
     any::Any,
+    borrow::Cow,
+    cmp::Reverse,
     ffi::OsStr,
     fmt::Debug,
     hash::Hash,
@@ -52,28 +76,33 @@ use std::{
     pin::Pin,
     str,
     sync::{
-        atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::SeqCst},
+        Arc, Weak,
     },
 };
-use syntax_map::{QueryCursorHandle, SyntaxSnapshot};
+use syntax_map::{language_name_from_capture, QueryCursorHandle, SyntaxSnapshot, TextProvider};
 use task::RunnableTag;
 pub use task_context::{ContextProvider, RunnableRange};
 use theme::SyntaxTheme;
-use tree_sitter::{self, wasmtime, Query, QueryCursor, WasmStore};
+use tree_sitter::{self, wasmtime, Query, QueryCursor, QueryErrorKind, WasmStore};
 
 pub use buffer::Operation;
 pub use buffer::*;
 pub use diagnostic_set::DiagnosticEntry;
 pub use language_registry::{
-    LanguageNotFound, LanguageQueries, LanguageRegistry, LanguageServerBinaryStatus,
-    PendingLanguageServer, QUERY_FILENAME_PREFIXES,
+    Detection, DetectionReason, LanguageNotFound, LanguageQueries, LanguageRegistry,
+    LanguageServerBinaryStatus, PendingLanguageServer, RegistrySnapshot,
+    DEFAULT_CONTENT_DETECTION_LINES, QUERY_FILENAME_PREFIXES,
 };
 pub use lsp::LanguageServerId;
-pub use outline::{render_item, Outline, OutlineItem};
+pub use node_walker::{NodeInfo, NodeKind, NodeWalker};
+pub use outline::{lsp_symbol_kind, render_item, Outline, OutlineItem};
+pub use semantic_tokens::split_spans;
+pub use source_encoding::{decode_source, LanguageError};
 pub use syntax_map::{OwnedSyntaxLayer, SyntaxLayer};
 pub use text::{AnchorRangeExt, LineEnding};
 pub use tree_sitter::{Node, Parser, Tree, TreeCursor};
+pub use whitespace::{DetectedIndent, WhitespaceIssue};
 
 /// Initializes the `language` crate.
 ///
@@ -116,6 +145,11 @@ lazy_static! {
     static ref WASM_ENGINE: wasmtime::Engine = {
         wasmtime::Engine::new(&wasmtime::Config::new()).unwrap()
     };
+    /// The compiled highlights queries that are in use, keyed by their grammar, along
+    /// with their source, so that languages sharing a grammar share them.
+    static ref HIGHLIGHTS_QUERIES: Mutex<
+        HashMap<tree_sitter::Language, Vec<(Arc<str>, Weak<Query>, Vec<ConfigWarning>)>>,
+    > = Default::default();
 
     /// A shared grammar for plain text, exposed for reuse by downstream crates.
     pub static ref PLAIN_TEXT: Arc<Language> = Arc::new(Language::new(
@@ -549,10 +583,29 @@ pub struct CodeLabel {
 pub struct LanguageConfig {
     /// Human-readable name of the language.
     pub name: Arc<str>,
+    /// A stable identifier for the language, such as `rust` or `c_sharp`, which
+    /// can be persisted in place of its name. Defaults to the name, slugified.
+    #[serde(default)]
+    pub id: Option<String>,
     /// The name of this language for a Markdown code fence block
     pub code_fence_block_name: Option<Arc<str>>,
     // The name of the grammar in a WASM bundle (experimental).
+    //
+    // Languages without a grammar, such as those for plain text or data files, can
+    // omit it or set it to `none`. They are still detected, but aren't parsed or highlighted.
+    #[serde(default, deserialize_with = "deserialize_grammar")]
     pub grammar: Option<Arc<str>>,
+    /// The name of the tree-sitter language within the grammar, for grammars whose
+    /// language isn't named after the grammar, such as `cpp` in a `c++` grammar.
+    ///
+    /// The grammar itself is always the one registered under the `grammar` name.
+    /// This only applies to WASM grammars, whose language is loaded from their
+    /// `tree_sitter_<grammar_name>` symbol, falling back to the name of the grammar's
+    /// file when this isn't set. Languages sharing a grammar can load different
+    /// languages from it. Native grammars contain a single language, so this is
+    /// ignored for them.
+    #[serde(default)]
+    pub grammar_name: Option<String>,
     /// The criteria for matching this language to a given file.
     #[serde(flatten)]
     pub matcher: LanguageMatcher,
@@ -587,6 +640,10 @@ pub struct LanguageConfig {
     /// used for comment continuations on the next line, but only the first one is used for Editor::ToggleComments.
     #[serde(default)]
     pub line_comments: Vec<Arc<str>>,
+    /// The line comment prefix that begins documentation comments, such as `/// ` in Rust,
+    /// as opposed to regular comments. It's never inserted by `toggle comments`.
+    #[serde(default)]
+    pub documentation_comment: Option<Arc<str>>,
     /// Starting and closing characters of a block comment.
     #[serde(default)]
     pub block_comment: Option<(Arc<str>, Arc<str>)>,
@@ -614,6 +671,55 @@ pub struct LanguageConfig {
     /// If there's a parser name in the language settings, that will be used instead.
     #[serde(default)]
     pub prettier_parser_name: Option<String>,
+    /// The maximum size, in bytes, of text that will be parsed with this language's grammar.
+    /// Larger texts are treated as plain text.
+    #[serde(default)]
+    pub max_parse_bytes: Option<usize>,
+    /// The maximum size, in bytes, of regions of other languages' text that will be
+    /// parsed as this language when they're injected. Larger regions are left with the
+    /// enclosing language's highlighting. The size of combined injections is their total size.
+    #[serde(default)]
+    pub max_injection_bytes: Option<usize>,
+    /// The query files that make up the language's highlights query, which are
+    /// concatenated in the given order. If empty, all of the query files whose
+    /// names start with `highlights` are used.
+    #[serde(default)]
+    pub highlight_queries: Vec<String>,
+    /// The outline items to leave out of the language's outline, given either as the
+    /// kind of the item's node, such as `mod_item`, or as the name of another capture
+    /// in the item's outline query match, such as `definition.test`.
+    #[serde(default)]
+    pub outline_ignore: Vec<String>,
+}
+
+/// A single capture of a language's highlights query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HighlightMatch {
+    /// The index of the query pattern that produced the capture.
+    pub pattern_index: usize,
+    pub capture_name: String,
+    pub range: Range<usize>,
+}
+
+/// A region of a syntax tree that is written in another language.
+#[derive(Clone, Debug)]
+pub struct Injection {
+    pub range: Range<usize>,
+    pub language: Arc<Language>,
+    /// Whether the injection is parsed together with the language's other combined
+    /// injections, rather than on its own.
+    pub combined: bool,
+}
+
+/// The outcome of parsing a piece of text with a language's grammar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseOutcome {
+    /// The text was parsed.
+    #[default]
+    Parsed,
+    /// The text was not parsed because it exceeded the maximum parse size,
+    /// and is treated as plain text instead.
+    Skipped { len: usize },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default, JsonSchema)]
@@ -621,7 +727,14 @@ pub struct LanguageMatcher {
     /// Given a list of `LanguageConfig`'s, the language of a file can be determined based on the path extension matching any of the `path_suffixes`.
     #[serde(default)]
     pub path_suffixes: Vec<String>,
+    /// Glob patterns matched against the file name, for files whose names can't be
+    /// matched by `path_suffixes` alone (e.g. `Dockerfile.*`).
+    #[serde(default)]
+    pub path_globs: Vec<String>,
     /// A regex pattern that determines whether the language should be assigned to a file or not.
+    ///
+    /// The pattern is matched against each of the first few lines of the file, so that
+    /// signatures that don't appear on the very first line can still be detected.
     #[serde(
         default,
         serialize_with = "serialize_regex",
@@ -629,6 +742,34 @@ pub struct LanguageMatcher {
     )]
     #[schemars(schema_with = "regex_json_schema")]
     pub first_line_pattern: Option<Regex>,
+    /// Patterns matched against the start of a file's content, which favor the language
+    /// over others that match the file equally well, such as languages that share a
+    /// path suffix.
+    #[serde(default)]
+    pub content_patterns: Vec<ContentPattern>,
+    /// Breaks ties between languages that match a file equally well: the one with the
+    /// highest priority is chosen. Among languages with the same priority, the last
+    /// registered one wins.
+    #[serde(default)]
+    pub priority: u32,
+}
+
+/// A pattern that favors a language for files whose content matches it.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ContentPattern {
+    #[serde(
+        serialize_with = "serialize_content_pattern",
+        deserialize_with = "deserialize_content_pattern"
+    )]
+    #[schemars(schema_with = "regex_json_schema")]
+    pub pattern: Regex,
+    /// How strongly a match favors the language. Patterns that match add their weights.
+    #[serde(default = "default_content_pattern_weight")]
+    pub weight: u32,
+}
+
+fn default_content_pattern_weight() -> u32 {
+    1
 }
 
 /// Represents a language for the given range. Some languages (e.g. HTML)
@@ -678,12 +819,44 @@ impl<T> Override<T> {
     }
 }
 
+impl LanguageConfig {
+    /// Returns the language's stable identifier, derived from its name if it
+    /// doesn't specify one.
+    pub fn stable_id(&self) -> StableLanguageId {
+        self.id
+            .clone()
+            .unwrap_or_else(|| slugify_language_name(&self.name))
+            .into()
+    }
+}
+
+/// Converts a language name into its default identifier, e.g. `C++` into `cpp`
+/// and `C#` into `c_sharp`.
+pub fn slugify_language_name(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '+' => slug.push('p'),
+            '#' => slug.push_str("_sharp"),
+            c if c.is_alphanumeric() => slug.extend(c.to_lowercase()),
+            _ => {
+                if !slug.is_empty() && !slug.ends_with('_') {
+                    slug.push('_');
+                }
+            }
+        }
+    }
+    slug.trim_end_matches('_').to_string()
+}
+
 impl Default for LanguageConfig {
     fn default() -> Self {
         Self {
             name: "".into(),
+            id: None,
             code_fence_block_name: None,
             grammar: None,
+            grammar_name: None,
             matcher: LanguageMatcher::default(),
             brackets: Default::default(),
             auto_indent_using_last_non_empty_line: auto_indent_using_last_non_empty_line_default(),
@@ -691,6 +864,7 @@ impl Default for LanguageConfig {
             decrease_indent_pattern: Default::default(),
             autoclose_before: Default::default(),
             line_comments: Default::default(),
+            documentation_comment: None,
             block_comment: Default::default(),
             scope_opt_in_language_servers: Default::default(),
             overrides: Default::default(),
@@ -700,6 +874,10 @@ impl Default for LanguageConfig {
             tab_size: None,
             soft_wrap: None,
             prettier_parser_name: None,
+            max_parse_bytes: None,
+            max_injection_bytes: None,
+            highlight_queries: Vec::new(),
+            outline_ignore: Vec::new(),
         }
     }
 }
@@ -717,6 +895,11 @@ fn deserialize_regex<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Regex>, D
     }
 }
 
+fn deserialize_grammar<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Arc<str>>, D::Error> {
+    let name = Option::<Arc<str>>::deserialize(d)?;
+    Ok(name.filter(|name| name.as_ref() != "none"))
+}
+
 fn regex_json_schema(_: &mut SchemaGenerator) -> Schema {
     Schema::Object(SchemaObject {
         instance_type: Some(InstanceType::String.into()),
@@ -724,6 +907,18 @@ fn regex_json_schema(_: &mut SchemaGenerator) -> Schema {
     })
 }
 
+fn deserialize_content_pattern<'de, D: Deserializer<'de>>(d: D) -> Result<Regex, D::Error> {
+    let source = String::deserialize(d)?;
+    Regex::new(&source).map_err(de::Error::custom)
+}
+
+fn serialize_content_pattern<S>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(regex.as_str())
+}
+
 fn serialize_regex<S>(regex: &Option<Regex>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -806,11 +1001,52 @@ pub struct BracketPair {
     /// True if an extra newline should be inserted while the cursor is in the middle
     /// of that bracket pair.
     pub newline: bool,
+    /// True if typing `start` while text is selected should wrap the selection in
+    /// this bracket pair.
+    #[serde(default)]
+    pub surround: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub(crate) struct LanguageId(usize);
 
+/// An identifier for a language that stays the same when the language is reloaded.
+///
+/// A reloaded language is a new `Arc<Language>`, so pointer equality isn't a reliable
+/// way to key state that's kept for each language. This identifier should be used instead.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct StableLanguageId(Arc<str>);
+
+impl StableLanguageId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for StableLanguageId {
+    fn from(id: String) -> Self {
+        Self(id.into())
+    }
+}
+
+impl std::fmt::Display for StableLanguageId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq<str> for StableLanguageId {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for StableLanguageId {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
 impl LanguageId {
     pub(crate) fn new() -> Self {
         Self(NEXT_LANGUAGE_ID.fetch_add(1, SeqCst))
@@ -833,11 +1069,33 @@ impl GrammarId {
     }
 }
 
+/// A problem with a language's queries that doesn't prevent the language from loading.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigWarning {
+    /// A pattern in the highlights query references a node kind that the grammar
+    /// doesn't have, such as a misspelled one, so the pattern was removed.
+    UnknownNodeKind { kind: String },
+}
+
+impl std::fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigWarning::UnknownNodeKind { kind } => {
+                write!(f, "highlights query references unknown node kind {kind:?}")
+            }
+        }
+    }
+}
+
 pub struct Grammar {
     id: GrammarId,
     pub ts_language: tree_sitter::Language,
     pub(crate) error_query: Query,
-    pub(crate) highlights_query: Option<Query>,
+    pub(crate) highlights_query: Option<Arc<Query>>,
+    /// The source of the highlights query, for tools that show or edit it.
+    highlights_query_source: Option<Arc<str>>,
+    /// The indices of the highlights query's captures, keyed by their names.
+    highlight_capture_indices: HashMap<String, u32>,
     pub(crate) brackets_config: Option<BracketConfig>,
     pub(crate) redactions_config: Option<RedactionConfig>,
     pub(crate) runnable_config: Option<RunnableConfig>,
@@ -845,16 +1103,29 @@ pub struct Grammar {
     pub outline_config: Option<OutlineConfig>,
     pub embedding_config: Option<EmbeddingConfig>,
     pub(crate) injection_config: Option<InjectionConfig>,
+    pub(crate) locals_config: Option<LocalsConfig>,
     pub(crate) override_config: Option<OverrideConfig>,
+    pub(crate) textobjects_query: Option<Query>,
+    pub(crate) config_warnings: Vec<ConfigWarning>,
     pub(crate) highlight_map: Mutex<HighlightMap>,
+    /// The grammar's anonymous, word-like token kinds, which are its keywords.
+    pub(crate) keyword_kinds: HashSet<&'static str>,
+    /// Queries matching the subtypes of the grammar's supertypes, keyed by the
+    /// supertypes' names, or `None` for names that aren't supertypes.
+    supertype_queries: Mutex<HashMap<String, Option<Arc<Query>>>>,
+    pub(crate) error_highlight_map: Mutex<HighlightMap>,
+    highlight_errors_leniently: AtomicBool,
 }
 
 struct IndentConfig {
     query: Query,
-    indent_capture_ix: u32,
+    indent_capture_ix: Option<u32>,
     start_capture_ix: Option<u32>,
     end_capture_ix: Option<u32>,
     outdent_capture_ix: Option<u32>,
+    /// Captures nodes whose continuation lines are aligned with the start of the
+    /// node, rather than indented by a fixed amount.
+    align_capture_ix: Option<u32>,
 }
 
 pub struct OutlineConfig {
@@ -863,6 +1134,9 @@ pub struct OutlineConfig {
     pub name_capture_ix: u32,
     pub context_capture_ix: Option<u32>,
     pub extra_context_capture_ix: Option<u32>,
+    /// The indices of the captures named in the language's `outline_ignore`, whose
+    /// matches are left out of the outline.
+    pub ignored_capture_ixs: Vec<u32>,
 }
 
 #[derive(Debug)]
@@ -933,17 +1207,26 @@ impl Language {
                 Arc::new(Grammar {
                     id: GrammarId::new(),
                     highlights_query: None,
+                    highlights_query_source: None,
+                    highlight_capture_indices: HashMap::default(),
                     brackets_config: None,
                     outline_config: None,
                     embedding_config: None,
                     indents_config: None,
                     injection_config: None,
+                    locals_config: None,
                     override_config: None,
                     redactions_config: None,
                     runnable_config: None,
+                    textobjects_query: None,
+                    config_warnings: Vec::new(),
                     error_query: Query::new(&ts_language, "(ERROR) @error").unwrap(),
+                    keyword_kinds: keyword_kinds(&ts_language),
+                    supertype_queries: Default::default(),
                     ts_language,
                     highlight_map: Default::default(),
+                    error_highlight_map: Default::default(),
+                    highlight_errors_leniently: AtomicBool::new(false),
                 })
             }),
             context_provider: None,
@@ -986,6 +1269,11 @@ impl Language {
                 .with_injection_query(query.as_ref())
                 .context("Error loading injection query")?;
         }
+        if let Some(query) = queries.locals {
+            self = self
+                .with_locals_query(query.as_ref())
+                .context("Error loading locals query")?;
+        }
         if let Some(query) = queries.overrides {
             self = self
                 .with_override_query(query.as_ref())
@@ -1001,14 +1289,37 @@ impl Language {
                 .with_runnable_query(query.as_ref())
                 .context("Error loading tests query")?;
         }
+        if let Some(query) = queries.textobjects {
+            self = self
+                .with_textobjects_query(query.as_ref())
+                .context("Error loading text objects query")?;
+        }
         Ok(self)
     }
 
+    /// Sets the language's highlights query.
+    ///
+    /// Patterns that reference node kinds the grammar doesn't have are removed, rather
+    /// than failing to load the query, and reported by [`Language::config_warnings`].
     pub fn with_highlights_query(mut self, source: &str) -> Result<Self> {
+        let name = self.config.name.clone();
         let grammar = self
             .grammar_mut()
             .ok_or_else(|| anyhow!("cannot mutate grammar"))?;
-        grammar.highlights_query = Some(Query::new(&grammar.ts_language, source)?);
+        let source = Arc::<str>::from(source);
+        let (query, warnings) = shared_highlights_query(&grammar.ts_language, &source)?;
+        for warning in &warnings {
+            log::warn!("{name}: {warning}");
+        }
+        grammar.highlight_capture_indices = query
+            .capture_names()
+            .iter()
+            .enumerate()
+            .map(|(ix, name)| (name.to_string(), ix as u32))
+            .collect();
+        grammar.highlights_query = Some(query);
+        grammar.highlights_query_source = Some(source);
+        grammar.config_warnings.extend(warnings);
         Ok(self)
     }
 
@@ -1056,12 +1367,22 @@ impl Language {
             ],
         );
         if let Some((item_capture_ix, name_capture_ix)) = item_capture_ix.zip(name_capture_ix) {
+            let ignored_capture_ixs = self
+                .config
+                .outline_ignore
+                .iter()
+                .filter_map(|name| query.capture_index_for_name(name))
+                .collect();
+            let grammar = self
+                .grammar_mut()
+                .ok_or_else(|| anyhow!("cannot mutate grammar"))?;
             grammar.outline_config = Some(OutlineConfig {
                 query,
                 item_capture_ix,
                 name_capture_ix,
                 context_capture_ix,
                 extra_context_capture_ix,
+                ignored_capture_ixs,
             });
         }
         Ok(self)
@@ -1133,6 +1454,7 @@ impl Language {
         let mut start_capture_ix = None;
         let mut end_capture_ix = None;
         let mut outdent_capture_ix = None;
+        let mut align_capture_ix = None;
         get_capture_indices(
             &query,
             &mut [
@@ -1140,15 +1462,17 @@ impl Language {
                 ("start", &mut start_capture_ix),
                 ("end", &mut end_capture_ix),
                 ("outdent", &mut outdent_capture_ix),
+                ("indent.align", &mut align_capture_ix),
             ],
         );
-        if let Some(indent_capture_ix) = indent_capture_ix {
+        if indent_capture_ix.is_some() || align_capture_ix.is_some() {
             grammar.indents_config = Some(IndentConfig {
                 query,
                 indent_capture_ix,
                 start_capture_ix,
                 end_capture_ix,
                 outdent_capture_ix,
+                align_capture_ix,
             });
         }
         Ok(self)
@@ -1161,11 +1485,15 @@ impl Language {
         let query = Query::new(&grammar.ts_language, source)?;
         let mut language_capture_ix = None;
         let mut content_capture_ix = None;
+        // Captures and settings can also be named as in Tree-sitter's own injection
+        // queries, e.g. `@injection.content` and `(#set! injection.combined)`.
         get_capture_indices(
             &query,
             &mut [
                 ("language", &mut language_capture_ix),
+                ("injection.language", &mut language_capture_ix),
                 ("content", &mut content_capture_ix),
+                ("injection.content", &mut content_capture_ix),
             ],
         );
         let patterns = (0..query.pattern_count())
@@ -1173,10 +1501,10 @@ impl Language {
                 let mut config = InjectionPatternConfig::default();
                 for setting in query.property_settings(ix) {
                     match setting.key.as_ref() {
-                        "language" => {
+                        "language" | "injection.language" => {
                             config.language.clone_from(&setting.value);
                         }
-                        "combined" => {
+                        "combined" | "injection.combined" => {
                             config.combined = true;
                         }
                         _ => {}
@@ -1297,6 +1625,36 @@ impl Language {
         Ok(self)
     }
 
+    pub fn with_locals_query(mut self, source: &str) -> Result<Self> {
+        let grammar = self
+            .grammar_mut()
+            .ok_or_else(|| anyhow!("cannot mutate grammar"))?;
+        let query = Query::new(&grammar.ts_language, source)?;
+        let mut scope_capture_ix = None;
+        let mut definition_capture_ix = None;
+        get_capture_indices(
+            &query,
+            &mut [
+                ("local.scope", &mut scope_capture_ix),
+                ("local.definition", &mut definition_capture_ix),
+            ],
+        );
+        grammar.locals_config = Some(LocalsConfig {
+            query,
+            scope_capture_ix,
+            definition_capture_ix,
+        });
+        Ok(self)
+    }
+
+    pub fn with_textobjects_query(mut self, source: &str) -> Result<Self> {
+        let grammar = self
+            .grammar_mut()
+            .ok_or_else(|| anyhow!("cannot mutate grammar"))?;
+        grammar.textobjects_query = Some(Query::new(&grammar.ts_language, source)?);
+        Ok(self)
+    }
+
     fn grammar_mut(&mut self) -> Option<&mut Grammar> {
         Arc::get_mut(self.grammar.as_mut()?)
     }
@@ -1305,6 +1663,24 @@ impl Language {
         self.config.name.clone()
     }
 
+    /// Returns the language's stable identifier, which unlike its name, is
+    /// suitable for keying settings and persisted state.
+    ///
+    /// The identifier is the same for each load of the language, so it's also the
+    /// key to use for state that's kept for each language, rather than the `Arc`.
+    pub fn stable_id(&self) -> StableLanguageId {
+        self.config.stable_id()
+    }
+
+    /// Returns the bracket pair that should wrap a selection when the given character
+    /// is typed, if it opens a pair that's configured to surround selections.
+    pub fn surround_pair_for(&self, c: char) -> Option<&BracketPair> {
+        self.config.brackets.pairs.iter().find(|pair| {
+            let mut start = pair.start.chars();
+            pair.surround && start.next() == Some(c) && start.next().is_none()
+        })
+    }
+
     pub fn code_fence_block_name(&self) -> Arc<str> {
         self.config
             .code_fence_block_name
@@ -1316,29 +1692,326 @@ impl Language {
         self.context_provider.clone()
     }
 
+    /// Returns whether text of the given length should be parsed with this language,
+    /// given this language's `max_parse_bytes` and an optional additional limit.
+    pub fn parse_outcome(&self, len: usize, max_parse_bytes: Option<usize>) -> ParseOutcome {
+        let limit = match (self.config.max_parse_bytes, max_parse_bytes) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if limit.map_or(false, |limit| len > limit) {
+            ParseOutcome::Skipped { len }
+        } else {
+            ParseOutcome::Parsed
+        }
+    }
+
+    /// Returns whether an injected region of the given length should be parsed as this
+    /// language, given this language's `max_injection_bytes` and an optional additional
+    /// limit.
+    pub fn allows_injection(&self, len: usize, max_injection_bytes: Option<usize>) -> bool {
+        let limit = match (self.config.max_injection_bytes, max_injection_bytes) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        limit.map_or(true, |limit| len <= limit)
+    }
+
+    /// Returns every capture of this language's highlights query in the given tree,
+    /// along with the pattern that produced it, before any overlapping captures are
+    /// resolved or capture names are mapped to a theme.
+    pub fn highlight_matches(&self, tree: &Tree, source: &Rope) -> Vec<HighlightMatch> {
+        let Some(query) = self
+            .grammar
+            .as_ref()
+            .and_then(|grammar| grammar.highlights_query.as_deref())
+        else {
+            return Vec::new();
+        };
+
+        let capture_names = query.capture_names();
+        let mut cursor = QueryCursorHandle::new();
+        let mut result = Vec::new();
+        for mat in cursor.matches(query, tree.root_node(), TextProvider(source)) {
+            for capture in mat.captures {
+                result.push(HighlightMatch {
+                    pattern_index: mat.pattern_index,
+                    capture_name: capture_names[capture.index as usize].to_string(),
+                    range: capture.node.byte_range(),
+                });
+            }
+        }
+        result
+    }
+
+    /// Returns the sorted ranges of the keywords in the given tree, which are the nodes
+    /// captured as `@keyword` or as a more specific kind of keyword, such as
+    /// `@keyword.control`, in this language's highlights query.
+    ///
+    /// This lets the caller offer to normalize the case of keywords in languages such as
+    /// SQL, where keywords are conventionally uppercase but aren't case-sensitive.
+    pub fn keyword_ranges(&self, tree: &Tree, source: &Rope) -> Vec<Range<usize>> {
+        let mut ranges = self
+            .highlight_matches(tree, source)
+            .into_iter()
+            .filter(|mat| {
+                let name = HighlightMap::normalize_name(&mat.capture_name);
+                name == "keyword" || name.starts_with("keyword.")
+            })
+            .map(|mat| mat.range)
+            .collect::<Vec<_>>();
+        ranges.sort_unstable_by_key(|range| (range.start, range.end));
+        ranges.dedup();
+        ranges
+    }
+
+    /// Returns the range of the innermost text object of the given kind that contains
+    /// the given offset.
+    ///
+    /// The kind is the name of a capture in the language's text objects query, such
+    /// as `function.inner` or `function.outer`. If a single match captures several
+    /// nodes with that name, the object spans all of them.
+    pub fn text_object(
+        &self,
+        tree: &Tree,
+        source: &Rope,
+        offset: usize,
+        kind: &str,
+    ) -> Option<Range<usize>> {
+        let query = self.grammar.as_ref()?.textobjects_query.as_ref()?;
+        let capture_ix = query.capture_index_for_name(kind)?;
+
+        let mut cursor = QueryCursorHandle::new();
+        cursor.set_byte_range(offset..offset + 1);
+        let mut result: Option<Range<usize>> = None;
+        for mat in cursor.matches(query, tree.root_node(), TextProvider(source)) {
+            let mut object: Option<Range<usize>> = None;
+            for capture in mat.captures.iter().filter(|c| c.index == capture_ix) {
+                let range = capture.node.byte_range();
+                object = Some(match object {
+                    Some(object) => object.start.min(range.start)..object.end.max(range.end),
+                    None => range,
+                });
+            }
+            let Some(object) = object else {
+                continue;
+            };
+            if object.contains(&offset)
+                && result
+                    .as_ref()
+                    .map_or(true, |result| object.len() < result.len())
+            {
+                result = Some(object);
+            }
+        }
+        result
+    }
+
+    /// Returns the delimiters of the bracket pairs that this language's brackets query
+    /// finds in the given tree, in order, along with their nesting depth, where the
+    /// outermost pairs have a depth of zero. This can be used to color brackets by depth.
+    ///
+    /// Depths are based on the syntax tree, so unbalanced brackets only affect the
+    /// depths within the region that failed to parse.
+    pub fn bracket_depths(&self, tree: &Tree, source: &Rope) -> Vec<(Range<usize>, usize)> {
+        let Some(config) = self
+            .grammar
+            .as_ref()
+            .and_then(|grammar| grammar.brackets_config.as_ref())
+        else {
+            return Vec::new();
+        };
+
+        let mut cursor = QueryCursorHandle::new();
+        let mut pairs = Vec::new();
+        for mat in cursor.matches(&config.query, tree.root_node(), TextProvider(source)) {
+            let mut open = None;
+            let mut close = None;
+            for capture in mat.captures {
+                if capture.index == config.open_capture_ix {
+                    open = Some(capture.node.byte_range());
+                } else if capture.index == config.close_capture_ix {
+                    close = Some(capture.node.byte_range());
+                }
+            }
+            // Delimiters that are missing from the source have empty ranges.
+            if let Some((open, close)) = open.zip(close) {
+                if !open.is_empty() && !close.is_empty() && open.end <= close.start {
+                    pairs.push((open, close));
+                }
+            }
+        }
+        pairs.sort_by_key(|(open, close)| (open.start, Reverse(close.end)));
+
+        let mut result = Vec::with_capacity(pairs.len() * 2);
+        let mut enclosing_ends = Vec::<usize>::new();
+        for (open, close) in pairs {
+            while enclosing_ends
+                .last()
+                .map_or(false, |end| *end <= open.start)
+            {
+                enclosing_ends.pop();
+            }
+            let depth = enclosing_ends.len();
+            enclosing_ends.push(close.end);
+            result.push((open, depth));
+            result.push((close, depth));
+        }
+        result.sort_by_key(|(range, _)| range.start);
+        result
+    }
+
+    /// Returns the regions of the given tree that this language's injections query
+    /// marks as being written in other languages, in order.
+    ///
+    /// The injected languages are resolved through the given registry, with unknown
+    /// names falling back to plain text, and injections of languages that aren't loaded,
+    /// or that are larger than the injected language or the registry allow, are skipped.
+    pub fn injections(
+        &self,
+        tree: &Tree,
+        source: &Rope,
+        registry: &Arc<LanguageRegistry>,
+    ) -> Vec<Injection> {
+        let Some(config) = self
+            .grammar
+            .as_ref()
+            .and_then(|grammar| grammar.injection_config.as_ref())
+        else {
+            return Vec::new();
+        };
+
+        let mut cursor = QueryCursorHandle::new();
+        let mut result = Vec::new();
+        for mat in cursor.matches(&config.query, tree.root_node(), TextProvider(source)) {
+            let mut content_nodes = mat.nodes_for_capture_index(config.content_capture_ix);
+            let Some(first_node) = content_nodes.next() else {
+                continue;
+            };
+            let last_node = content_nodes.last().unwrap_or(first_node);
+            let pattern = &config.patterns[mat.pattern_index];
+
+            let language_name = if let Some(name) = pattern.language.as_ref() {
+                name.to_string()
+            } else if let Some(language_node) = config
+                .language_capture_ix
+                .and_then(|ix| mat.nodes_for_capture_index(ix).next())
+            {
+                let text = source
+                    .chunks_in_range(language_node.byte_range())
+                    .collect::<String>();
+                language_name_from_capture(&text).to_string()
+            } else {
+                continue;
+            };
+            if language_name.is_empty() {
+                continue;
+            }
+
+            let Some(language) = registry.find_injection_language(&language_name) else {
+                continue;
+            };
+            let range = first_node.start_byte()..last_node.end_byte();
+            if !language.allows_injection(range.len(), registry.max_injection_bytes()) {
+                continue;
+            }
+            result.push(Injection {
+                range,
+                language,
+                combined: pattern.combined,
+            });
+        }
+        result
+    }
+
+    /// Parses the given text and highlights the given range of it.
+    ///
+    /// Like [`Language::parse_and_highlight`], this is independent of any registry, so
+    /// it only honors this language's own `max_parse_bytes`, and not the limit set with
+    /// [`LanguageRegistry::set_max_parse_bytes`].
     pub fn highlight_text<'a>(
         self: &'a Arc<Self>,
         text: &'a Rope,
         range: Range<usize>,
     ) -> Vec<(Range<usize>, HighlightId)> {
+        if self.parse_outcome(text.len(), None) != ParseOutcome::Parsed {
+            return Vec::new();
+        }
+        let Some(grammar) = &self.grammar else {
+            return Vec::new();
+        };
+        let tree = grammar.parse_text(text, None);
+        self.highlight_tree(grammar, &tree, text, range)
+    }
+
+    /// Parses the given text and highlights all of it, returning the syntax tree along
+    /// with the highlights, so that the tree can be reused, such as for folding or
+    /// outlines, without parsing the text a second time.
+    ///
+    /// Callers that want to update the highlights incrementally as the text is edited
+    /// should hold on to the returned tree, so that it can be edited and reparsed.
+    ///
+    /// Returns `None` if the language has no grammar, or the text is larger than this
+    /// language's `max_parse_bytes`. The registry's limit doesn't apply, as the language
+    /// may not belong to a registry.
+    pub fn parse_and_highlight(
+        self: &Arc<Self>,
+        text: &Rope,
+    ) -> Option<(Tree, Vec<(Range<usize>, HighlightId)>)> {
+        if self.parse_outcome(text.len(), None) != ParseOutcome::Parsed {
+            return None;
+        }
+        let grammar = self.grammar.as_ref()?;
+        let tree = grammar.parse_text(text, None);
+        let highlights = self.highlight_tree(grammar, &tree, text, 0..text.len());
+        Some((tree, highlights))
+    }
+
+    /// Parses text that is provided in chunks, rather than as one contiguous slice, such
+    /// as text that is stored in segments or that hasn't been read in its entirety.
+    ///
+    /// `read` is called with the byte offset from which the parser needs more text, and
+    /// returns the text from that offset up to the end of a chunk, or an empty slice at
+    /// the end of the text. Returns `None` if the language has no grammar.
+    pub fn parse_chunked<T: AsRef<[u8]>>(&self, mut read: impl FnMut(usize) -> T) -> Option<Tree> {
+        let grammar = self.grammar.as_ref()?;
+        with_parser(|parser| {
+            parser
+                .set_language(&grammar.ts_language)
+                .expect("incompatible grammar");
+            parser.parse_with(&mut |offset, _| read(offset), None)
+        })
+    }
+
+    fn highlight_tree(
+        self: &Arc<Self>,
+        grammar: &Grammar,
+        tree: &Tree,
+        text: &Rope,
+        range: Range<usize>,
+    ) -> Vec<(Range<usize>, HighlightId)> {
+        let captures =
+            SyntaxSnapshot::single_tree_captures(range.clone(), text, tree, self, |grammar| {
+                grammar.highlights_query.as_deref()
+            });
+        let highlight_maps = vec![grammar.highlight_map()];
+        let mut chunks = BufferChunks::new(
+            text,
+            range.clone(),
+            Some((captures, highlight_maps)),
+            vec![],
+        );
+        chunks.set_error_highlights(error_highlights([(self, tree.root_node())], text, range));
         let mut result = Vec::new();
-        if let Some(grammar) = &self.grammar {
-            let tree = grammar.parse_text(text, None);
-            let captures =
-                SyntaxSnapshot::single_tree_captures(range.clone(), text, &tree, self, |grammar| {
-                    grammar.highlights_query.as_ref()
-                });
-            let highlight_maps = vec![grammar.highlight_map()];
-            let mut offset = 0;
-            for chunk in BufferChunks::new(text, range, Some((captures, highlight_maps)), vec![]) {
-                let end_offset = offset + chunk.text.len();
-                if let Some(highlight_id) = chunk.syntax_highlight_id {
-                    if !highlight_id.is_default() {
-                        result.push((offset..end_offset, highlight_id));
-                    }
+        let mut offset = 0;
+        for chunk in chunks {
+            let end_offset = offset + chunk.text.len();
+            if let Some(highlight_id) = chunk.syntax_highlight_id {
+                if !highlight_id.is_default() {
+                    result.push((offset..end_offset, highlight_id));
                 }
-                offset = end_offset;
             }
+            offset = end_offset;
         }
         result
     }
@@ -1347,6 +2020,42 @@ impl Language {
         &self.config.matcher.path_suffixes
     }
 
+    /// Returns the line comment prefix that begins documentation comments, if the
+    /// language distinguishes them from regular comments.
+    pub fn documentation_comment(&self) -> Option<&Arc<str>> {
+        self.config.documentation_comment.as_ref()
+    }
+
+    /// Returns whether the given character can be part of a word in this language:
+    /// alphanumeric characters and underscores always can, as can the language's
+    /// `word_characters`.
+    pub fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || self.config.word_characters.contains(&c)
+    }
+
+    /// Returns the range of the word that contains, or is adjacent to, the given
+    /// offset in the source. The range is empty if there is no such word.
+    ///
+    /// Offsets past the end of the source are clamped to it, and offsets within a
+    /// character are moved back to its start.
+    pub fn word_range_at(&self, source: &str, offset: usize) -> Range<usize> {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let start = source[..offset]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| self.is_word_char(*c))
+            .last()
+            .map_or(offset, |(ix, _)| ix);
+        let end = source[offset..]
+            .char_indices()
+            .find(|(_, c)| !self.is_word_char(*c))
+            .map_or(source.len(), |(ix, _)| offset + ix);
+        start..end
+    }
+
     pub fn should_autoclose_before(&self, c: char) -> bool {
         c.is_whitespace() || self.config.autoclose_before.contains(c)
     }
@@ -1357,6 +2066,24 @@ impl Language {
                 *grammar.highlight_map.lock() =
                     HighlightMap::new(highlights_query.capture_names(), theme);
             }
+            *grammar.error_highlight_map.lock() = HighlightMap::new(ERROR_HIGHLIGHT_NAMES, theme);
+        }
+    }
+
+    /// Clears the mapping of this language's highlights to a theme, so that nothing is
+    /// styled until [`Language::set_theme`] is called again.
+    pub fn reset_theme(&self) {
+        if let Some(grammar) = self.grammar.as_ref() {
+            *grammar.highlight_map.lock() = HighlightMap::default();
+            *grammar.error_highlight_map.lock() = HighlightMap::default();
+        }
+    }
+
+    /// Sets whether the regions of text that fail to parse are highlighted lexically,
+    /// so that their keywords, strings and comments are still colored.
+    pub fn set_highlight_errors_leniently(&self, enabled: bool) {
+        if let Some(grammar) = self.grammar.as_ref() {
+            grammar.highlight_errors_leniently.store(enabled, SeqCst);
         }
     }
 
@@ -1364,6 +2091,35 @@ impl Language {
         self.grammar.as_ref()
     }
 
+    /// Returns the source of the language's highlights query, as it was compiled,
+    /// after any query files that it's made up of were combined.
+    pub fn highlight_query_source(&self) -> Option<&str> {
+        self.grammar.as_ref()?.highlights_query_source.as_deref()
+    }
+
+    /// Returns the name of the highlights query's capture with the given index.
+    pub fn highlight_capture_name(&self, index: u32) -> Option<&str> {
+        let query = self.grammar.as_ref()?.highlights_query.as_deref()?;
+        query.capture_names().get(index as usize).copied()
+    }
+
+    /// Returns the index of the highlights query's capture with the given name,
+    /// such as `keyword`, for matching against the captures of a [`QueryCursor`].
+    pub fn highlight_capture_index(&self, name: &str) -> Option<u32> {
+        self.grammar
+            .as_ref()?
+            .highlight_capture_indices
+            .get(name)
+            .copied()
+    }
+
+    /// Returns the problems found while loading this language's queries.
+    pub fn config_warnings(&self) -> &[ConfigWarning] {
+        self.grammar
+            .as_ref()
+            .map_or(&[], |grammar| grammar.config_warnings.as_slice())
+    }
+
     pub fn default_scope(self: &Arc<Self>) -> LanguageScope {
         LanguageScope {
             language: self.clone(),
@@ -1398,6 +2154,16 @@ impl LanguageScope {
         .map_or(&[] as &[_], |e| e.as_slice())
     }
 
+    /// Returns the line comment prefix that is inserted by the `toggle comments`
+    /// action, which is the first of the line comment prefixes that doesn't begin
+    /// documentation comments.
+    pub fn toggle_comment_prefix(&self) -> Option<&Arc<str>> {
+        let documentation_comment = self.language.documentation_comment();
+        self.line_comment_prefixes()
+            .iter()
+            .find(|prefix| Some(*prefix) != documentation_comment)
+    }
+
     pub fn block_comment_delimiters(&self) -> Option<(&Arc<str>, &Arc<str>)> {
         Override::as_option(
             self.config_override().map(|o| &o.block_comment),
@@ -1496,34 +2262,67 @@ impl Grammar {
     }
 
     fn parse_text(&self, text: &Rope, old_tree: Option<Tree>) -> Tree {
-        with_parser(|parser| {
-            parser
-                .set_language(&self.ts_language)
-                .expect("incompatible grammar");
-            let mut chunks = text.chunks_in_range(0..text.len());
-            parser
-                .parse_with(
-                    &mut move |offset, _| {
-                        chunks.seek(offset);
-                        chunks.next().unwrap_or("").as_bytes()
-                    },
-                    old_tree.as_ref(),
-                )
-                .unwrap()
-        })
+        with_parser(|parser| self.parse_text_with_parser(parser, text, old_tree))
+    }
+
+    pub(crate) fn parse_text_with_parser(
+        &self,
+        parser: &mut Parser,
+        text: &Rope,
+        old_tree: Option<Tree>,
+    ) -> Tree {
+        parser
+            .set_language(&self.ts_language)
+            .expect("incompatible grammar");
+        let mut chunks = text.chunks_in_range(0..text.len());
+        parser
+            .parse_with(
+                &mut move |offset, _| {
+                    chunks.seek(offset);
+                    chunks.next().unwrap_or("").as_bytes()
+                },
+                old_tree.as_ref(),
+            )
+            .unwrap()
     }
 
     pub fn highlight_map(&self) -> HighlightMap {
         self.highlight_map.lock().clone()
     }
 
+    pub(crate) fn highlights_errors_leniently(&self) -> bool {
+        self.highlight_errors_leniently.load(SeqCst)
+    }
+
     pub fn highlight_id_for_name(&self, name: &str) -> Option<HighlightId> {
         let capture_id = self
             .highlights_query
             .as_ref()?
-            .capture_index_for_name(name)?;
+            .capture_index_for_name(HighlightMap::normalize_name(name))?;
         Some(self.highlight_map.lock().get(capture_id))
     }
+
+    /// Returns whether the grammar declares a supertype with the given name, such as
+    /// `_expression`. Supertypes are hidden, so they never appear in syntax trees, but
+    /// queries can use them to match any of their subtypes.
+    pub fn has_supertype(&self, name: &str) -> bool {
+        self.supertype_query(name).is_some()
+    }
+
+    pub(crate) fn supertype_query(&self, name: &str) -> Option<Arc<Query>> {
+        self.supertype_queries
+            .lock()
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let id = self.ts_language.id_for_node_kind(name, true);
+                if id == 0 || self.ts_language.node_kind_is_visible(id) {
+                    return None;
+                }
+                let query = Query::new(&self.ts_language, &format!("({name}) @supertype")).ok()?;
+                Some(Arc::new(query))
+            })
+            .clone()
+    }
 }
 
 impl CodeLabel {
@@ -1553,12 +2352,26 @@ impl CodeLabel {
 
 impl Ord for LanguageMatcher {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.path_suffixes.cmp(&other.path_suffixes).then_with(|| {
-            self.first_line_pattern
-                .as_ref()
-                .map(Regex::as_str)
-                .cmp(&other.first_line_pattern.as_ref().map(Regex::as_str))
-        })
+        self.path_suffixes
+            .cmp(&other.path_suffixes)
+            .then_with(|| self.path_globs.cmp(&other.path_globs))
+            .then_with(|| {
+                self.first_line_pattern
+                    .as_ref()
+                    .map(Regex::as_str)
+                    .cmp(&other.first_line_pattern.as_ref().map(Regex::as_str))
+            })
+            .then_with(|| {
+                let patterns = |matcher: &Self| {
+                    matcher
+                        .content_patterns
+                        .iter()
+                        .map(|pattern| (pattern.pattern.as_str(), pattern.weight))
+                        .collect::<Vec<_>>()
+                };
+                patterns(self).cmp(&patterns(other))
+            })
+            .then_with(|| self.priority.cmp(&other.priority))
     }
 }
 
@@ -1573,8 +2386,16 @@ impl Eq for LanguageMatcher {}
 impl PartialEq for LanguageMatcher {
     fn eq(&self, other: &Self) -> bool {
         self.path_suffixes == other.path_suffixes
+            && self.path_globs == other.path_globs
             && self.first_line_pattern.as_ref().map(Regex::as_str)
                 == other.first_line_pattern.as_ref().map(Regex::as_str)
+            && self.content_patterns.len() == other.content_patterns.len()
+            && self
+                .content_patterns
+                .iter()
+                .zip(&other.content_patterns)
+                .all(|(a, b)| a.pattern.as_str() == b.pattern.as_str() && a.weight == b.weight)
+            && self.priority == other.priority
     }
 }
 
@@ -1666,6 +2487,124 @@ impl LspAdapter for FakeLspAdapter {
     }
 }
 
+fn keyword_kinds(ts_language: &tree_sitter::Language) -> HashSet<&'static str> {
+    (0..ts_language.node_kind_count() as u16)
+        .filter(|id| ts_language.node_kind_is_visible(*id) && !ts_language.node_kind_is_named(*id))
+        .filter_map(|id| ts_language.node_kind_for_id(id))
+        .filter(|kind| {
+            kind.starts_with(|c: char| c.is_alphabetic())
+                && kind.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+        .collect()
+}
+
+/// Compiles a highlights query like [`query_without_unknown_node_kinds`], reusing the
+/// compiled query of any other language with the same grammar and query source.
+///
+/// Grammars are compared by identity rather than by name, so a WASM grammar that's
+/// reinstalled under the same name doesn't reuse queries compiled for its old version.
+fn shared_highlights_query(
+    ts_language: &tree_sitter::Language,
+    source: &Arc<str>,
+) -> Result<(Arc<Query>, Vec<ConfigWarning>)> {
+    if let Some(queries) = HIGHLIGHTS_QUERIES.lock().get(ts_language) {
+        for (query_source, query, warnings) in queries {
+            if query_source == source {
+                if let Some(query) = query.upgrade() {
+                    return Ok((query, warnings.clone()));
+                }
+            }
+        }
+    }
+
+    let (query, warnings) = query_without_unknown_node_kinds(ts_language, source)?;
+    let query = Arc::new(query);
+    let mut all_queries = HIGHLIGHTS_QUERIES.lock();
+    all_queries.retain(|_, queries| {
+        queries.retain(|(_, query, _)| query.strong_count() > 0);
+        !queries.is_empty()
+    });
+    all_queries.entry(ts_language.clone()).or_default().push((
+        source.clone(),
+        Arc::downgrade(&query),
+        warnings.clone(),
+    ));
+    Ok((query, warnings))
+}
+
+/// Compiles a query, removing any patterns that reference node kinds the grammar
+/// doesn't have instead of failing to compile.
+fn query_without_unknown_node_kinds(
+    ts_language: &tree_sitter::Language,
+    source: &str,
+) -> Result<(Query, Vec<ConfigWarning>)> {
+    let mut source = Cow::Borrowed(source);
+    let mut warnings = Vec::new();
+    loop {
+        let error = match Query::new(ts_language, &source) {
+            Ok(query) => return Ok((query, warnings)),
+            Err(error) => error,
+        };
+        let pattern_range = (error.kind == QueryErrorKind::NodeType)
+            .then(|| top_level_pattern_range(&source, error.offset))
+            .flatten();
+        let Some(pattern_range) = pattern_range else {
+            return Err(error.into());
+        };
+        // Blank out the pattern, keeping its newlines so that the rows of any
+        // later errors are unchanged.
+        let blank = source[pattern_range.clone()]
+            .chars()
+            .map(|c| if c == '\n' { '\n' } else { ' ' })
+            .collect::<String>();
+        source.to_mut().replace_range(pattern_range, &blank);
+        warnings.push(ConfigWarning::UnknownNodeKind {
+            kind: error.message,
+        });
+    }
+}
+
+/// Returns the range of the top-level pattern of a query that contains the given offset,
+/// which extends up to the start of the next pattern.
+fn top_level_pattern_range(source: &str, offset: usize) -> Option<Range<usize>> {
+    let bytes = source.as_bytes();
+    let mut pattern_starts = Vec::new();
+    let mut depth = 0_usize;
+    let mut ix = 0;
+    while ix < bytes.len() {
+        match bytes[ix] {
+            b';' => {
+                ix += source[ix..].find('\n').unwrap_or(source.len() - ix);
+            }
+            b'"' => {
+                if depth == 0 {
+                    pattern_starts.push(ix);
+                }
+                ix += 1;
+                while ix < bytes.len() && bytes[ix] != b'"' {
+                    ix += if bytes[ix] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'(' | b'[' => {
+                // Predicates following a pattern at the top level belong to that pattern.
+                if depth == 0 && bytes.get(ix + 1) != Some(&b'#') {
+                    pattern_starts.push(ix);
+                }
+                depth += 1;
+            }
+            b')' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        ix += 1;
+    }
+
+    let ix = pattern_starts
+        .partition_point(|start| *start <= offset)
+        .checked_sub(1)?;
+    let end = pattern_starts.get(ix + 1).copied().unwrap_or(source.len());
+    Some(pattern_starts[ix]..end)
+}
+
 fn get_capture_indices(query: &Query, captures: &mut [(&str, &mut Option<u32>)]) {
     for (ix, name) in query.capture_names().iter().enumerate() {
         for (capture_name, index) in captures.iter_mut() {
@@ -1770,4 +2709,625 @@ mod tests {
         // Loading an unknown language returns an error.
         assert!(languages.language_for_name("Unknown").await.is_err());
     }
+
+    #[gpui::test]
+    fn test_unused_theme_scopes(cx: &mut AppContext) {
+        let languages = LanguageRegistry::test(cx.background_executor().clone());
+        languages.add(Arc::new(
+            Language::new(
+                LanguageConfig {
+                    name: "Rust".into(),
+                    ..Default::default()
+                },
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(
+                r#"
+                (string_literal) @string
+                (function_item name: (identifier) @function.definition)
+                "#,
+            )
+            .unwrap(),
+        ));
+        languages.add(Arc::new(
+            Language::new(
+                LanguageConfig {
+                    name: "JSON".into(),
+                    ..Default::default()
+                },
+                Some(tree_sitter_json::language()),
+            )
+            .with_highlights_query("(string) @string (number) @number")
+            .unwrap(),
+        ));
+
+        assert_eq!(
+            languages.all_highlight_names(),
+            &["function.definition", "number", "string"]
+        );
+
+        let theme = SyntaxTheme {
+            highlights: ["function", "keyword", "number", "string", "string.escape"]
+                .into_iter()
+                .map(|name| (name.to_string(), gpui::red().into()))
+                .collect(),
+            ..Default::default()
+        };
+        assert_eq!(
+            languages.unused_theme_scopes(&theme),
+            &["keyword", "string.escape"]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_language_for_id(cx: &mut TestAppContext) {
+        let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+        languages.register_test_language(LanguageConfig {
+            name: "Rust (Renamed)".into(),
+            id: Some("rust".into()),
+            ..Default::default()
+        });
+        languages.register_test_language(LanguageConfig {
+            name: "C++".into(),
+            ..Default::default()
+        });
+
+        let rust = languages.language_for_id("rust").await.unwrap();
+        assert_eq!(rust.name().as_ref(), "Rust (Renamed)");
+        assert_eq!(rust.stable_id(), "rust");
+        let cpp = languages.language_for_id("cpp").await.unwrap();
+        assert_eq!(cpp.name().as_ref(), "C++");
+        assert!(languages
+            .language_for_id("rust_renamed")
+            .await
+            .unwrap_err()
+            .is::<LanguageNotFound>());
+
+        assert_eq!(slugify_language_name("Plain Text"), "plain_text");
+        assert_eq!(slugify_language_name("C#"), "c_sharp");
+        assert_eq!(slugify_language_name("Objective-C++"), "objective_cpp");
+    }
+
+    #[gpui::test]
+    async fn test_stable_id_survives_reload(cx: &mut TestAppContext) {
+        let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+        languages.register_test_language(LanguageConfig {
+            name: "Rust".into(),
+            ..Default::default()
+        });
+
+        let rust = languages.language_for_name("Rust").await.unwrap();
+        let mut state_by_language = HashMap::default();
+        state_by_language.insert(rust.stable_id(), "state");
+
+        languages.reload();
+        let reloaded_rust = languages.language_for_name("Rust").await.unwrap();
+        assert!(!Arc::ptr_eq(&rust, &reloaded_rust));
+        assert_eq!(reloaded_rust.stable_id(), rust.stable_id());
+        assert_eq!(
+            state_by_language.get(&reloaded_rust.stable_id()),
+            Some(&"state")
+        );
+    }
+
+    #[test]
+    fn test_surround_pair_for() {
+        let language = Language::new(
+            LanguageConfig {
+                brackets: BracketPairConfig {
+                    pairs: vec![
+                        BracketPair {
+                            start: "(".into(),
+                            end: ")".into(),
+                            close: true,
+                            newline: false,
+                            surround: true,
+                        },
+                        BracketPair {
+                            start: "<".into(),
+                            end: ">".into(),
+                            close: false,
+                            newline: false,
+                            surround: false,
+                        },
+                        BracketPair {
+                            start: "/*".into(),
+                            end: " */".into(),
+                            close: true,
+                            newline: false,
+                            surround: true,
+                        },
+                        BracketPair {
+                            start: "\"".into(),
+                            end: "\"".into(),
+                            close: true,
+                            newline: false,
+                            surround: true,
+                        },
+                    ],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            None,
+        );
+
+        let surround_end = |c| language.surround_pair_for(c).map(|pair| pair.end.as_str());
+        assert_eq!(surround_end('('), Some(")"));
+        assert_eq!(surround_end('"'), Some("\""));
+        // Pairs that don't surround selections, or that are opened by several characters.
+        assert_eq!(surround_end('<'), None);
+        assert_eq!(surround_end('/'), None);
+        // Closing characters don't surround selections.
+        assert_eq!(surround_end(')'), None);
+    }
+
+    #[test]
+    fn test_word_range_at() {
+        let shell = Language::new(
+            LanguageConfig {
+                name: "Shell Script".into(),
+                word_characters: HashSet::from_iter(['$']),
+                ..Default::default()
+            },
+            None,
+        );
+        let rust = Language::new(LanguageConfig::default(), None);
+
+        let source = "echo $foo_bar-baz";
+        assert!(shell.is_word_char('$'));
+        assert!(!rust.is_word_char('$'));
+        assert_eq!(shell.word_range_at(source, 7), 5..13);
+        assert_eq!(rust.word_range_at(source, 7), 6..13);
+        assert_eq!(rust.word_range_at(source, 5), 5..5);
+        assert_eq!(rust.word_range_at(source, 4), 0..4);
+        assert_eq!(rust.word_range_at(source, source.len()), 14..17);
+        assert_eq!(rust.word_range_at("ünï code", 2), 0..5);
+
+        // Offsets past the end or within a character don't panic.
+        assert_eq!(rust.word_range_at(source, source.len() + 10), 14..17);
+        assert_eq!(rust.word_range_at("ünï code", 1), 0..5);
+        assert_eq!(rust.word_range_at("a ü", 3), 2..4);
+        assert_eq!(rust.word_range_at("", 1), 0..0);
+    }
+
+    #[test]
+    fn test_text_object() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_textobjects_query(
+            r#"
+                (function_item body: (_) @function.inner) @function.outer
+                (parameters (_) @parameter.inner)
+                (block) @block.outer
+                "#,
+        )
+        .unwrap();
+
+        let text = "fn a(b: u8, c: u8) { { d; } }\nconst E: u8 = 1;";
+        let source = Rope::from(text);
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+
+        let object = |offset, kind| {
+            language
+                .text_object(&tree, &source, offset, kind)
+                .map(|range| &text[range])
+        };
+        let d_offset = text.find('d').unwrap();
+        assert_eq!(
+            object(d_offset, "function.outer"),
+            Some("fn a(b: u8, c: u8) { { d; } }")
+        );
+        assert_eq!(object(d_offset, "function.inner"), Some("{ { d; } }"));
+        assert_eq!(object(d_offset, "block.outer"), Some("{ d; }"));
+        assert_eq!(
+            object(text.find('c').unwrap(), "parameter.inner"),
+            Some("c: u8")
+        );
+        assert_eq!(object(text.find('E').unwrap(), "function.outer"), None);
+        assert_eq!(object(0, "unknown"), None);
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query(
+            r#"
+                "fn" @keyword
+                (function_item name: (identifier) @function)
+                (identifier) @variable
+                "#,
+        )
+        .unwrap();
+
+        let source = Rope::from("fn a() {}");
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source.to_string(), None).unwrap();
+
+        assert_eq!(
+            language.highlight_matches(&tree, &source),
+            &[
+                HighlightMatch {
+                    pattern_index: 0,
+                    capture_name: "keyword".into(),
+                    range: 0..2,
+                },
+                HighlightMatch {
+                    pattern_index: 1,
+                    capture_name: "function".into(),
+                    range: 3..4,
+                },
+                HighlightMatch {
+                    pattern_index: 2,
+                    capture_name: "variable".into(),
+                    range: 3..4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_and_highlight() {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(r#""fn" @keyword (identifier) @function"#)
+            .unwrap(),
+        );
+        let theme = SyntaxTheme {
+            highlights: vec![
+                ("keyword".into(), gpui::red().into()),
+                ("function".into(), gpui::blue().into()),
+            ],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        let text = Rope::from("fn a() {}");
+        let (tree, highlights) = language.parse_and_highlight(&text).unwrap();
+        assert_eq!(tree.root_node().kind(), "source_file");
+        assert_eq!(tree.root_node().byte_range(), 0..text.len());
+        assert_eq!(highlights, language.highlight_text(&text, 0..text.len()));
+        assert_eq!(
+            highlights
+                .iter()
+                .map(|(range, id)| (range.clone(), id.name(&theme).unwrap()))
+                .collect::<Vec<_>>(),
+            &[(0..2, "keyword"), (3..4, "function")]
+        );
+
+        let language = Arc::new(Language::new(LanguageConfig::default(), None));
+        assert!(language.parse_and_highlight(&text).is_none());
+    }
+
+    #[test]
+    fn test_parse_chunked() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        );
+        let source = "fn a() {\n    let b = \"c\";\n}\n\nstruct D { e: u32 }\n";
+        let chunks = [
+            "fn a",
+            "() {\n    let ",
+            "b = \"",
+            "c\";\n}\n",
+            "\nstruct D { e: u32 }\n",
+        ];
+        assert_eq!(chunks.concat(), source);
+
+        let mut read_offsets = Vec::new();
+        let tree = language
+            .parse_chunked(|offset| {
+                read_offsets.push(offset);
+                let mut chunk_start = 0;
+                for chunk in chunks {
+                    if offset < chunk_start + chunk.len() {
+                        return &chunk[offset - chunk_start..];
+                    }
+                    chunk_start += chunk.len();
+                }
+                ""
+            })
+            .unwrap();
+        assert!(read_offsets.len() > 1);
+
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let expected_tree = parser.parse(source, None).unwrap();
+        assert_eq!(
+            tree.root_node().to_sexp(),
+            expected_tree.root_node().to_sexp()
+        );
+        assert_eq!(tree.root_node().byte_range(), 0..source.len());
+
+        let language = Language::new(LanguageConfig::default(), None);
+        assert!(language.parse_chunked(|_| "").is_none());
+    }
+
+    #[test]
+    fn test_keyword_ranges() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query(
+            r#"
+                ["fn" "let"] @keyword
+                ["if" "else"] @keyword.control
+                (visibility_modifier) @keyword.modifier
+                (identifier) @keywords
+                (string_literal) @string
+                "#,
+        )
+        .unwrap();
+
+        let text = "pub fn a() { let b = \"if\"; if b {} else {} }";
+        let source = Rope::from(text);
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+
+        let keywords = language
+            .keyword_ranges(&tree, &source)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect::<Vec<_>>();
+        assert_eq!(keywords, &["pub", "fn", "let", "if", "else"]);
+    }
+
+    #[test]
+    fn test_any_of_predicates_in_highlights_query() {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(
+                r#"
+                ((primitive_type) @type (#any-of? @type "i32" "u32" "usize"))
+                ((identifier) @constant (#not-any-of? @constant "a" "b"))
+                "#,
+            )
+            .unwrap(),
+        );
+        let theme = SyntaxTheme {
+            highlights: vec![
+                ("type".into(), gpui::red().into()),
+                ("constant".into(), gpui::blue().into()),
+            ],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        let text = Rope::from("fn f(a: i32, b: u8, c: usize, d: u32) {}");
+        let highlights = language
+            .highlight_text(&text, 0..text.len())
+            .into_iter()
+            .map(|(range, highlight_id)| {
+                (
+                    text.chunks_in_range(range).collect::<String>(),
+                    highlight_id.name(&theme).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            highlights,
+            &[
+                ("f".to_string(), "constant"),
+                ("i32".to_string(), "type"),
+                ("c".to_string(), "constant"),
+                ("usize".to_string(), "type"),
+                ("d".to_string(), "constant"),
+                ("u32".to_string(), "type"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlights_queries_are_shared_across_languages() {
+        let query_source = "(line_comment) @comment (functon_item) @function";
+        let language = |name: &str, query_source: &str| {
+            Language::new(
+                LanguageConfig {
+                    name: name.into(),
+                    grammar: Some("rust".into()),
+                    ..Default::default()
+                },
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(query_source)
+            .unwrap()
+        };
+        let highlights_query = |language: &Language| {
+            language
+                .grammar()
+                .unwrap()
+                .highlights_query
+                .clone()
+                .unwrap()
+        };
+
+        // Languages sharing a grammar and a query source share the compiled query.
+        let rust = language("Rust", query_source);
+        let make = language("Make", query_source);
+        assert!(Arc::ptr_eq(
+            &highlights_query(&rust),
+            &highlights_query(&make)
+        ));
+        assert_eq!(make.config_warnings(), rust.config_warnings());
+        assert_eq!(make.config_warnings().len(), 1);
+
+        let other = language("Other", "(line_comment) @comment");
+        assert!(!Arc::ptr_eq(
+            &highlights_query(&rust),
+            &highlights_query(&other)
+        ));
+
+        // A different grammar that's loaded under the same name, as when a grammar is
+        // reinstalled, doesn't reuse the query compiled for the old one.
+        let query_source = "(identifier) @variable";
+        let rust = language("Rust", query_source);
+        let reloaded = Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                grammar: Some("rust".into()),
+                ..Default::default()
+            },
+            Some(tree_sitter_ruby::language()),
+        )
+        .with_highlights_query(query_source)
+        .unwrap();
+        assert!(!Arc::ptr_eq(
+            &highlights_query(&rust),
+            &highlights_query(&reloaded)
+        ));
+    }
+
+    #[test]
+    fn test_highlights_query_with_unknown_node_kinds() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query(
+            r#"
+            ; A misspelled node kind.
+            (functon_item name: (identifier) @function)
+            "fn" @keyword
+            ((identifier) @constant (#match? @constant "^[A-Z]"))
+            "fnn" @keyword
+            (identifier) @variable
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            language.config_warnings(),
+            &[
+                ConfigWarning::UnknownNodeKind {
+                    kind: "functon_item".into()
+                },
+                ConfigWarning::UnknownNodeKind { kind: "fnn".into() },
+            ]
+        );
+        assert_eq!(
+            language
+                .grammar()
+                .unwrap()
+                .highlights_query
+                .as_ref()
+                .unwrap()
+                .capture_names(),
+            &["keyword", "constant", "variable"]
+        );
+
+        // Other errors still fail to load the query.
+        assert!(Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query("(identifier @variable")
+        .is_err());
+    }
+
+    #[gpui::test]
+    fn test_injections(cx: &mut TestAppContext) {
+        let registry = Arc::new(LanguageRegistry::test(cx.executor()));
+        let sql = Arc::new(Language::new(
+            LanguageConfig {
+                name: "SQL".into(),
+                ..Default::default()
+            },
+            None,
+        ));
+        registry.add(sql.clone());
+        let rust = Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_injection_query(
+            r#"
+            (macro_invocation
+                macro: (identifier) @injection.language
+                (token_tree) @injection.content)
+            "#,
+        )
+        .unwrap();
+
+        let source = Rope::from("fn a() { sql!(SELECT 1); html!(<br>); }");
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source.to_string(), None).unwrap();
+
+        // There's no HTML language, so the HTML injection is plain text.
+        let injections = rust.injections(&tree, &source, &registry);
+        assert_eq!(
+            injections
+                .iter()
+                .map(|injection| (
+                    injection.range.clone(),
+                    injection.language.name(),
+                    injection.combined
+                ))
+                .collect::<Vec<_>>(),
+            &[
+                (13..23, sql.name(), false),
+                (30..36, PLAIN_TEXT.name(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bracket_depths() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_brackets_query(
+            r#"
+            ("(" @open ")" @close)
+            ("[" @open "]" @close)
+            ("{" @open "}" @close)
+            "#,
+        )
+        .unwrap();
+
+        let source = Rope::from("fn a() { [(1)]; }");
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source.to_string(), None).unwrap();
+        assert_eq!(
+            language.bracket_depths(&tree, &source),
+            &[
+                (4..5, 0),
+                (5..6, 0),
+                (7..8, 0),
+                (9..10, 1),
+                (10..11, 2),
+                (12..13, 2),
+                (13..14, 1),
+                (16..17, 0),
+            ]
+        );
+
+        // An unclosed bracket doesn't affect the depths before it.
+        let source = Rope::from("fn a() { [(1]; }");
+        let tree = parser.parse(source.to_string(), None).unwrap();
+        let depths = language.bracket_depths(&tree, &source);
+        assert_eq!(&depths[..2], &[(4..5, 0), (5..6, 0)]);
+    }
 }
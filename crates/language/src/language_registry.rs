@@ -1,20 +1,23 @@
 use crate::{
+    decode_source,
     language_settings::{
         all_language_settings, AllLanguageSettingsContent, LanguageSettingsContent,
     },
+    slugify_language_name,
     task_context::ContextProvider,
-    with_parser, CachedLspAdapter, File, Language, LanguageConfig, LanguageId, LanguageMatcher,
-    LanguageServerName, LspAdapter, LspAdapterDelegate, PLAIN_TEXT,
+    with_parser, CachedLspAdapter, ContentPattern, File, HighlightMap, Language, LanguageConfig,
+    LanguageId, LanguageMatcher, LanguageServerName, LspAdapter, LspAdapterDelegate, ParseOutcome,
+    StableLanguageId, Tree, PLAIN_TEXT,
 };
 use anyhow::{anyhow, Context as _, Result};
-use collections::{hash_map, HashMap};
+use collections::{hash_map, HashMap, HashSet};
 use futures::TryFutureExt;
 use futures::{
     channel::{mpsc, oneshot},
-    future::Shared,
+    future::{join_all, Shared},
     Future, FutureExt as _,
 };
-use globset::GlobSet;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use gpui::{AppContext, BackgroundExecutor, Task};
 use lsp::LanguageServerId;
 use parking_lot::{Mutex, RwLock};
@@ -22,16 +25,25 @@ use postage::watch;
 use std::{
     borrow::Cow,
     ffi::OsStr,
+    iter,
     ops::Not,
     path::{Path, PathBuf},
     sync::Arc,
 };
 use sum_tree::Bias;
 use text::{Point, Rope};
-use theme::Theme;
+use theme::{SyntaxTheme, Theme};
 use unicase::UniCase;
 use util::{maybe, paths::PathExt, post_inc, ResultExt};
 
+/// The number of lines at the start of a file that are scanned by default when
+/// detecting the file's language from its content.
+pub const DEFAULT_CONTENT_DETECTION_LINES: u32 = 3;
+
+/// The number of bytes at the start of a file that are matched against each
+/// language's `content_patterns`.
+const CONTENT_PATTERN_BYTES: usize = 4096;
+
 pub struct LanguageRegistry {
     state: RwLock<LanguageRegistryState>,
     language_server_download_dir: Option<Arc<Path>>,
@@ -45,13 +57,23 @@ struct LanguageRegistryState {
     languages: Vec<Arc<Language>>,
     language_settings: AllLanguageSettingsContent,
     available_languages: Vec<AvailableLanguage>,
+    path_index: Option<Arc<PathIndex>>,
     grammars: HashMap<Arc<str>, AvailableGrammar>,
+    /// The languages loaded from WASM grammars under a `grammar_name`, keyed by the
+    /// grammar and that name, as a grammar can contain several languages.
+    grammar_languages: HashMap<(Arc<str>, String), AvailableGrammar>,
     lsp_adapters: HashMap<Arc<str>, Vec<Arc<CachedLspAdapter>>>,
     available_lsp_adapters:
         HashMap<LanguageServerName, Arc<dyn Fn() -> Arc<CachedLspAdapter> + 'static + Send + Sync>>,
     loading_languages: HashMap<LanguageId, Vec<oneshot::Sender<Result<Arc<Language>>>>>,
     subscription: (watch::Sender<()>, watch::Receiver<()>),
     theme: Option<Arc<Theme>>,
+    content_detection_lines: u32,
+    max_parse_bytes: Option<usize>,
+    max_injection_bytes: Option<usize>,
+    highlight_errors_leniently: bool,
+    /// The names of the languages that are never chosen for files.
+    disabled_languages: HashSet<Arc<str>>,
     version: usize,
     reload_count: usize,
 
@@ -74,10 +96,258 @@ pub struct PendingLanguageServer {
     pub container_dir: Option<Arc<Path>>,
 }
 
+/// An index of the default path suffixes and globs of the available languages,
+/// so that finding the languages matching a path doesn't require checking each
+/// language's matcher.
+struct PathIndex {
+    suffixes: HashMap<String, Vec<Arc<str>>>,
+    globs: GlobSet,
+    /// The name of the language and the pattern for each glob in `globs`.
+    glob_languages: Vec<(Arc<str>, String)>,
+    /// The languages with a `first_line_pattern`.
+    first_line_languages: Vec<Arc<str>>,
+    /// The languages that each mode named by a modeline refers to, by the lowercase
+    /// names and path suffixes of the languages.
+    modes: HashMap<String, Vec<Arc<str>>>,
+    /// The position of each language in the languages that the index was built from.
+    positions: HashMap<Arc<str>, usize>,
+}
+
+/// The language detected for a file, along with the reason it was chosen.
+#[derive(Clone, Debug)]
+pub struct Detection {
+    pub language: Option<Arc<Language>>,
+    pub reason: DetectionReason,
+}
+
+/// The signal that caused a language to be chosen for a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DetectionReason {
+    /// The file's name or extension is associated with the language in the
+    /// user's `file_types` settings.
+    UserFileType(String),
+    /// The file's name is one of the language's path suffixes.
+    ExactFileName(String),
+    /// The file's name ends with one of the language's path suffixes that contains a
+    /// dot, such as `d.ts` for `index.d.ts`.
+    CompoundSuffix(String),
+    /// The file's extension is one of the language's path suffixes.
+    Extension(String),
+    /// The file's name matches one of the language's path globs.
+    Glob(String),
+    /// The file's shebang line, which runs the given interpreter, matches the
+    /// language's `first_line_pattern`.
+    Shebang(String),
+    /// One of the first lines of the file's content matches the language's
+    /// `first_line_pattern`.
+    FirstLine,
+    /// The file's path and first lines don't match any language, but one of its first
+    /// lines is an Emacs or Vim modeline that names the given mode, which is the
+    /// language's name or one of its path suffixes.
+    Modeline(String),
+    /// The file matched the language in one of the other ways, and was favored over
+    /// other languages because its content matches this one of the language's
+    /// `content_patterns`.
+    ContentPattern(String),
+    /// No language matched the file.
+    NoMatch,
+}
+
+impl PathIndex {
+    fn new(languages: &[AvailableLanguage]) -> Self {
+        let mut suffixes = HashMap::<String, Vec<Arc<str>>>::default();
+        let mut globs = GlobSetBuilder::new();
+        let mut glob_languages = Vec::new();
+        let mut first_line_languages = Vec::new();
+        let mut modes = HashMap::<String, Vec<Arc<str>>>::default();
+        let mut positions = HashMap::default();
+        for (ix, language) in languages.iter().enumerate() {
+            positions.insert(language.name.clone(), ix);
+            if language.matcher.first_line_pattern.is_some() {
+                first_line_languages.push(language.name.clone());
+            }
+            let mut language_modes = iter::once(language.name.to_ascii_lowercase())
+                .chain(
+                    language
+                        .matcher
+                        .path_suffixes
+                        .iter()
+                        .map(|suffix| suffix.to_ascii_lowercase()),
+                )
+                .collect::<Vec<_>>();
+            language_modes.sort_unstable();
+            language_modes.dedup();
+            for mode in language_modes {
+                modes.entry(mode).or_default().push(language.name.clone());
+            }
+            for suffix in &language.matcher.path_suffixes {
+                suffixes
+                    .entry(suffix.clone())
+                    .or_default()
+                    .push(language.name.clone());
+            }
+            for glob in &language.matcher.path_globs {
+                match Glob::new(glob) {
+                    Ok(glob) => {
+                        glob_languages.push((language.name.clone(), glob.glob().to_string()));
+                        globs.add(glob);
+                    }
+                    Err(error) => log::warn!(
+                        "skipping invalid path glob {glob:?} for language {}: {error}",
+                        language.name
+                    ),
+                }
+            }
+        }
+
+        Self {
+            suffixes,
+            globs: globs.build().log_err().unwrap_or_else(GlobSet::empty),
+            glob_languages,
+            first_line_languages,
+            modes,
+            positions,
+        }
+    }
+
+    /// Returns the position of the language with the given name in the given languages,
+    /// which may have changed since the index was built from them.
+    fn position(&self, languages: &[AvailableLanguage], name: &str) -> Option<usize> {
+        match self.positions.get(name) {
+            Some(&ix)
+                if languages
+                    .get(ix)
+                    .map_or(false, |language| &*language.name == name) =>
+            {
+                Some(ix)
+            }
+            Some(_) => languages
+                .iter()
+                .position(|language| &*language.name == name),
+            None => None,
+        }
+    }
+
+    /// Returns the languages that the given file name, the file name's compound
+    /// suffixes or the extension belongs to, or whose globs match the given file name,
+    /// along with what matched.
+    fn matching_languages(
+        &self,
+        extension: Option<&str>,
+        filename: Option<&str>,
+    ) -> HashMap<Arc<str>, DetectionReason> {
+        let mut result = HashMap::default();
+        if let Some(filename) = filename {
+            for language in self.suffixes.get(filename).into_iter().flatten() {
+                result
+                    .entry(language.clone())
+                    .or_insert_with(|| DetectionReason::ExactFileName(filename.to_string()));
+            }
+        }
+        if let Some(filename) = filename {
+            for (ix, _) in filename.match_indices('.') {
+                let suffix = &filename[ix + 1..];
+                if !suffix.contains('.') {
+                    break;
+                }
+                for language in self.suffixes.get(suffix).into_iter().flatten() {
+                    result
+                        .entry(language.clone())
+                        .or_insert_with(|| DetectionReason::CompoundSuffix(suffix.to_string()));
+                }
+            }
+        }
+        if let Some(extension) = extension {
+            for language in self.suffixes.get(extension).into_iter().flatten() {
+                result
+                    .entry(language.clone())
+                    .or_insert_with(|| DetectionReason::Extension(extension.to_string()));
+            }
+        }
+        if let Some(filename) = filename {
+            for glob_ix in self.globs.matches(filename) {
+                let (language, glob) = &self.glob_languages[glob_ix];
+                result
+                    .entry(language.clone())
+                    .or_insert_with(|| DetectionReason::Glob(glob.clone()));
+            }
+        }
+        result
+    }
+}
+
+/// Returns the detection score of a language whose path or first line matched for the
+/// given reason. Compound suffixes are more specific than extensions, so they're favored.
+fn path_match_score(reason: &DetectionReason) -> usize {
+    match reason {
+        DetectionReason::CompoundSuffix(_) => 2,
+        _ => 1,
+    }
+}
+
+/// An immutable view of the languages that a [`LanguageRegistry`] had loaded when the
+/// snapshot was taken, for classifying many files without locking the registry.
+///
+/// The snapshot doesn't change when languages are added to the registry, loaded, enabled
+/// or disabled, or when the registry is reloaded, so it can be outdated. This can be
+/// detected by comparing [`Self::version`] with [`LanguageRegistry::version`]. Languages
+/// that aren't loaded yet are never returned, so they should be loaded first, for example
+/// with [`LanguageRegistry::prewarm`].
+#[derive(Clone)]
+pub struct RegistrySnapshot {
+    /// The enabled, loaded languages, in the order they were registered in.
+    languages: Arc<[Arc<Language>]>,
+    path_index: Arc<PathIndex>,
+    version: usize,
+}
+
+impl RegistrySnapshot {
+    /// Returns the version of the registry that the snapshot was taken at.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    pub fn languages(&self) -> &[Arc<Language>] {
+        &self.languages
+    }
+
+    /// Returns the language with the given name, ignoring case.
+    pub fn language_for_name(&self, name: &str) -> Option<Arc<Language>> {
+        let name = UniCase::new(name);
+        self.languages
+            .iter()
+            .find(|language| UniCase::new(language.name().as_ref()) == name)
+            .cloned()
+    }
+
+    /// Returns the language for the file at the given path, chosen by the path's name,
+    /// compound suffixes, extension and globs.
+    ///
+    /// Unlike [`LanguageRegistry::language_for_file`], this ignores the user's
+    /// `file_types` settings, and as there's no content, first lines, modelines and
+    /// content patterns aren't matched either. Paths are matched and scored as in the
+    /// registry, so among the languages that match equally well, the one with the highest
+    /// priority wins, or the last registered one if their priorities are equal.
+    pub fn language_for_file_path(&self, path: &Path) -> Option<Arc<Language>> {
+        let filename = path.file_name().and_then(|name| name.to_str());
+        let extension = path.extension_or_hidden_file_name();
+        let path_matches = self.path_index.matching_languages(extension, filename);
+        self.languages
+            .iter()
+            .filter_map(|language| {
+                let reason = path_matches.get(&language.name())?;
+                Some((language, path_match_score(reason)))
+            })
+            .max_by_key(|(language, score)| (*score, language.config.matcher.priority))
+            .map(|(language, _)| language.clone())
+    }
+}
+
 #[derive(Clone)]
 struct AvailableLanguage {
     id: LanguageId,
     name: Arc<str>,
+    stable_id: StableLanguageId,
     grammar: Option<Arc<str>>,
     matcher: LanguageMatcher,
     load: Arc<
@@ -101,7 +371,19 @@ enum AvailableGrammar {
         Vec<oneshot::Sender<Result<tree_sitter::Language, Arc<anyhow::Error>>>>,
     ),
     Unloaded(PathBuf),
-    LoadFailed(Arc<anyhow::Error>),
+    LoadFailed(PathBuf, Arc<anyhow::Error>),
+}
+
+impl AvailableGrammar {
+    fn wasm_path(&self) -> Option<&PathBuf> {
+        match self {
+            Self::Native(_) => None,
+            Self::Loaded(path, _)
+            | Self::Loading(path, _)
+            | Self::Unloaded(path)
+            | Self::LoadFailed(path, _) => Some(path),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -123,9 +405,11 @@ pub const QUERY_FILENAME_PREFIXES: &[(
     ("indents", |q| &mut q.indents),
     ("embedding", |q| &mut q.embedding),
     ("injections", |q| &mut q.injections),
+    ("locals", |q| &mut q.locals),
     ("overrides", |q| &mut q.overrides),
     ("redactions", |q| &mut q.redactions),
     ("runnables", |q| &mut q.runnables),
+    ("text-objects", |q| &mut q.textobjects),
 ];
 
 /// Tree-sitter language queries for a given language.
@@ -137,9 +421,11 @@ pub struct LanguageQueries {
     pub outline: Option<Cow<'static, str>>,
     pub embedding: Option<Cow<'static, str>>,
     pub injections: Option<Cow<'static, str>>,
+    pub locals: Option<Cow<'static, str>>,
     pub overrides: Option<Cow<'static, str>>,
     pub redactions: Option<Cow<'static, str>>,
     pub runnables: Option<Cow<'static, str>>,
+    pub textobjects: Option<Cow<'static, str>>,
 }
 
 #[derive(Clone, Default)]
@@ -154,13 +440,20 @@ impl LanguageRegistry {
                 next_language_server_id: 0,
                 languages: Vec::new(),
                 available_languages: Vec::new(),
+                path_index: None,
                 grammars: Default::default(),
+                grammar_languages: Default::default(),
                 language_settings: Default::default(),
                 loading_languages: Default::default(),
                 lsp_adapters: Default::default(),
                 available_lsp_adapters: HashMap::default(),
                 subscription: watch::channel(),
                 theme: Default::default(),
+                content_detection_lines: DEFAULT_CONTENT_DETECTION_LINES,
+                max_parse_bytes: None,
+                max_injection_bytes: None,
+                highlight_errors_leniently: false,
+                disabled_languages: HashSet::default(),
                 version: 0,
                 reload_count: 0,
 
@@ -213,6 +506,7 @@ impl LanguageRegistry {
     pub fn register_test_language(&self, config: LanguageConfig) {
         self.register_language(
             config.name.clone(),
+            config.id.clone(),
             config.grammar.clone(),
             config.matcher.clone(),
             move || Ok((config.clone(), Default::default(), None)),
@@ -314,9 +608,13 @@ impl LanguageRegistry {
     }
 
     /// Adds a language to the registry, which can be loaded if needed.
+    ///
+    /// The language can be looked up by the given stable identifier, or if there
+    /// isn't one, by its slugified name.
     pub fn register_language(
         &self,
         name: Arc<str>,
+        stable_id: Option<String>,
         grammar_name: Option<Arc<str>>,
         matcher: LanguageMatcher,
         load: impl Fn() -> Result<(
@@ -329,10 +627,14 @@ impl LanguageRegistry {
             + Sync,
     ) {
         let load = Arc::new(load);
+        let stable_id =
+            StableLanguageId::from(stable_id.unwrap_or_else(|| slugify_language_name(&name)));
         let state = &mut *self.state.write();
+        state.path_index = None;
 
         for existing_language in &mut state.available_languages {
             if existing_language.name == name {
+                existing_language.stable_id = stable_id;
                 existing_language.grammar = grammar_name;
                 existing_language.matcher = matcher;
                 existing_language.load = load;
@@ -343,6 +645,7 @@ impl LanguageRegistry {
         state.available_languages.push(AvailableLanguage {
             id: LanguageId::new(),
             name,
+            stable_id,
             grammar: grammar_name,
             matcher,
             load,
@@ -408,9 +711,11 @@ impl LanguageRegistry {
     /// Add a pre-loaded language to the registry.
     pub fn add(&self, language: Arc<Language>) {
         let mut state = self.state.write();
+        state.path_index = None;
         state.available_languages.push(AvailableLanguage {
             id: language.id,
             name: language.name(),
+            stable_id: language.stable_id(),
             grammar: language.config.grammar.clone(),
             matcher: language.config.matcher.clone(),
             load: Arc::new(|| Err(anyhow!("already loaded"))),
@@ -419,6 +724,42 @@ impl LanguageRegistry {
         state.add(language);
     }
 
+    /// Returns the sorted, deduplicated capture names of the highlights queries of
+    /// all of the loaded languages.
+    pub fn all_highlight_names(&self) -> Vec<String> {
+        let state = self.state.read();
+        let mut names = state
+            .languages
+            .iter()
+            .filter_map(|language| language.grammar()?.highlights_query.as_deref())
+            .flat_map(|query| query.capture_names().iter().map(|name| name.to_string()))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Returns the names of the theme's highlights that none of the loaded languages'
+    /// highlight captures resolve to, so that they can be removed from the theme.
+    pub fn unused_theme_scopes(&self, theme: &SyntaxTheme) -> Vec<String> {
+        let names = self.all_highlight_names();
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        let highlight_map = HighlightMap::new(&names, theme);
+        let mut used = vec![false; theme.highlights.len()];
+        for ix in 0..names.len() {
+            if let Some(used) = used.get_mut(highlight_map.get(ix as u32).0 as usize) {
+                *used = true;
+            }
+        }
+        theme
+            .highlights
+            .iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|((name, _), _)| name.clone())
+            .collect()
+    }
+
     pub fn subscribe(&self) -> watch::Receiver<()> {
         self.state.read().subscription.1.clone()
     }
@@ -429,6 +770,31 @@ impl LanguageRegistry {
         self.state.read().version
     }
 
+    /// Takes a snapshot of the enabled languages that are currently loaded, which can
+    /// look up languages without locking the registry. See [`RegistrySnapshot`] for
+    /// when the snapshot becomes outdated.
+    pub fn snapshot(&self) -> RegistrySnapshot {
+        let path_index = self.path_index();
+        let state = self.state.read();
+        let languages = state
+            .available_languages
+            .iter()
+            .filter(|language| !state.disabled_languages.contains(&language.name))
+            .filter_map(|available| {
+                state
+                    .languages
+                    .iter()
+                    .find(|language| language.id == available.id)
+                    .cloned()
+            })
+            .collect();
+        RegistrySnapshot {
+            languages,
+            path_index,
+            version: state.version,
+        }
+    }
+
     /// Returns the number of times that the registry has been reloaded.
     pub fn reload_count(&self) -> usize {
         self.state.read().reload_count
@@ -442,6 +808,76 @@ impl LanguageRegistry {
         }
     }
 
+    /// Clears the theme and the mapping of every language's highlights to it, so that
+    /// nothing is styled until [`Self::set_theme`] is called again, including in the
+    /// languages that are loaded in the meantime.
+    pub fn reset_theme(&self) {
+        let mut state = self.state.write();
+        state.theme = None;
+        for language in &state.languages {
+            language.reset_theme();
+        }
+    }
+
+    /// Sets the number of lines at the start of a file that are matched against
+    /// each language's `first_line_pattern`.
+    pub fn set_content_detection_lines(&self, lines: u32) {
+        self.state.write().content_detection_lines = lines;
+    }
+
+    /// Sets the maximum size, in bytes, of buffers that will be parsed. Larger
+    /// buffers are treated as plain text.
+    pub fn set_max_parse_bytes(&self, max_parse_bytes: Option<usize>) {
+        self.state.write().max_parse_bytes = max_parse_bytes;
+    }
+
+    pub fn max_parse_bytes(&self) -> Option<usize> {
+        self.state.read().max_parse_bytes
+    }
+
+    /// Sets the maximum size, in bytes, of injected regions that will be parsed as
+    /// their injected language. Larger regions are left with the enclosing language's
+    /// highlighting. The size of combined injections is their total size.
+    ///
+    /// The limit only applies to later parses. Buffers that are already parsed keep
+    /// their injections until the regions containing them are edited and reparsed.
+    pub fn set_max_injection_bytes(&self, max_injection_bytes: Option<usize>) {
+        self.state.write().max_injection_bytes = max_injection_bytes;
+    }
+
+    pub fn max_injection_bytes(&self) -> Option<usize> {
+        self.state.read().max_injection_bytes
+    }
+
+    /// Sets whether regions of text that fail to parse are given lexical highlighting
+    /// for their keywords, strings and comments, rather than being left unstyled.
+    pub fn set_highlight_errors_leniently(&self, enabled: bool) {
+        let mut state = self.state.write();
+        state.highlight_errors_leniently = enabled;
+        for language in &state.languages {
+            language.set_highlight_errors_leniently(enabled);
+        }
+    }
+
+    /// Sets whether the language with the given name can be chosen for files. Disabled
+    /// languages are still listed, but are skipped when detecting a file's language.
+    pub fn set_language_enabled(&self, name: &str, enabled: bool) {
+        let mut state = self.state.write();
+        let changed = if enabled {
+            state.disabled_languages.remove(name)
+        } else {
+            state.disabled_languages.insert(name.into())
+        };
+        if changed {
+            state.version += 1;
+            *state.subscription.0.borrow_mut() = ();
+        }
+    }
+
+    pub fn is_language_enabled(&self, name: &str) -> bool {
+        !self.state.read().disabled_languages.contains(name)
+    }
+
     pub fn set_language_server_download_dir(&mut self, path: impl Into<Arc<Path>>) {
         self.language_server_download_dir = Some(path.into());
     }
@@ -461,6 +897,76 @@ impl LanguageRegistry {
         async move { rx.await? }
     }
 
+    /// Loads the languages with the given names, or all of the enabled languages, ahead
+    /// of time, so that opening the first file of each language doesn't wait for its
+    /// grammar to load and its queries to compile. As each language is loaded, its
+    /// highlights are mapped to the current theme.
+    ///
+    /// The returned future resolves once all of the languages have been loaded, or have
+    /// failed to load, which is logged.
+    pub fn prewarm(self: &Arc<Self>, names: Option<&[&str]>) -> impl Future<Output = ()> {
+        let state = self.state.read();
+        let language_names = state
+            .available_languages
+            .iter()
+            .filter(|language| !state.disabled_languages.contains(&language.name))
+            .filter(|language| {
+                names.map_or(true, |names| {
+                    names
+                        .iter()
+                        .any(|name| UniCase::new(*name) == UniCase::new(&*language.name))
+                })
+            })
+            .map(|language| language.name.clone())
+            .collect::<Vec<_>>();
+        drop(state);
+
+        let loads = language_names
+            .iter()
+            .map(|name| self.language_for_name(name))
+            .collect::<Vec<_>>();
+        async move {
+            join_all(loads).await;
+        }
+    }
+
+    /// Loads the language with the given stable identifier.
+    pub fn language_for_id(
+        self: &Arc<Self>,
+        id: &str,
+    ) -> impl Future<Output = Result<Arc<Language>>> {
+        let name = self
+            .state
+            .read()
+            .available_languages
+            .iter()
+            .find(|language| language.stable_id == id)
+            .map(|language| language.name.clone());
+        let rx = self.get_or_load_language(|language_name, _| {
+            if Some(language_name) == name.as_deref() {
+                1
+            } else {
+                0
+            }
+        });
+        async move { rx.await? }
+    }
+
+    /// Returns the language that an injection with the given language name refers to, in
+    /// the same way as the syntax map resolves injections when highlighting, by either
+    /// the language's name or one of its path suffixes, such as `js` for JavaScript.
+    ///
+    /// Names that no language has fall back to [`PLAIN_TEXT`], which has no grammar, so
+    /// the syntax map leaves those injections to be resolved if such a language is added.
+    /// Returns `None` if the language isn't loaded yet, in which case it starts loading.
+    pub fn find_injection_language(self: &Arc<Self>, name: &str) -> Option<Arc<Language>> {
+        match self.language_for_name_or_extension(name).now_or_never()? {
+            Ok(language) => Some(language),
+            Err(error) if error.is::<LanguageNotFound>() => Some(PLAIN_TEXT.clone()),
+            Err(_) => None,
+        }
+    }
+
     pub fn language_for_name_or_extension(
         self: &Arc<Self>,
         string: &str,
@@ -503,70 +1009,338 @@ impl LanguageRegistry {
             .map_err(|error| error.context(format!("language for file path {}", path.display())))
     }
 
+    /// Returns the language for a file with the given name and extension, where a hidden
+    /// file's extension is its name without the leading dot, as for `.bashrc`.
+    ///
+    /// This is equivalent to [`Self::language_for_file_path`], for callers that have
+    /// already split the paths of many files.
+    pub fn language_for_file_name_parts(
+        self: &Arc<Self>,
+        file_name: Option<&str>,
+        extension: Option<&str>,
+    ) -> impl Future<Output = Result<Arc<Language>>> {
+        let detection = self.detect_language_for_parts(file_name, extension, None, None);
+        async move {
+            detection
+                .await?
+                .language
+                .ok_or_else(|| anyhow!(LanguageNotFound))
+        }
+    }
+
+    /// Detects the language of the file at the given path in the same way as
+    /// [`Self::language_for_file_path`], also returning why the language was chosen.
+    pub fn explain_detection(
+        self: &Arc<Self>,
+        path: &Path,
+        content: Option<&Rope>,
+    ) -> impl Future<Output = Result<Detection>> {
+        self.detect_language(path, content, None)
+    }
+
+    /// Detects the languages of the given files and parses them, spreading the parsing
+    /// across the background executor's threads, each of which reuses a single parser.
+    ///
+    /// Files whose language isn't detected or has no grammar, that are too large to
+    /// parse, or that aren't valid text yield `None`.
+    pub async fn parse_files(
+        self: &Arc<Self>,
+        files: &[(PathBuf, Vec<u8>)],
+    ) -> Vec<(PathBuf, Option<Tree>)> {
+        let mut texts = Vec::with_capacity(files.len());
+        let mut languages = Vec::with_capacity(files.len());
+        for (path, content) in files {
+            let text = decode_source(content)
+                .ok()
+                .map(|text| Rope::from(text.as_ref()));
+            let language = match &text {
+                Some(text) => self
+                    .detect_language(path, Some(text), None)
+                    .await
+                    .ok()
+                    .and_then(|detection| detection.language),
+                None => None,
+            };
+            texts.push(text);
+            languages.push(language);
+        }
+
+        let mut trees = vec![None; files.len()];
+        if !files.is_empty() {
+            let max_parse_bytes = self.max_parse_bytes();
+            let num_cpus = self.executor.num_cpus().min(files.len());
+            let segment_size = (files.len() + num_cpus - 1) / num_cpus;
+            let texts = &texts;
+            let languages = &languages;
+            self.executor
+                .scoped(|scope| {
+                    for (segment_ix, trees) in trees.chunks_mut(segment_size).enumerate() {
+                        scope.spawn(async move {
+                            let segment_start = segment_ix * segment_size;
+                            with_parser(|parser| {
+                                for (ix, tree) in trees.iter_mut().enumerate() {
+                                    let ix = segment_start + ix;
+                                    let (Some(text), Some(language)) = (&texts[ix], &languages[ix])
+                                    else {
+                                        continue;
+                                    };
+                                    let Some(grammar) = language.grammar() else {
+                                        continue;
+                                    };
+                                    if language.parse_outcome(text.len(), max_parse_bytes)
+                                        == ParseOutcome::Parsed
+                                    {
+                                        *tree = Some(
+                                            grammar.parse_text_with_parser(parser, text, None),
+                                        );
+                                    }
+                                }
+                            })
+                        });
+                    }
+                })
+                .await;
+        }
+
+        files
+            .iter()
+            .map(|(path, _)| path.clone())
+            .zip(trees)
+            .collect()
+    }
+
     fn language_for_file_internal(
         self: &Arc<Self>,
         path: &Path,
         content: Option<&Rope>,
         user_file_types: Option<&HashMap<Arc<str>, GlobSet>>,
     ) -> impl Future<Output = Result<Arc<Language>>> {
+        let detection = self.detect_language(path, content, user_file_types);
+        async move {
+            detection
+                .await?
+                .language
+                .ok_or_else(|| anyhow!(LanguageNotFound))
+        }
+    }
+
+    fn detect_language(
+        self: &Arc<Self>,
+        path: &Path,
+        content: Option<&Rope>,
+        user_file_types: Option<&HashMap<Arc<str>, GlobSet>>,
+    ) -> impl Future<Output = Result<Detection>> {
         let filename = path.file_name().and_then(|name| name.to_str());
         let extension = path.extension_or_hidden_file_name();
-        let path_suffixes = [extension, filename];
-        let empty = GlobSet::empty();
+        self.detect_language_for_parts(filename, extension, content, user_file_types)
+    }
 
-        let rx = self.get_or_load_language(move |language_name, config| {
-            let path_matches_default_suffix = config
-                .path_suffixes
-                .iter()
-                .any(|suffix| path_suffixes.contains(&Some(suffix.as_str())));
-            let custom_suffixes = user_file_types
-                .and_then(|types| types.get(language_name))
-                .unwrap_or(&empty);
-            let path_matches_custom_suffix = path_suffixes
-                .iter()
-                .map(|suffix| suffix.unwrap_or(""))
-                .any(|suffix| custom_suffixes.is_match(suffix));
-            let content_matches = content.zip(config.first_line_pattern.as_ref()).map_or(
-                false,
-                |(content, pattern)| {
-                    let end = content.clip_point(Point::new(0, 256), Bias::Left);
-                    let end = content.point_to_offset(end);
-                    let text = content.chunks_in_range(0..end).collect::<String>();
-                    pattern.is_match(&text)
-                },
+    fn detect_language_for_parts(
+        self: &Arc<Self>,
+        filename: Option<&str>,
+        extension: Option<&str>,
+        content: Option<&Rope>,
+        user_file_types: Option<&HashMap<Arc<str>, GlobSet>>,
+    ) -> impl Future<Output = Result<Detection>> {
+        let content_lines = content.map_or(Vec::new(), |content| {
+            leading_lines(content, self.state.read().content_detection_lines)
+        });
+        let content_start = content.map(|content| {
+            let end = content.clip_offset(content.len().min(CONTENT_PATTERN_BYTES), Bias::Left);
+            content.chunks_in_range(0..end).collect::<String>()
+        });
+
+        let modeline_mode = content_lines.iter().find_map(|line| modeline_mode(line));
+        let path_index = self.path_index();
+        let path_matches = path_index.matching_languages(extension, filename);
+        let state = self.state.read();
+        let enabled_language = |name: &str| {
+            let ix = path_index.position(&state.available_languages, name)?;
+            let language = &state.available_languages[ix];
+            if state.disabled_languages.contains(&language.name) {
+                log::trace!("skipping disabled language {name} during detection");
+                return None;
+            }
+            Some((ix, language))
+        };
+
+        // Only the languages that the user's settings, the path, the first lines or a
+        // modeline associate with the file are candidates, which are found in the path index.
+        let mut candidates = HashMap::<Arc<str>, (usize, DetectionReason)>::default();
+        for (name, custom_suffixes) in user_file_types.into_iter().flatten() {
+            let Some((_, language)) = enabled_language(name) else {
+                continue;
+            };
+            let custom_suffix = [extension, filename]
+                .into_iter()
+                .flatten()
+                .find(|suffix| custom_suffixes.is_match(suffix));
+            if let Some(suffix) = custom_suffix {
+                candidates.insert(
+                    language.name.clone(),
+                    (
+                        usize::MAX,
+                        DetectionReason::UserFileType(suffix.to_string()),
+                    ),
+                );
+            }
+        }
+
+        let first_line_matches = path_index
+            .first_line_languages
+            .iter()
+            .filter(|name| !path_matches.contains_key(*name))
+            .filter_map(|name| {
+                let (_, language) = enabled_language(name)?;
+                let pattern = language.matcher.first_line_pattern.as_ref()?;
+                let line = content_lines.iter().find(|line| pattern.is_match(line))?;
+                let reason = match shebang_interpreter(line) {
+                    Some(interpreter) => DetectionReason::Shebang(interpreter.to_string()),
+                    None => DetectionReason::FirstLine,
+                };
+                Some((name.clone(), reason))
+            })
+            .collect::<Vec<_>>();
+        for (name, mut reason) in path_matches.into_iter().chain(first_line_matches) {
+            if candidates.contains_key(&name) {
+                continue;
+            }
+            let Some((_, language)) = enabled_language(&name) else {
+                continue;
+            };
+            let base_score = path_match_score(&reason);
+
+            // Among the languages that match, those whose content patterns match are favored
+            // by the total weight of the matching patterns.
+            let mut weight = 0;
+            let mut heaviest_pattern = None::<&ContentPattern>;
+            if let Some(content_start) = &content_start {
+                for pattern in &language.matcher.content_patterns {
+                    if pattern.pattern.is_match(content_start) {
+                        weight += pattern.weight as usize;
+                        if heaviest_pattern
+                            .map_or(true, |heaviest| pattern.weight > heaviest.weight)
+                        {
+                            heaviest_pattern = Some(pattern);
+                        }
+                    }
+                }
+            }
+            if let Some(pattern) = heaviest_pattern {
+                reason = DetectionReason::ContentPattern(pattern.pattern.as_str().to_string());
+            }
+            log::trace!(
+                "language {name} is a detection candidate with score {}: {reason:?}",
+                base_score + weight
             );
-            if path_matches_custom_suffix {
-                2
-            } else if path_matches_default_suffix || content_matches {
-                1
-            } else {
-                0
+            candidates.insert(name, (base_score + weight, reason));
+        }
+
+        // A modeline only names the language of a file that doesn't match any other way.
+        if let Some(mode) = modeline_mode.filter(|_| candidates.is_empty()) {
+            for name in path_index
+                .modes
+                .get(&mode.to_ascii_lowercase())
+                .into_iter()
+                .flatten()
+            {
+                if enabled_language(name).is_some() {
+                    candidates.insert(
+                        name.clone(),
+                        (1, DetectionReason::Modeline(mode.to_string())),
+                    );
+                }
             }
+        }
+
+        // Among the languages with the best score, the one with the highest priority wins,
+        // or the last registered one if their priorities are equal.
+        let language = candidates
+            .iter()
+            .filter_map(|(name, (score, _))| {
+                let (ix, language) = enabled_language(name)?;
+                Some(((*score, language.matcher.priority, ix), language))
+            })
+            .max_by_key(|(key, _)| *key)
+            .map(|(_, language)| language.clone());
+        drop(state);
+
+        // The language may have been removed since the candidates were found.
+        let mut state = self.state.write();
+        let language = language.filter(|language| {
+            path_index
+                .position(&state.available_languages, &language.name)
+                .map_or(false, |ix| state.available_languages[ix].id == language.id)
         });
-        async move { rx.await? }
+        let rx = self.load_available_language(&mut state, language);
+        drop(state);
+        async move {
+            match rx.await? {
+                Ok(language) => {
+                    let reason = candidates
+                        .remove(&language.name())
+                        .map_or(DetectionReason::NoMatch, |(_, reason)| reason);
+                    log::trace!("detected language {}: {reason:?}", language.name());
+                    Ok(Detection {
+                        language: Some(language),
+                        reason,
+                    })
+                }
+                Err(error) if error.is::<LanguageNotFound>() => {
+                    log::trace!("no language detected");
+                    Ok(Detection {
+                        language: None,
+                        reason: DetectionReason::NoMatch,
+                    })
+                }
+                Err(error) => Err(error),
+            }
+        }
+    }
+
+    fn path_index(&self) -> Arc<PathIndex> {
+        if let Some(path_index) = self.state.read().path_index.clone() {
+            return path_index;
+        }
+
+        // The index may have been built by another caller since the read lock was released.
+        let state = &mut *self.state.write();
+        state
+            .path_index
+            .get_or_insert_with(|| Arc::new(PathIndex::new(&state.available_languages)))
+            .clone()
     }
 
     fn get_or_load_language(
         self: &Arc<Self>,
         callback: impl Fn(&str, &LanguageMatcher) -> usize,
     ) -> oneshot::Receiver<Result<Arc<Language>>> {
-        let (tx, rx) = oneshot::channel();
-
         let mut state = self.state.write();
-        let Some((language, _)) = state
+        let language = state
             .available_languages
             .iter()
             .filter_map(|language| {
                 let score = callback(&language.name, &language.matcher);
                 if score > 0 {
-                    Some((language.clone(), score))
+                    Some((language, score))
                 } else {
                     None
                 }
             })
-            .max_by_key(|e| e.1)
-            .clone()
-        else {
+            .max_by_key(|(language, score)| (*score, language.matcher.priority))
+            .map(|(language, _)| language.clone());
+        self.load_available_language(&mut state, language)
+    }
+
+    /// Loads the given language, unless it's already loaded or being loaded, failing with
+    /// [`LanguageNotFound`] if there's no language.
+    fn load_available_language(
+        self: &Arc<Self>,
+        state: &mut LanguageRegistryState,
+        language: Option<AvailableLanguage>,
+    ) -> oneshot::Receiver<Result<Arc<Language>>> {
+        let (tx, rx) = oneshot::channel();
+        let Some(language) = language else {
             let _ = tx.send(Err(anyhow!(LanguageNotFound)));
             return rx;
         };
@@ -592,10 +1366,22 @@ impl LanguageRegistry {
                         let id = language.id;
                         let name = language.name.clone();
                         let language = async {
-                            let (config, queries, provider) = (language.load)()?;
+                            let (config, mut queries, provider) = (language.load)()?;
+                            let query_names = QUERY_FILENAME_PREFIXES
+                                .iter()
+                                .filter(|(_, query)| query(&mut queries).is_some())
+                                .map(|(name, _)| *name)
+                                .collect::<Vec<_>>();
+                            log::debug!(
+                                "loading language {name} with queries: {}",
+                                query_names.join(", ")
+                            );
 
                             if let Some(grammar) = config.grammar.clone() {
-                                let grammar = Some(this.get_or_load_grammar(grammar).await?);
+                                let grammar = Some(
+                                    this.get_or_load_grammar(grammar, config.grammar_name.clone())
+                                        .await?,
+                                );
                                 Language::new_with_id(id, config, grammar)
                                     .with_context_provider(provider)
                                     .with_queries(queries)
@@ -608,6 +1394,7 @@ impl LanguageRegistry {
 
                         match language {
                             Ok(language) => {
+                                log::debug!("loaded language {name}");
                                 let language = Arc::new(language);
                                 let mut state = this.state.write();
 
@@ -646,13 +1433,30 @@ impl LanguageRegistry {
     fn get_or_load_grammar(
         self: &Arc<Self>,
         name: Arc<str>,
+        language_name: Option<String>,
     ) -> impl Future<Output = Result<tree_sitter::Language>> {
         let (tx, rx) = oneshot::channel();
         let mut state = self.state.write();
+        let state = &mut *state;
+
+        // Native grammars contain a single language, so `language_name` only applies
+        // to WASM grammars.
+        let wasm_path = language_name
+            .as_ref()
+            .and_then(|_| state.grammars.get(name.as_ref())?.wasm_path().cloned());
+        let grammar = match (&language_name, wasm_path) {
+            (Some(language_name), Some(wasm_path)) => Some(
+                state
+                    .grammar_languages
+                    .entry((name.clone(), language_name.clone()))
+                    .or_insert(AvailableGrammar::Unloaded(wasm_path)),
+            ),
+            _ => state.grammars.get_mut(name.as_ref()),
+        };
 
-        if let Some(grammar) = state.grammars.get_mut(name.as_ref()) {
+        if let Some(grammar) = grammar {
             match grammar {
-                AvailableGrammar::LoadFailed(error) => {
+                AvailableGrammar::LoadFailed(_, error) => {
                     tx.send(Err(error.clone())).ok();
                 }
                 AvailableGrammar::Native(grammar) | AvailableGrammar::Loaded(_, grammar) => {
@@ -669,10 +1473,13 @@ impl LanguageRegistry {
                         .spawn(async move {
                             let grammar_result = maybe!({
                                 let wasm_bytes = std::fs::read(&wasm_path)?;
-                                let grammar_name = wasm_path
-                                    .file_stem()
-                                    .and_then(OsStr::to_str)
-                                    .ok_or_else(|| anyhow!("invalid grammar filename"))?;
+                                let grammar_name = match language_name.as_deref() {
+                                    Some(language_name) => language_name,
+                                    None => wasm_path
+                                        .file_stem()
+                                        .and_then(OsStr::to_str)
+                                        .ok_or_else(|| anyhow!("invalid grammar filename"))?,
+                                };
                                 anyhow::Ok(with_parser(|parser| {
                                     let mut store = parser.take_wasm_store().unwrap();
                                     let grammar = store.load_language(&grammar_name, &wasm_bytes);
@@ -683,11 +1490,24 @@ impl LanguageRegistry {
                             .map_err(Arc::new);
 
                             let value = match &grammar_result {
-                                Ok(grammar) => AvailableGrammar::Loaded(wasm_path, grammar.clone()),
-                                Err(error) => AvailableGrammar::LoadFailed(error.clone()),
+                                Ok(grammar) => {
+                                    log::debug!("loaded grammar {name}");
+                                    AvailableGrammar::Loaded(wasm_path, grammar.clone())
+                                }
+                                Err(error) => {
+                                    log::warn!("failed to load grammar {name}: {error}");
+                                    AvailableGrammar::LoadFailed(wasm_path, error.clone())
+                                }
                             };
 
-                            let old_value = this.state.write().grammars.insert(name, value);
+                            let mut state = this.state.write();
+                            let old_value = match language_name {
+                                Some(language_name) => {
+                                    state.grammar_languages.insert((name, language_name), value)
+                                }
+                                None => state.grammars.insert(name, value),
+                            };
+                            drop(state);
                             if let Some(AvailableGrammar::Loading(_, txs)) = old_value {
                                 for tx in txs {
                                     tx.send(grammar_result.clone()).ok();
@@ -886,6 +1706,67 @@ impl LanguageRegistry {
     }
 }
 
+/// Returns the interpreter run by the given line if it's a shebang, such as `python3`
+/// for `#!/usr/bin/env python3`.
+fn shebang_interpreter(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        words.find(|word| !word.starts_with('-') && !word.contains('='))
+    } else {
+        Some(program)
+    }
+}
+
+/// Returns the mode named by the given line if it's an Emacs or Vim modeline, such as
+/// `python` for `# -*- mode: python -*-` or `# vim: set ft=python:`.
+fn modeline_mode(line: &str) -> Option<&str> {
+    let mode = if let Some((_, rest)) = line.split_once("-*-") {
+        let (variables, _) = rest.split_once("-*-")?;
+        let variables = variables.trim();
+        if variables.contains(':') {
+            variables.split(';').find_map(|variable| {
+                let (name, value) = variable.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("mode")
+                    .then(|| value.trim())
+            })?
+        } else {
+            variables
+        }
+    } else {
+        let options = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+            let ix = line.find(marker)?;
+            let is_word_start = line[..ix]
+                .chars()
+                .next_back()
+                .map_or(true, char::is_whitespace);
+            is_word_start.then(|| &line[ix + marker.len()..])
+        })?;
+        options
+            .split(|c: char| c.is_whitespace() || c == ':')
+            .find_map(|option| {
+                let (name, value) = option.split_once('=')?;
+                matches!(name, "ft" | "filetype" | "syn" | "syntax").then_some(value)
+            })?
+    };
+    Some(mode).filter(|mode| !mode.is_empty())
+}
+
+/// Returns up to `count` lines from the start of the given text, each truncated
+/// to a bounded length.
+fn leading_lines(content: &Rope, count: u32) -> Vec<String> {
+    let line_count = count.min(content.max_point().row + 1);
+    (0..line_count)
+        .map(|row| {
+            let start = content.point_to_offset(Point::new(row, 0));
+            let end = content.clip_point(Point::new(row, 256), Bias::Left);
+            let end = content.point_to_offset(end);
+            content.chunks_in_range(start..end).collect()
+        })
+        .collect()
+}
+
 impl LanguageRegistryState {
     fn next_language_server_id(&mut self) -> LanguageServerId {
         LanguageServerId(post_inc(&mut self.next_language_server_id))
@@ -895,6 +1776,7 @@ impl LanguageRegistryState {
         if let Some(theme) = self.theme.as_ref() {
             language.set_theme(theme.syntax());
         }
+        language.set_highlight_errors_leniently(self.highlight_errors_leniently);
         self.language_settings.languages.insert(
             language.name(),
             LanguageSettingsContent {
@@ -933,8 +1815,11 @@ impl LanguageRegistryState {
             .retain(|language| !languages_to_remove.contains(&language.name()));
         self.available_languages
             .retain(|language| !languages_to_remove.contains(&language.name));
+        self.path_index = None;
         self.grammars
             .retain(|name, _| !grammars_to_remove.contains(&name));
+        self.grammar_languages
+            .retain(|(name, _), _| !grammars_to_remove.contains(name));
         self.version += 1;
         self.reload_count += 1;
         *self.subscription.0.borrow_mut() = ();
@@ -0,0 +1,482 @@
+use crate::syntax_map::{QueryCursorHandle, TextProvider};
+use collections::HashMap;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    ops::Range,
+    sync::{Arc, OnceLock},
+};
+use text::Rope;
+use tree_sitter::{InputEdit, Node, Query, QueryMatch};
+
+/// The name of the query property that highlight patterns use to test whether
+/// a node is a local variable, as in `(#is? local)` or `(#is-not? local)`.
+const LOCAL_PROPERTY: &str = "local";
+
+pub(crate) struct LocalsConfig {
+    pub(crate) query: Query,
+    pub(crate) scope_capture_ix: Option<u32>,
+    pub(crate) definition_capture_ix: Option<u32>,
+}
+
+/// The local definitions found in a syntax tree by a language's locals query.
+///
+/// Ranges are relative to the start of the tree's layer, as the tree's own are, so
+/// that they stay valid when the layer moves within its buffer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Locals {
+    /// The definitions with each name, ordered by their start.
+    definitions: HashMap<String, Vec<LocalDefinition>>,
+}
+
+#[derive(Clone, Debug)]
+struct LocalDefinition {
+    range: Range<usize>,
+    /// The range of the innermost scope containing the definition, or `None` if it's
+    /// the whole tree.
+    scope: Option<Range<usize>>,
+}
+
+/// The local definitions of a syntax layer's tree, which are found when they're first
+/// needed by updating those of an earlier version of the tree.
+#[derive(Debug, Default)]
+pub(crate) struct LayerLocals {
+    locals: OnceLock<Arc<Locals>>,
+    /// The definitions of the last parsed version of the tree whose definitions were
+    /// found, and the edits that have been made to it since.
+    base: Option<(Arc<Locals>, Vec<LocalsEdit>)>,
+    /// Whether the tree has been edited since it was parsed. The definitions of edited
+    /// trees aren't used as a base, as their syntax around the edits is outdated.
+    is_edited: bool,
+}
+
+/// An edit that replaced the bytes in `start..old_end` of a tree with those in
+/// `start..new_end`, or that marks them as changed if the two are the same.
+#[derive(Clone, Copy, Debug)]
+struct LocalsEdit {
+    start: usize,
+    old_end: usize,
+    new_end: usize,
+}
+
+impl LocalsConfig {
+    /// Returns whether any of the patterns in the given query have a `local` property predicate.
+    pub(crate) fn is_used_by(query: &Query) -> bool {
+        (0..query.pattern_count()).any(|pattern_ix| {
+            query
+                .property_predicates(pattern_ix)
+                .iter()
+                .any(|(property, _)| property.key.as_ref() == LOCAL_PROPERTY)
+        })
+    }
+}
+
+impl LayerLocals {
+    /// Returns the locals of the tree after the given edit was applied to it.
+    pub(crate) fn edit(&self, edit: &InputEdit) -> Self {
+        let edit = LocalsEdit {
+            start: edit.start_byte,
+            old_end: edit.old_end_byte,
+            new_end: edit.new_end_byte,
+        };
+        Self {
+            is_edited: true,
+            ..self.with_edits([edit])
+        }
+    }
+
+    /// Returns the locals of the tree after it was reparsed, which changed its syntax
+    /// in the given ranges.
+    pub(crate) fn reparse(&self, changed_ranges: impl IntoIterator<Item = Range<usize>>) -> Self {
+        self.with_edits(changed_ranges.into_iter().map(|range| LocalsEdit {
+            start: range.start,
+            old_end: range.end,
+            new_end: range.end,
+        }))
+    }
+
+    fn with_edits(&self, new_edits: impl IntoIterator<Item = LocalsEdit>) -> Self {
+        let base = if let Some(locals) = self.locals.get().filter(|_| !self.is_edited) {
+            Some((locals.clone(), new_edits.into_iter().collect()))
+        } else {
+            self.base.as_ref().map(|(locals, edits)| {
+                let edits = edits.iter().copied().chain(new_edits).collect();
+                (locals.clone(), edits)
+            })
+        };
+        Self {
+            locals: OnceLock::new(),
+            base,
+            is_edited: false,
+        }
+    }
+
+    /// Returns the local definitions within the given node, the root of the tree, where
+    /// `offset` is the start of the tree's layer.
+    pub(crate) fn get(
+        &self,
+        config: &LocalsConfig,
+        node: Node,
+        text: &Rope,
+        offset: usize,
+    ) -> &Locals {
+        self.locals.get_or_init(|| {
+            Arc::new(match &self.base {
+                Some((locals, edits)) => {
+                    let mut locals = Locals::clone(locals);
+                    locals.update(edits, config, node, text, offset);
+                    locals
+                }
+                None => Locals::new(config, node, text, offset),
+            })
+        })
+    }
+}
+
+impl Locals {
+    /// Finds the local definitions within the given node, where `offset` is the start
+    /// of the node's layer.
+    pub(crate) fn new(config: &LocalsConfig, node: Node, text: &Rope, offset: usize) -> Self {
+        let mut locals = Self::default();
+        for (name, definition) in Self::find(config, node, text, offset, None).0 {
+            locals.definitions.entry(name).or_default().push(definition);
+        }
+        locals
+    }
+
+    /// Updates the definitions of an earlier version of the tree after the given edits
+    /// by searching the parts of the tree around the edits again. Scopes whose bounds
+    /// were edited, or that start or end around the edits, are searched as a whole, as
+    /// the definitions within them may have been in other scopes.
+    fn update(
+        &mut self,
+        edits: &[LocalsEdit],
+        config: &LocalsConfig,
+        node: Node,
+        text: &Rope,
+        offset: usize,
+    ) {
+        let mut changed_ranges = Vec::<Range<usize>>::new();
+        for edit in edits {
+            for range in &mut changed_ranges {
+                *range = edit.adjust(range);
+            }
+            changed_ranges.push(edit.start..edit.new_end);
+            for definitions in self.definitions.values_mut() {
+                definitions.retain_mut(|definition| {
+                    if let Some(scope) = &mut definition.scope {
+                        if edit.touches_bounds(scope) {
+                            changed_ranges.push(edit.adjust(scope));
+                            return false;
+                        }
+                        *scope = edit.adjust(scope);
+                    }
+                    if edit.touches(&definition.range) {
+                        return false;
+                    }
+                    definition.range = edit.adjust(&definition.range);
+                    true
+                });
+            }
+        }
+
+        changed_ranges.sort_unstable_by_key(|range| range.start);
+        let mut pending_ranges = Vec::<Range<usize>>::new();
+        for range in changed_ranges {
+            match pending_ranges.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => pending_ranges.push(range),
+            }
+        }
+
+        // The definitions touching each range are replaced by searching the range and the
+        // bytes on either side of it, which also finds the scopes containing them.
+        let mut searched_ranges = Vec::<Range<usize>>::new();
+        while let Some(range) = pending_ranges.pop() {
+            let range = Self::affected_range(node, offset, range);
+            if searched_ranges
+                .iter()
+                .any(|searched| searched.start <= range.start && range.end <= searched.end)
+            {
+                continue;
+            }
+            let query_range = offset + range.start.saturating_sub(1)..offset + range.end + 1;
+            let (definitions, scopes) = Self::find(config, node, text, offset, Some(query_range));
+            let edit = LocalsEdit {
+                start: range.start,
+                old_end: range.end,
+                new_end: range.end,
+            };
+            pending_ranges.extend(scopes.into_iter().filter(|scope| {
+                edit.touches_bounds(scope) && (scope.start < range.start || range.end < scope.end)
+            }));
+            searched_ranges.push(range.clone());
+
+            for definitions in self.definitions.values_mut() {
+                definitions.retain(|definition| !edit.touches(&definition.range));
+            }
+            for (name, definition) in definitions {
+                if !edit.touches(&definition.range) {
+                    continue;
+                }
+                let definitions = self.definitions.entry(name).or_default();
+                let ix = definitions.binary_search_by_key(&definition.range.start, |definition| {
+                    definition.range.start
+                });
+                if let Err(ix) = ix {
+                    definitions.insert(ix, definition);
+                }
+            }
+        }
+        self.definitions
+            .retain(|_, definitions| !definitions.is_empty());
+    }
+
+    /// Returns the range whose definitions may have changed when the given range did. As
+    /// whether a node is a definition depends on its parent, this is the smallest node
+    /// containing the range, or its closest ancestor that isn't a leaf or an error, but
+    /// only the children of the root that touch the range when that's the root.
+    fn affected_range(root: Node, offset: usize, range: Range<usize>) -> Range<usize> {
+        let Some(mut node) =
+            root.descendant_for_byte_range(offset + range.start, offset + range.end)
+        else {
+            return range;
+        };
+        while node.child_count() == 0 || node.is_error() {
+            let Some(parent) = node.parent() else { break };
+            node = parent;
+        }
+        let node_range = if node.id() == root.id() {
+            let mut cursor = root.walk();
+            let children = root
+                .children(&mut cursor)
+                .map(|child| child.byte_range())
+                .filter(|child| {
+                    child.start <= offset + range.end && offset + range.start <= child.end
+                })
+                .collect::<Vec<_>>();
+            match (children.first(), children.last()) {
+                (Some(first), Some(last)) => first.start..last.end,
+                _ => return range,
+            }
+        } else {
+            node.byte_range()
+        };
+        range.start.min(node_range.start - offset)..range.end.max(node_range.end - offset)
+    }
+
+    /// Returns the definitions within the given node, or within the part of it in the
+    /// given range, along with their names, ordered by their start, and the ranges of
+    /// the scopes intersecting it.
+    fn find(
+        config: &LocalsConfig,
+        node: Node,
+        text: &Rope,
+        offset: usize,
+        range: Option<Range<usize>>,
+    ) -> (Vec<(String, LocalDefinition)>, Vec<Range<usize>>) {
+        let mut scopes = Vec::new();
+        let mut definition_ranges = Vec::new();
+        let mut cursor = QueryCursorHandle::new();
+        if let Some(range) = range {
+            cursor.set_byte_range(range);
+        }
+        for mat in cursor.matches(&config.query, node, TextProvider(text)) {
+            for capture in mat.captures {
+                if Some(capture.index) == config.scope_capture_ix {
+                    scopes.push(capture.node.byte_range());
+                } else if Some(capture.index) == config.definition_capture_ix
+                    && !capture.node.is_missing()
+                {
+                    definition_ranges.push(capture.node.byte_range());
+                }
+            }
+        }
+        drop(cursor);
+
+        scopes.sort_unstable_by_key(|scope| (scope.start, Reverse(scope.end)));
+        definition_ranges.sort_unstable_by_key(|range| range.start);
+        definition_ranges.dedup();
+
+        // Scopes nest, so the innermost scope containing each definition is found by
+        // walking the scopes and definitions in order, keeping a stack of the scopes
+        // that may contain the current definition. When searching a range, the scopes
+        // containing it are matched too, as they intersect it.
+        let relative = |range: &Range<usize>| range.start - offset..range.end - offset;
+        let scopes = scopes.iter().map(relative).collect::<Vec<_>>();
+        let mut scope_iter = scopes.iter().peekable();
+        let mut scope_stack = Vec::<&Range<usize>>::new();
+        let definitions = definition_ranges
+            .into_iter()
+            .map(|absolute_range| {
+                let range = relative(&absolute_range);
+                while let Some(scope) = scope_iter.next_if(|scope| scope.start <= range.start) {
+                    scope_stack.push(scope);
+                }
+                while scope_stack
+                    .last()
+                    .map_or(false, |scope| scope.end < range.end)
+                {
+                    scope_stack.pop();
+                }
+                let definition = LocalDefinition {
+                    scope: scope_stack.last().map(|&scope| scope.clone()),
+                    range,
+                };
+                (text.chunks_in_range(absolute_range).collect(), definition)
+            })
+            .collect();
+        (definitions, scopes)
+    }
+
+    /// Returns whether the given capture of a highlights query satisfies the `local`
+    /// property predicates of the pattern that produced it, where `offset` is the
+    /// current start of the tree's layer.
+    pub(crate) fn satisfies_predicates(
+        &self,
+        query: &Query,
+        mat: &QueryMatch,
+        capture_ix: usize,
+        text: &Rope,
+        offset: usize,
+    ) -> bool {
+        query
+            .property_predicates(mat.pattern_index)
+            .iter()
+            .filter(|(property, _)| property.key.as_ref() == LOCAL_PROPERTY)
+            .all(|(property, is_positive)| {
+                let node = match property.capture_id {
+                    Some(capture_id) => mat
+                        .captures
+                        .iter()
+                        .find(|capture| capture.index as usize == capture_id)
+                        .map(|capture| capture.node),
+                    None => Some(mat.captures[capture_ix].node),
+                };
+                node.map_or(false, |node| {
+                    self.is_local(node, text, offset) == *is_positive
+                })
+            })
+    }
+
+    /// Returns whether the given node is a local definition, or a reference to a
+    /// definition that precedes it in an enclosing scope.
+    fn is_local(&self, node: Node, text: &Rope, offset: usize) -> bool {
+        let absolute_range = node.byte_range();
+        let range = absolute_range.start - offset..absolute_range.end - offset;
+        let mut chunks = text.chunks_in_range(absolute_range);
+        let name = match (chunks.next(), chunks.next()) {
+            (Some(chunk), None) => Cow::Borrowed(chunk),
+            (first, second) => Cow::Owned(first.into_iter().chain(second).chain(chunks).collect()),
+        };
+        let Some(definitions) = self.definitions.get(name.as_ref()) else {
+            return false;
+        };
+        definitions
+            .iter()
+            .take_while(|definition| definition.range.start <= range.start)
+            .any(|definition| {
+                definition.range == range
+                    || (definition.range.end <= range.start
+                        && definition.scope.as_ref().map_or(true, |scope| {
+                            scope.start <= range.start && range.end <= scope.end
+                        }))
+            })
+    }
+}
+
+impl LocalsEdit {
+    /// Returns whether the given range intersects the edited bytes or is adjacent to them.
+    fn touches(&self, range: &Range<usize>) -> bool {
+        range.start <= self.old_end && self.start <= range.end
+    }
+
+    /// Returns whether either end of the given range is within the edited bytes or
+    /// adjacent to them.
+    fn touches_bounds(&self, range: &Range<usize>) -> bool {
+        self.touches(&(range.start..range.start)) || self.touches(&(range.end..range.end))
+    }
+
+    /// Returns the given range after the edit, for ranges that don't touch the edit or
+    /// that contain it.
+    fn adjust(&self, range: &Range<usize>) -> Range<usize> {
+        let adjust = |position: usize| {
+            if position <= self.start {
+                position
+            } else if position >= self.old_end {
+                position - self.old_end + self.new_end
+            } else {
+                self.new_end
+            }
+        };
+        adjust(range.start)..adjust(range.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Language, LanguageConfig};
+    use std::sync::Arc;
+    use text::Rope;
+    use theme::SyntaxTheme;
+
+    #[test]
+    fn test_local_predicates() {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(
+                r#"
+                ((identifier) @variable.definition (#is? local))
+                ((identifier) @variable (#is-not? local))
+                "#,
+            )
+            .unwrap()
+            .with_locals_query(
+                r#"
+                (function_item) @local.scope
+                (block) @local.scope
+                (parameter pattern: (identifier) @local.definition)
+                (let_declaration pattern: (identifier) @local.definition)
+                "#,
+            )
+            .unwrap(),
+        );
+        let theme = SyntaxTheme {
+            highlights: vec![
+                ("variable".into(), gpui::red().into()),
+                ("variable.definition".into(), gpui::blue().into()),
+            ],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        let text = Rope::from("fn f(a: u32) { let b = a; { let c = b; } c + d }");
+        let highlights = language
+            .highlight_text(&text, 0..text.len())
+            .into_iter()
+            .map(|(range, highlight_id)| {
+                (
+                    text.chunks_in_range(range).collect::<String>(),
+                    highlight_id.name(&theme).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            highlights,
+            &[
+                ("f".to_string(), "variable"),
+                ("a".to_string(), "variable.definition"),
+                ("b".to_string(), "variable.definition"),
+                ("a".to_string(), "variable.definition"),
+                ("c".to_string(), "variable.definition"),
+                ("b".to_string(), "variable.definition"),
+                // The inner block's `c` is out of scope after the block.
+                ("c".to_string(), "variable"),
+                ("d".to_string(), "variable"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use crate::Language;
+use gpui::HighlightStyle;
+use std::ops::Range;
+use theme::SyntaxTheme;
+use tree_sitter::Tree;
+
+impl Language {
+    /// Highlights the given tree by styling its nodes according to their kinds, using
+    /// the theme's node kind styles, for languages that don't have a highlights query.
+    ///
+    /// Returns `None` if the language has a highlights query. Where styled nodes are
+    /// nested, the innermost node's style applies, and the enclosing node's style is
+    /// split around it.
+    pub fn node_kind_highlights(
+        &self,
+        tree: &Tree,
+        theme: &SyntaxTheme,
+    ) -> Option<Vec<(Range<usize>, HighlightStyle)>> {
+        if self
+            .grammar
+            .as_ref()
+            .map_or(false, |grammar| grammar.highlights_query.is_some())
+        {
+            return None;
+        }
+
+        let mut result = Vec::new();
+        // The styled nodes enclosing the current node, and the offset up to which
+        // their styles have been emitted.
+        let mut stack = Vec::<(Range<usize>, HighlightStyle)>::new();
+        let mut offset = 0;
+        let mut emit = |range: Range<usize>, style: HighlightStyle| {
+            if !range.is_empty() {
+                result.push((range, style));
+            }
+        };
+        for node in self.walk(tree) {
+            let Some(style) = theme.node_kind_style(node.kind) else {
+                continue;
+            };
+            while let Some((range, style)) = stack.pop() {
+                if range.end > node.range.start {
+                    stack.push((range, style));
+                    break;
+                }
+                emit(offset.max(range.start)..range.end, style);
+                offset = range.end;
+            }
+            if let Some((range, style)) = stack.last() {
+                emit(offset.max(range.start)..node.range.start, *style);
+            }
+            offset = offset.max(node.range.start);
+            stack.push((node.range, style));
+        }
+        while let Some((range, style)) = stack.pop() {
+            emit(offset.max(range.start)..range.end, style);
+            offset = range.end;
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+    use tree_sitter::Parser;
+
+    #[test]
+    fn test_node_kind_highlights() {
+        let theme = SyntaxTheme {
+            node_kind_styles: vec![
+                ("line_comment".into(), gpui::red().into()),
+                ("function_item".into(), gpui::blue().into()),
+            ],
+            ..Default::default()
+        };
+        let comment = theme.node_kind_styles[0].1;
+        let function = theme.node_kind_styles[1].1;
+
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        );
+        let source = "// a\nfn b() { // c\n}";
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        assert_eq!(
+            language.node_kind_highlights(&tree, &theme).unwrap(),
+            &[
+                (0..4, comment),
+                (5..14, function),
+                (14..18, comment),
+                (18..20, function)
+            ]
+        );
+
+        let language = language
+            .with_highlights_query("(line_comment) @comment")
+            .unwrap();
+        assert_eq!(language.node_kind_highlights(&tree, &theme), None);
+    }
+}
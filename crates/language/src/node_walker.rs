@@ -0,0 +1,332 @@
+use crate::{
+    syntax_map::{QueryCursorHandle, ToTreeSitterPoint},
+    Language,
+};
+use std::{fmt::Write as _, ops::Range};
+use text::Point;
+use tree_sitter::{Node, Tree, TreeCursor};
+
+/// A node visited by a [`NodeWalker`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub kind: &'static str,
+    pub range: Range<usize>,
+    pub named: bool,
+    /// The depth of the node, where the root node has a depth of zero.
+    pub depth: usize,
+    /// The row and column where the node starts.
+    pub start_point: Point,
+    /// The row and column where the node ends.
+    pub end_point: Point,
+}
+
+/// A kind of node in a language's grammar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeKind {
+    pub id: u16,
+    pub name: &'static str,
+    /// Whether nodes of this kind are named, as opposed to being anonymous tokens.
+    pub named: bool,
+}
+
+/// The maximum number of characters of source text shown for each leaf node by
+/// [`Language::debug_tree`].
+const DEBUG_TREE_SNIPPET_LEN: usize = 40;
+
+/// An iterator over the nodes of a syntax tree, in pre-order.
+pub struct NodeWalker<'a> {
+    cursor: TreeCursor<'a>,
+    depth: usize,
+    done: bool,
+}
+
+impl Language {
+    /// Returns an iterator over all of the nodes in the given tree, in pre-order.
+    pub fn walk<'a>(&self, tree: &'a Tree) -> NodeWalker<'a> {
+        NodeWalker::new(tree.walk())
+    }
+
+    /// Returns an indented S-expression of the named nodes in the given tree, with their
+    /// field names, byte ranges, and the source text of the nodes without named children,
+    /// like the output of `tree-sitter parse`.
+    ///
+    /// Nodes deeper than `max_depth` are omitted, and shown as `...` in their parents.
+    pub fn debug_tree(&self, tree: &Tree, source: &str, max_depth: usize) -> String {
+        let mut result = String::new();
+        let mut cursor = tree.walk();
+        // Whether each node from the root to the cursor's node was printed.
+        let mut printed = Vec::new();
+        let mut depth = 0;
+        loop {
+            let node = cursor.node();
+            let print = node.is_named();
+            if print {
+                if !result.is_empty() {
+                    result.push('\n');
+                }
+                result.extend(std::iter::repeat(' ').take(depth * 2));
+                if let Some(field_name) = cursor.field_name() {
+                    write!(result, "{field_name}: ").unwrap();
+                }
+                let range = node.byte_range();
+                write!(result, "({} [{}..{}]", node.kind(), range.start, range.end).unwrap();
+                if node.named_child_count() == 0 {
+                    let text = source.get(range).unwrap_or_default();
+                    let mut snippet = text
+                        .chars()
+                        .take(DEBUG_TREE_SNIPPET_LEN)
+                        .collect::<String>();
+                    if snippet.len() < text.len() {
+                        snippet.push('…');
+                    }
+                    write!(result, " {snippet:?}").unwrap();
+                } else if depth == max_depth {
+                    result.push_str(" ...");
+                }
+            }
+
+            let descend = print && depth < max_depth;
+            printed.push(print);
+            if print {
+                depth += 1;
+            }
+            if descend && cursor.goto_first_child() {
+                continue;
+            }
+
+            // Close the nodes that have been finished, until reaching one with a next sibling.
+            loop {
+                if printed.pop() == Some(true) {
+                    depth -= 1;
+                    result.push(')');
+                }
+                if cursor.goto_next_sibling() {
+                    break;
+                }
+                if !cursor.goto_parent() {
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Returns all of the kinds of nodes in this language's grammar, ordered by id.
+    pub fn node_kinds(&self) -> Vec<NodeKind> {
+        let Some(grammar) = self.grammar.as_ref() else {
+            return Vec::new();
+        };
+        let ts_language = &grammar.ts_language;
+        (0..ts_language.node_kind_count() as u16)
+            .filter_map(|id| {
+                Some(NodeKind {
+                    id,
+                    name: ts_language.node_kind_for_id(id)?,
+                    named: ts_language.node_kind_is_named(id),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns whether the given node's kind is a subtype of the given supertype of
+    /// this language's grammar, such as `_expression`, so that coarse rules can apply
+    /// to all of the supertype's subtypes. See [`crate::Grammar::has_supertype`].
+    pub fn is_supertype_instance(&self, node: Node, supertype: &str) -> bool {
+        let Some(query) = self
+            .grammar
+            .as_ref()
+            .and_then(|grammar| grammar.supertype_query(supertype))
+        else {
+            return false;
+        };
+        let mut cursor = QueryCursorHandle::new();
+        cursor.set_byte_range(node.byte_range());
+        let result = cursor
+            .captures(&query, node, b"".as_slice())
+            .map(|(mat, capture_ix)| mat.captures[capture_ix].node)
+            .take_while(|captured_node| captured_node.start_byte() == node.start_byte())
+            .any(|captured_node| captured_node == node);
+        result
+    }
+}
+
+impl<'a> NodeWalker<'a> {
+    /// Creates a walker over the node that the given cursor is pointing to and
+    /// all of its descendants.
+    pub fn new(cursor: TreeCursor<'a>) -> Self {
+        Self {
+            cursor,
+            depth: 0,
+            done: false,
+        }
+    }
+
+    /// Only yields the nodes whose kind is one of the given kinds.
+    pub fn filter_kind<'b>(self, kinds: &'b [&'b str]) -> impl Iterator<Item = NodeInfo> + 'b
+    where
+        'a: 'b,
+    {
+        self.filter(move |node| kinds.contains(&node.kind))
+    }
+}
+
+impl<'a> Iterator for NodeWalker<'a> {
+    type Item = NodeInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let node = self.cursor.node();
+        let info = NodeInfo {
+            kind: node.kind(),
+            range: node.byte_range(),
+            named: node.is_named(),
+            depth: self.depth,
+            start_point: Point::from_ts_point(node.start_position()),
+            end_point: Point::from_ts_point(node.end_position()),
+        };
+
+        if self.cursor.goto_first_child() {
+            self.depth += 1;
+        } else {
+            while !self.cursor.goto_next_sibling() {
+                if self.depth == 0 || !self.cursor.goto_parent() {
+                    self.done = true;
+                    break;
+                }
+                self.depth -= 1;
+            }
+        }
+
+        Some(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+    use indoc::indoc;
+
+    #[test]
+    fn test_walk() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        );
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse("fn a() {}", None).unwrap();
+
+        assert_eq!(
+            language
+                .walk(&tree)
+                .filter(|node| node.named)
+                .map(|node| (node.kind, node.depth))
+                .collect::<Vec<_>>(),
+            &[
+                ("source_file", 0),
+                ("function_item", 1),
+                ("identifier", 2),
+                ("parameters", 2),
+                ("block", 2),
+            ]
+        );
+        assert_eq!(
+            language
+                .walk(&tree)
+                .filter_kind(&["identifier", "block"])
+                .collect::<Vec<_>>(),
+            &[
+                NodeInfo {
+                    kind: "identifier",
+                    range: 3..4,
+                    named: true,
+                    depth: 2,
+                    start_point: Point::new(0, 3),
+                    end_point: Point::new(0, 4),
+                },
+                NodeInfo {
+                    kind: "block",
+                    range: 7..9,
+                    named: true,
+                    depth: 2,
+                    start_point: Point::new(0, 7),
+                    end_point: Point::new(0, 9),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_node_kinds() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        );
+        let node_kinds = language.node_kinds();
+        let kind = |name| node_kinds.iter().find(|kind| kind.name == name);
+
+        assert!(kind("source_file").unwrap().named);
+        assert!(kind("string_literal").unwrap().named);
+        assert!(!kind("fn").unwrap().named);
+        assert_eq!(kind("not_a_kind"), None);
+
+        let language = Language::new(LanguageConfig::default(), None);
+        assert_eq!(language.node_kinds(), Vec::new());
+    }
+
+    #[test]
+    fn test_supertype_instances() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        );
+        let grammar = language.grammar().unwrap();
+        assert!(grammar.has_supertype("_expression"));
+        assert!(!grammar.has_supertype("binary_expression"));
+        assert!(!grammar.has_supertype("_not_a_kind"));
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse("fn a() { b + 1; }", None).unwrap();
+        let binary_expression = tree.root_node().descendant_for_byte_range(9, 14).unwrap();
+        assert_eq!(binary_expression.kind(), "binary_expression");
+        assert!(language.is_supertype_instance(binary_expression, "_expression"));
+        assert!(!language.is_supertype_instance(binary_expression, "_type"));
+        assert!(!language.is_supertype_instance(binary_expression, "binary_expression"));
+
+        let statement = binary_expression.parent().unwrap();
+        assert_eq!(statement.kind(), "expression_statement");
+        assert!(!language.is_supertype_instance(statement, "_expression"));
+    }
+
+    #[test]
+    fn test_debug_tree() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        );
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        let source = "fn a() {}";
+        let tree = parser.parse(source, None).unwrap();
+
+        assert_eq!(
+            language.debug_tree(&tree, source, usize::MAX),
+            indoc! {r#"
+                (source_file [0..9]
+                  (function_item [0..9]
+                    name: (identifier [3..4] "a")
+                    parameters: (parameters [4..6] "()")
+                    body: (block [7..9] "{}")))"#}
+        );
+        assert_eq!(
+            language.debug_tree(&tree, source, 1),
+            indoc! {r#"
+                (source_file [0..9]
+                  (function_item [0..9] ...))"#}
+        );
+    }
+}
@@ -5,6 +5,7 @@ use gpui::{
 };
 use settings::Settings;
 use std::ops::Range;
+use text::Point;
 use theme::{ActiveTheme, ThemeSettings};
 
 /// An outline of all the symbols contained in a buffer.
@@ -23,6 +24,20 @@ pub struct OutlineItem<T> {
     pub text: String,
     pub highlight_ranges: Vec<(Range<usize>, HighlightStyle)>,
     pub name_ranges: Vec<Range<usize>>,
+    /// The kind of the item's syntax node, such as `function_item`.
+    pub kind: &'static str,
+    /// The row and column where the item's syntax node starts.
+    pub start_point: Point,
+    /// The row and column where the item's syntax node ends.
+    pub end_point: Point,
+}
+
+impl<T> OutlineItem<T> {
+    /// Returns the LSP symbol kind of this item, for reporting it as a document symbol.
+    /// See [`lsp_symbol_kind`].
+    pub fn lsp_symbol_kind(&self) -> lsp::SymbolKind {
+        lsp_symbol_kind(self.kind)
+    }
 }
 
 impl<T> Outline<T> {
@@ -174,3 +189,60 @@ pub fn render_item<T>(
 
     StyledText::new(outline_item.text.clone()).with_highlights(&text_style, highlights)
 }
+
+/// Returns the LSP symbol kind for an outline item of the given kind, such as
+/// `function_item` or `definition.function`.
+///
+/// The kind's words are checked from the last one to the first, so that an
+/// `enum_variant` is an enum member rather than an enum. Kinds without any of the
+/// common words are variables.
+pub fn lsp_symbol_kind(kind: &str) -> lsp::SymbolKind {
+    kind.split(|c: char| !c.is_alphanumeric())
+        .rev()
+        .find_map(|word| {
+            Some(match word {
+                "function" | "fn" | "func" => lsp::SymbolKind::FUNCTION,
+                "method" => lsp::SymbolKind::METHOD,
+                "constructor" => lsp::SymbolKind::CONSTRUCTOR,
+                "class" => lsp::SymbolKind::CLASS,
+                "struct" => lsp::SymbolKind::STRUCT,
+                "enum" => lsp::SymbolKind::ENUM,
+                "variant" => lsp::SymbolKind::ENUM_MEMBER,
+                "trait" | "interface" | "protocol" => lsp::SymbolKind::INTERFACE,
+                "impl" | "implementation" => lsp::SymbolKind::OBJECT,
+                "mod" | "module" | "namespace" | "package" => lsp::SymbolKind::MODULE,
+                "const" | "constant" => lsp::SymbolKind::CONSTANT,
+                "field" => lsp::SymbolKind::FIELD,
+                "property" => lsp::SymbolKind::PROPERTY,
+                "type" => lsp::SymbolKind::TYPE_PARAMETER,
+                "macro" => lsp::SymbolKind::FUNCTION,
+                _ => return None,
+            })
+        })
+        .unwrap_or(lsp::SymbolKind::VARIABLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lsp_symbol_kind() {
+        for (kind, expected_symbol_kind) in [
+            ("function_item", lsp::SymbolKind::FUNCTION),
+            ("definition.function", lsp::SymbolKind::FUNCTION),
+            ("struct_item", lsp::SymbolKind::STRUCT),
+            ("definition.struct", lsp::SymbolKind::STRUCT),
+            ("enum_item", lsp::SymbolKind::ENUM),
+            ("enum_variant", lsp::SymbolKind::ENUM_MEMBER),
+            ("trait_item", lsp::SymbolKind::INTERFACE),
+            ("definition.interface", lsp::SymbolKind::INTERFACE),
+            ("impl_item", lsp::SymbolKind::OBJECT),
+            ("mod_item", lsp::SymbolKind::MODULE),
+            ("let_declaration", lsp::SymbolKind::VARIABLE),
+            ("", lsp::SymbolKind::VARIABLE),
+        ] {
+            assert_eq!(lsp_symbol_kind(kind), expected_symbol_kind, "kind {kind:?}");
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    error_highlights::error_highlights, syntax_map::SyntaxSnapshot, HighlightId, Language,
+    ParseOutcome,
+};
+use gpui::HighlightStyle;
+use std::{ops::Range, sync::Arc};
+use text::Rope;
+use theme::SyntaxTheme;
+
+impl Language {
+    /// Highlights the given text and resolves the highlights' styles against the theme,
+    /// taking the theme's scope selectors into account.
+    ///
+    /// When the theme has no selectors, this is equivalent to resolving the styles of
+    /// [`Language::highlight_text`]. Otherwise, the captures enclosing each piece of text
+    /// are tracked, so that the most specific selector matching them can apply. Where
+    /// no selector matches, the innermost capture's style applies as usual. Either way,
+    /// text outside of any capture gets the lexical highlighting of parse errors, as in
+    /// [`Language::highlight_text`].
+    ///
+    /// As with [`Language::highlight_to_ansi`], `theme` must be the one that was last
+    /// passed to [`Language::set_theme`].
+    pub fn highlight_styles(
+        self: &Arc<Self>,
+        text: &Rope,
+        theme: &SyntaxTheme,
+    ) -> Vec<(Range<usize>, HighlightStyle)> {
+        if theme.selectors.is_empty() {
+            return self
+                .highlight_text(text, 0..text.len())
+                .into_iter()
+                .filter_map(|(range, highlight_id)| Some((range, highlight_id.style(theme)?)))
+                .collect();
+        }
+
+        let mut result = Vec::new();
+        if self.parse_outcome(text.len(), None) != ParseOutcome::Parsed {
+            return result;
+        }
+        let Some(grammar) = self.grammar.as_ref() else {
+            return result;
+        };
+        let Some(query) = grammar.highlights_query.as_deref() else {
+            return result;
+        };
+        let capture_names = query.capture_names();
+        let highlight_map = grammar.highlight_map();
+        let tree = grammar.parse_text(text, None);
+        let captures =
+            SyntaxSnapshot::single_tree_captures(0..text.len(), text, &tree, self, |grammar| {
+                grammar.highlights_query.as_deref()
+            });
+        let errors = error_highlights([(self, tree.root_node())], text, 0..text.len());
+        let mut errors = errors.iter().peekable();
+
+        // The captures enclosing the current offset, from the outermost to the innermost.
+        let mut stack = Vec::<(usize, &str, HighlightId)>::new();
+        let mut offset = 0;
+        let mut advance_to = |stack: &mut Vec<(usize, &str, HighlightId)>, end: usize| {
+            while offset < end {
+                while stack
+                    .last()
+                    .map_or(false, |(capture_end, _, _)| *capture_end <= offset)
+                {
+                    stack.pop();
+                }
+                let Some((capture_end, _, highlight_id)) = stack.last() else {
+                    // As in `BufferChunks`, errors are only highlighted outside of captures.
+                    while let Some((error_range, highlight_id)) = errors.peek() {
+                        if error_range.start >= end {
+                            break;
+                        }
+                        let range = error_range.start.max(offset)..error_range.end.min(end);
+                        if !range.is_empty() {
+                            if let Some(style) = highlight_id.style(theme) {
+                                result.push((range, style));
+                            }
+                        }
+                        if error_range.end > end {
+                            break;
+                        }
+                        errors.next();
+                    }
+                    offset = end;
+                    break;
+                };
+                let segment_end = end.min(*capture_end);
+                let scopes = stack.iter().map(|(_, name, _)| *name).collect::<Vec<_>>();
+                if let Some(style) = theme
+                    .selector_style(&scopes)
+                    .or_else(|| highlight_id.style(theme))
+                {
+                    result.push((offset..segment_end, style));
+                }
+                offset = segment_end;
+            }
+        };
+        for capture in captures {
+            advance_to(&mut stack, capture.node.start_byte());
+            stack.push((
+                capture.node.end_byte(),
+                capture_names[capture.index as usize],
+                highlight_map.get(capture.index),
+            ));
+        }
+        advance_to(&mut stack, text.len());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+
+    #[test]
+    fn test_highlight_styles_with_selectors() {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(
+                r#"
+                (attribute_item) @meta.attribute
+                (string_literal) @string.special
+                "#,
+            )
+            .unwrap(),
+        );
+        let mut theme = SyntaxTheme {
+            highlights: vec![("string.special".into(), gpui::red().into())],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        let text = Rope::from("#[doc = \"a\"]\nconst B: &str = \"b\";");
+        let red = HighlightStyle::from(gpui::red());
+        let blue = HighlightStyle::from(gpui::blue());
+        assert_eq!(
+            language.highlight_styles(&text, &theme),
+            &[(8..11, red), (29..32, red)]
+        );
+
+        // Only the string within the attribute matches the selector.
+        theme.selectors = vec![("meta.attribute string.special".into(), blue)];
+        assert_eq!(
+            language.highlight_styles(&text, &theme),
+            &[(8..11, blue), (29..32, red)]
+        );
+    }
+
+    #[test]
+    fn test_highlight_styles_with_selectors_and_errors() {
+        // Keywords are only captured in well-formed functions.
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(r#"(function_item "fn" @keyword)"#)
+            .unwrap(),
+        );
+        language.set_highlight_errors_leniently(true);
+        let mut theme = SyntaxTheme {
+            highlights: vec![("keyword".into(), gpui::blue().into())],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        let text = Rope::from("fn a() {}\nstruct ) enum )\n");
+        let styles = language.highlight_styles(&text, &theme);
+        assert!(styles.len() > 1);
+
+        // Keywords within errors are highlighted the same way when the theme has selectors.
+        theme.selectors = vec![("meta.attribute keyword".into(), gpui::red().into())];
+        assert_eq!(language.highlight_styles(&text, &theme), styles);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{HighlightId, HighlightMap, Language};
+use gpui::HighlightStyle;
+use std::ops::Range;
+use theme::SyntaxTheme;
+
+impl Language {
+    /// Layers semantic tokens, such as those provided by a language server, over
+    /// the syntax highlighting produced by [`Language::highlight_text`].
+    ///
+    /// The semantic token types are resolved against the theme in the same way as
+    /// highlight capture names. Wherever a semantic token's style applies, it replaces
+    /// the style of the underlying syntax highlights, which are split as needed.
+    /// Semantic tokens whose types don't match any of the theme's highlights are ignored.
+    pub fn merge_semantic(
+        &self,
+        syntax_spans: &[(Range<usize>, HighlightId)],
+        semantic_spans: &[(Range<usize>, String)],
+        theme: &SyntaxTheme,
+    ) -> Vec<(Range<usize>, HighlightStyle)> {
+        let token_types = semantic_spans
+            .iter()
+            .map(|(_, token_type)| token_type.as_str())
+            .collect::<Vec<_>>();
+        let highlight_map = HighlightMap::new(&token_types, theme);
+
+        let mut semantic_spans = semantic_spans
+            .iter()
+            .enumerate()
+            .filter(|(_, (range, _))| !range.is_empty())
+            .filter_map(|(ix, (range, _))| {
+                let style = highlight_map.get(ix as u32).style(theme)?;
+                Some((range.clone(), style))
+            })
+            .collect::<Vec<_>>();
+        semantic_spans.sort_by_key(|(range, _)| range.start);
+
+        // Clip overlapping semantic tokens, so that each offset has at most one.
+        let mut previous_end = 0;
+        semantic_spans.retain_mut(|(range, _)| {
+            range.start = range.start.max(previous_end);
+            previous_end = previous_end.max(range.end);
+            !range.is_empty()
+        });
+
+        // Once split at the semantic tokens' boundaries, each piece of a syntax highlight
+        // is either entirely covered by a semantic token or not at all.
+        let boundaries = semantic_spans
+            .iter()
+            .flat_map(|(range, _)| [range.start, range.end])
+            .collect::<Vec<_>>();
+        let mut result = split_spans(syntax_spans, &boundaries)
+            .into_iter()
+            .filter(|(range, _)| {
+                let ix = semantic_spans
+                    .partition_point(|(semantic_range, _)| semantic_range.end <= range.start);
+                semantic_spans.get(ix).map_or(true, |(semantic_range, _)| {
+                    semantic_range.start > range.start
+                })
+            })
+            .filter_map(|(range, highlight_id)| Some((range, highlight_id.style(theme)?)))
+            .collect::<Vec<_>>();
+        result.extend(semantic_spans);
+        result.sort_by_key(|(range, _)| range.start);
+        result
+    }
+}
+
+/// Splits each of the given spans at the boundaries that fall within it, into adjacent
+/// spans with the same style, preserving the spans' order and the offsets they cover.
+///
+/// The boundaries can be in any order, and empty spans are omitted.
+pub fn split_spans<T: Clone>(
+    spans: &[(Range<usize>, T)],
+    boundaries: &[usize],
+) -> Vec<(Range<usize>, T)> {
+    let mut boundaries = boundaries.to_vec();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut result = Vec::with_capacity(spans.len());
+    for (range, style) in spans {
+        let mut start = range.start;
+        let first_boundary = boundaries.partition_point(|boundary| *boundary <= range.start);
+        for boundary in &boundaries[first_boundary..] {
+            if *boundary >= range.end {
+                break;
+            }
+            result.push((start..*boundary, style.clone()));
+            start = *boundary;
+        }
+        if start < range.end {
+            result.push((start..range.end, style.clone()));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+
+    #[test]
+    fn test_merge_semantic() {
+        let theme = SyntaxTheme {
+            highlights: vec![
+                ("variable".into(), gpui::red().into()),
+                ("variable.readonly".into(), gpui::blue().into()),
+            ],
+            ..Default::default()
+        };
+        let variable = theme.highlights[0].1;
+        let readonly = theme.highlights[1].1;
+
+        let language = Language::new(LanguageConfig::default(), None);
+        let syntax_spans = [(0..5, HighlightId(0)), (6..9, HighlightId(0))];
+
+        assert_eq!(
+            language.merge_semantic(
+                &syntax_spans,
+                &[
+                    (1..3, "variable.readonly".into()),
+                    (4..5, "unknown".into()),
+                    (8..12, "variable.readonly".into()),
+                ],
+                &theme,
+            ),
+            &[
+                (0..1, variable),
+                (1..3, readonly),
+                (3..5, variable),
+                (6..8, variable),
+                (8..12, readonly),
+            ]
+        );
+        assert_eq!(
+            language.merge_semantic(&syntax_spans, &[], &theme),
+            &[(0..5, variable), (6..9, variable)]
+        );
+    }
+
+    #[test]
+    fn test_split_spans() {
+        let spans = [(2..6, 'a'), (6..8, 'b'), (10..12, 'c')];
+
+        // Boundaries inside of spans split them.
+        assert_eq!(
+            split_spans(&spans, &[4, 11, 3]),
+            &[
+                (2..3, 'a'),
+                (3..4, 'a'),
+                (4..6, 'a'),
+                (6..8, 'b'),
+                (10..11, 'c'),
+                (11..12, 'c')
+            ]
+        );
+        // Boundaries at the edges of spans, or outside of them, don't.
+        assert_eq!(split_spans(&spans, &[0, 2, 6, 8, 9, 12, 20]), &spans);
+        assert_eq!(split_spans(&spans, &[]), &spans);
+        assert_eq!(split_spans::<char>(&[], &[1, 2]), &[]);
+    }
+}
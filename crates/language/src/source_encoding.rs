@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// An error in the source of a language's text or queries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LanguageError {
+    /// The source isn't valid UTF-8 or BOM-prefixed UTF-16.
+    InvalidEncoding,
+}
+
+impl std::fmt::Display for LanguageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidEncoding => write!(f, "invalid encoding"),
+        }
+    }
+}
+
+impl std::error::Error for LanguageError {}
+
+/// Decodes source text, such as a file's contents or a query.
+///
+/// Text without a byte order mark must be valid UTF-8. A UTF-8 or UTF-16 byte
+/// order mark is removed, and UTF-16 text is transcoded to UTF-8.
+pub fn decode_source(bytes: &[u8]) -> Result<Cow<'_, str>, LanguageError> {
+    if let Some(bytes) = bytes.strip_prefix(UTF8_BOM) {
+        decode_utf8(bytes)
+    } else if let Some(bytes) = bytes.strip_prefix(UTF16_LE_BOM) {
+        decode_utf16(bytes, u16::from_le_bytes)
+    } else if let Some(bytes) = bytes.strip_prefix(UTF16_BE_BOM) {
+        decode_utf16(bytes, u16::from_be_bytes)
+    } else {
+        decode_utf8(bytes)
+    }
+}
+
+fn decode_utf8(bytes: &[u8]) -> Result<Cow<'_, str>, LanguageError> {
+    std::str::from_utf8(bytes)
+        .map(Cow::Borrowed)
+        .map_err(|_| LanguageError::InvalidEncoding)
+}
+
+fn decode_utf16(
+    bytes: &[u8],
+    decode_unit: fn([u8; 2]) -> u16,
+) -> Result<Cow<'static, str>, LanguageError> {
+    if bytes.len() % 2 != 0 {
+        return Err(LanguageError::InvalidEncoding);
+    }
+    let units = bytes
+        .chunks_exact(2)
+        .map(|unit| decode_unit([unit[0], unit[1]]));
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map(Cow::Owned)
+        .map_err(|_| LanguageError::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Language, LanguageConfig};
+
+    #[test]
+    fn test_decode_source() {
+        assert_eq!(decode_source(b"fn a() {}").unwrap(), "fn a() {}");
+        assert!(matches!(
+            decode_source(b"fn a() {}").unwrap(),
+            Cow::Borrowed(_)
+        ));
+
+        let utf16_le = [0xFF, 0xFE, b'h', 0, b'i', 0];
+        assert_eq!(decode_source(&utf16_le).unwrap(), "hi");
+        let utf16_be = [0xFE, 0xFF, 0, b'h', 0, b'i'];
+        assert_eq!(decode_source(&utf16_be).unwrap(), "hi");
+
+        // Latin-1 text isn't valid UTF-8.
+        assert_eq!(
+            decode_source(b"caf\xE9"),
+            Err(LanguageError::InvalidEncoding)
+        );
+        assert_eq!(
+            decode_source(&[0xFF, 0xFE, b'h']),
+            Err(LanguageError::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn test_decode_query_with_bom() {
+        let query = b"\xEF\xBB\xBF(identifier) @variable\n";
+        let query = decode_source(query).unwrap();
+        assert_eq!(query, "(identifier) @variable\n");
+
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query(&query)
+        .unwrap();
+        assert_eq!(
+            language
+                .grammar()
+                .unwrap()
+                .highlights_query
+                .as_ref()
+                .unwrap()
+                .capture_names(),
+            &["variable"]
+        );
+    }
+}
@@ -2,10 +2,10 @@
 mod syntax_map_tests;
 
 use crate::{
+    locals::{LayerLocals, Locals, LocalsConfig},
     with_parser, Grammar, InjectionConfig, Language, LanguageId, LanguageRegistry, QUERY_CURSORS,
 };
 use collections::HashMap;
-use futures::FutureExt;
 use std::{
     borrow::Cow,
     cmp::{self, Ordering, Reverse},
@@ -30,6 +30,7 @@ pub struct SyntaxSnapshot {
     parsed_version: clock::Global,
     interpolated_version: clock::Global,
     language_registry_version: usize,
+    changed_ranges: Vec<Range<Anchor>>,
 }
 
 #[derive(Default)]
@@ -51,6 +52,8 @@ pub struct SyntaxMapCapture<'a> {
     pub node: Node<'a>,
     pub index: u32,
     pub grammar_index: usize,
+    /// The depth of the capture's syntax layer, where the root layer has a depth of zero.
+    pub depth: usize,
 }
 
 #[derive(Debug)]
@@ -67,6 +70,9 @@ struct SyntaxMapCapturesLayer<'a> {
     captures: QueryCaptures<'a, 'a, TextProvider<'a>, &'a [u8]>,
     next_capture: Option<QueryCapture<'a>>,
     grammar_index: usize,
+    /// The local definitions used to evaluate the query's `local` predicates, if it has any,
+    /// along with the offset of the layer that they're relative to.
+    locals: Option<(Cow<'a, Locals>, &'a Query, &'a Rope, usize)>,
     _query_cursor: QueryCursorHandle,
 }
 
@@ -93,6 +99,9 @@ enum SyntaxLayerContent {
     Parsed {
         tree: tree_sitter::Tree,
         language: Arc<Language>,
+        /// The tree's local definitions, found when they're first needed to highlight the
+        /// layer by updating those of the tree before it was edited.
+        locals: Arc<LayerLocals>,
     },
     Pending {
         language_name: Arc<str>,
@@ -124,6 +133,7 @@ pub struct SyntaxLayer<'a> {
     depth: usize,
     tree: &'a Tree,
     offset: (usize, tree_sitter::Point),
+    locals: Option<&'a LayerLocals>,
 }
 
 /// A layer of syntax highlighting. Like [SyntaxLayer], but holding
@@ -205,7 +215,7 @@ struct ChangedRegion {
 #[derive(Default)]
 struct ChangeRegionSet(Vec<ChangedRegion>);
 
-struct TextProvider<'a>(&'a Rope);
+pub(crate) struct TextProvider<'a>(pub(crate) &'a Rope);
 
 struct ByteChunks<'a>(text::Chunks<'a>);
 
@@ -243,7 +253,10 @@ impl SyntaxMap {
     }
 
     pub fn clear(&mut self) {
-        self.snapshot = SyntaxSnapshot::default();
+        self.snapshot = SyntaxSnapshot {
+            changed_ranges: vec![Anchor::MIN..Anchor::MAX],
+            ..SyntaxSnapshot::default()
+        };
     }
 }
 
@@ -319,7 +332,7 @@ impl SyntaxSnapshot {
             }
 
             let mut layer = layer.clone();
-            if let SyntaxLayerContent::Parsed { tree, .. } = &mut layer.content {
+            if let SyntaxLayerContent::Parsed { tree, locals, .. } = &mut layer.content {
                 for (edit, edit_range) in &edits[first_edit_ix_for_depth..] {
                     // Ignore any edits that follow this layer.
                     if edit_range.start.cmp(&layer.range.end, text).is_ge() {
@@ -327,7 +340,9 @@ impl SyntaxSnapshot {
                     }
 
                     // Apply any edits that intersect this layer to the layer's syntax tree.
-                    let tree_edit = if edit_range.start.cmp(&layer.range.start, text).is_ge() {
+                    let starts_within_layer =
+                        edit_range.start.cmp(&layer.range.start, text).is_ge();
+                    let tree_edit = if starts_within_layer {
                         tree_sitter::InputEdit {
                             start_byte: edit.new.start.0 - start_byte,
                             old_end_byte: edit.new.start.0 - start_byte
@@ -352,6 +367,14 @@ impl SyntaxSnapshot {
                     };
 
                     tree.edit(&tree_edit);
+
+                    // An edit that starts before the layer replaces its whole tree, so its
+                    // locals are found again from scratch.
+                    *locals = if starts_within_layer {
+                        Arc::new(locals.edit(&tree_edit))
+                    } else {
+                        Default::default()
+                    };
                 }
 
                 debug_assert!(
@@ -381,6 +404,7 @@ impl SyntaxSnapshot {
             .edits_since::<usize>(&self.parsed_version)
             .map(|edit| edit.new)
             .collect::<Vec<_>>();
+        self.changed_ranges.clear();
         self.reparse_with_ranges(text, root_language.clone(), edit_ranges, registry.as_ref());
 
         if let Some(registry) = registry {
@@ -395,10 +419,8 @@ impl SyntaxSnapshot {
                         unreachable!()
                     };
                     if registry
-                        .language_for_name_or_extension(language_name)
-                        .now_or_never()
-                        .and_then(|language| language.ok())
-                        .is_some()
+                        .find_injection_language(language_name)
+                        .map_or(false, |language| language.grammar().is_some())
                     {
                         resolved_injection_ranges.push(layer.range.to_offset(text));
                     }
@@ -540,6 +562,7 @@ impl SyntaxSnapshot {
                     };
                     let tree;
                     let changed_ranges;
+                    let locals;
 
                     let mut included_ranges = step.included_ranges;
                     for range in &mut included_ranges {
@@ -553,8 +576,14 @@ impl SyntaxSnapshot {
                             .to_ts_point();
                     }
 
-                    if let Some((SyntaxLayerContent::Parsed { tree: old_tree, .. }, layer_start)) =
-                        old_layer.map(|layer| (&layer.content, layer.range.start))
+                    if let Some((
+                        SyntaxLayerContent::Parsed {
+                            tree: old_tree,
+                            locals: old_locals,
+                            ..
+                        },
+                        layer_start,
+                    )) = old_layer.map(|layer| (&layer.content, layer.range.start))
                     {
                         log::trace!(
                             "existing layer. language:{}, start:{:?}, ranges:{:?}",
@@ -579,13 +608,24 @@ impl SyntaxSnapshot {
                                 &parent_layer_changed_ranges,
                                 &included_ranges,
                             );
-                            insert_newlines_between_ranges(
-                                changed_indices,
-                                &mut included_ranges,
-                                text,
-                                step_start_byte,
-                                step_start_point,
-                            );
+                            // The spliced ranges include those of the unchanged injections,
+                            // so the combination may have grown too large.
+                            let max_injection_bytes =
+                                registry.and_then(|registry| registry.max_injection_bytes());
+                            if language.allows_injection(
+                                included_ranges_len(&included_ranges),
+                                max_injection_bytes,
+                            ) {
+                                insert_newlines_between_ranges(
+                                    changed_indices,
+                                    &mut included_ranges,
+                                    text,
+                                    step_start_byte,
+                                    step_start_point,
+                                );
+                            } else {
+                                included_ranges.clear();
+                            }
                         }
 
                         if included_ranges.is_empty() {
@@ -619,6 +659,10 @@ impl SyntaxSnapshot {
                             }
                         };
 
+                        let tree_changed_ranges = old_tree
+                            .changed_ranges(&tree)
+                            .map(|r| r.start_byte..r.end_byte)
+                            .collect::<Vec<_>>();
                         changed_ranges = join_ranges(
                             invalidated_ranges
                                 .iter()
@@ -626,10 +670,11 @@ impl SyntaxSnapshot {
                                     range.start <= step_end_byte && range.end >= step_start_byte
                                 })
                                 .cloned(),
-                            old_tree.changed_ranges(&tree).map(|r| {
-                                step_start_byte + r.start_byte..step_start_byte + r.end_byte
+                            tree_changed_ranges.iter().map(|range| {
+                                step_start_byte + range.start..step_start_byte + range.end
                             }),
                         );
+                        locals = Arc::new(old_locals.reparse(tree_changed_ranges));
                     } else {
                         if matches!(step.mode, ParseMode::Combined { .. }) {
                             insert_newlines_between_ranges(
@@ -672,8 +717,14 @@ impl SyntaxSnapshot {
                             }
                         };
                         changed_ranges = vec![step_start_byte..step_end_byte];
+                        locals = Default::default();
                     }
 
+                    self.changed_ranges
+                        .extend(changed_ranges.iter().map(|range| {
+                            text.anchor_before(range.start)..text.anchor_after(range.end)
+                        }));
+
                     if let (Some((config, registry)), false) = (
                         grammar.injection_config.as_ref().zip(registry.as_ref()),
                         changed_ranges.is_empty(),
@@ -704,7 +755,11 @@ impl SyntaxSnapshot {
                         );
                     }
 
-                    SyntaxLayerContent::Parsed { tree, language }
+                    SyntaxLayerContent::Parsed {
+                        tree,
+                        language,
+                        locals,
+                    }
                 }
                 ParseStepLanguage::Pending { name } => SyntaxLayerContent::Pending {
                     language_name: name,
@@ -767,6 +822,7 @@ impl SyntaxSnapshot {
                 tree,
                 depth: 0,
                 offset: (0, tree_sitter::Point::new(0, 0)),
+                locals: None,
             }]
             .into_iter(),
             query,
@@ -830,7 +886,12 @@ impl SyntaxSnapshot {
         iter::from_fn(move || {
             while let Some(layer) = cursor.item() {
                 let mut info = None;
-                if let SyntaxLayerContent::Parsed { tree, language } = &layer.content {
+                if let SyntaxLayerContent::Parsed {
+                    tree,
+                    language,
+                    locals,
+                } = &layer.content
+                {
                     let layer_start_offset = layer.range.start.to_offset(buffer);
                     let layer_start_point = layer.range.start.to_point(buffer).to_ts_point();
 
@@ -839,6 +900,7 @@ impl SyntaxSnapshot {
                         language,
                         depth: layer.depth,
                         offset: (layer_start_offset, layer_start_point),
+                        locals: Some(locals),
                     });
                 }
                 cursor.next(buffer);
@@ -857,6 +919,11 @@ impl SyntaxSnapshot {
     pub fn language_registry_version(&self) -> usize {
         self.language_registry_version
     }
+
+    /// The ranges whose syntax may have changed in the most recent reparse.
+    pub fn changed_ranges(&self) -> &[Range<Anchor>] {
+        &self.changed_ranges
+    }
 }
 
 impl<'a> SyntaxMapCaptures<'a> {
@@ -892,6 +959,19 @@ impl<'a> SyntaxMapCaptures<'a> {
 
             cursor.set_byte_range(range.clone());
             let captures = cursor.captures(query, layer.node(), TextProvider(text));
+            let locals = grammar
+                .locals_config
+                .as_ref()
+                .filter(|_| LocalsConfig::is_used_by(query))
+                .map(|config| {
+                    let node = layer.node();
+                    let offset = layer.offset.0;
+                    let locals = match layer.locals {
+                        Some(locals) => Cow::Borrowed(locals.get(config, node, text, offset)),
+                        None => Cow::Owned(Locals::new(config, node, text, offset)),
+                    };
+                    (locals, query, text, offset)
+                });
             let grammar_index = result
                 .grammars
                 .iter()
@@ -905,6 +985,7 @@ impl<'a> SyntaxMapCaptures<'a> {
                 grammar_index,
                 next_capture: None,
                 captures,
+                locals,
                 _query_cursor: query_cursor,
             };
 
@@ -937,6 +1018,7 @@ impl<'a> SyntaxMapCaptures<'a> {
             grammar_index: layer.grammar_index,
             index: capture.index,
             node: capture.node,
+            depth: layer.depth,
         })
     }
 
@@ -1093,7 +1175,17 @@ impl<'a> SyntaxMapMatches<'a> {
 
 impl<'a> SyntaxMapCapturesLayer<'a> {
     fn advance(&mut self) {
-        self.next_capture = self.captures.next().map(|(mat, ix)| mat.captures[ix]);
+        self.next_capture = loop {
+            let Some((mat, ix)) = self.captures.next() else {
+                break None;
+            };
+            if let Some((locals, query, text, offset)) = &self.locals {
+                if !locals.satisfies_predicates(query, &mat, ix, text, *offset) {
+                    continue;
+                }
+            }
+            break Some(mat.captures[ix]);
+        };
     }
 
     fn sort_key(&self) -> (usize, Reverse<usize>, usize) {
@@ -1212,6 +1304,7 @@ fn get_injections(
 ) {
     let mut query_cursor = QueryCursorHandle::new();
     let mut prev_match = None;
+    let max_injection_bytes = language_registry.max_injection_bytes();
 
     // Ensure that a `ParseStep` is created for every combined injection language, even
     // if there currently no matches for that injection.
@@ -1219,9 +1312,8 @@ fn get_injections(
     for pattern in &config.patterns {
         if let (Some(language_name), true) = (pattern.language.as_ref(), pattern.combined) {
             if let Some(language) = language_registry
-                .language_for_name_or_extension(language_name)
-                .now_or_never()
-                .and_then(|language| language.ok())
+                .find_injection_language(language_name)
+                .filter(|language| language.grammar().is_some())
             {
                 combined_injection_ranges.insert(language, Vec::new());
             }
@@ -1250,6 +1342,10 @@ fn get_injections(
             }
 
             prev_match = Some((mat.pattern_index, content_range.clone()));
+            let content_len = content_ranges
+                .iter()
+                .map(|range| range.end_byte - range.start_byte)
+                .sum::<usize>();
             let combined = config.patterns[mat.pattern_index].combined;
 
             let mut language_name = None;
@@ -1262,24 +1358,31 @@ fn get_injections(
             {
                 step_range.start = cmp::min(content_range.start, language_node.start_byte());
                 step_range.end = cmp::max(content_range.end, language_node.end_byte());
-                language_name = Some(Cow::Owned(
-                    text.text_for_range(language_node.byte_range()).collect(),
-                ))
+                let language_text = text
+                    .text_for_range(language_node.byte_range())
+                    .collect::<String>();
+                let name = language_name_from_capture(&language_text);
+                if !name.is_empty() {
+                    language_name = Some(Cow::Owned(name.to_string()))
+                }
             };
 
             if let Some(language_name) = language_name {
+                // Injections of languages without grammars, such as the plain text that
+                // unknown names fall back to, are pending until a language is added.
                 let language = language_registry
-                    .language_for_name_or_extension(&language_name)
-                    .now_or_never()
-                    .and_then(|language| language.ok());
+                    .find_injection_language(&language_name)
+                    .filter(|language| language.grammar().is_some());
                 let range = text.anchor_before(step_range.start)..text.anchor_after(step_range.end);
                 if let Some(language) = language {
+                    // Oversized regions keep the enclosing layer's highlighting. Combined
+                    // regions are limited by their total size, once they're all found.
                     if combined {
                         combined_injection_ranges
                             .entry(language.clone())
                             .or_default()
                             .extend(content_ranges);
-                    } else {
+                    } else if language.allows_injection(content_len, max_injection_bytes) {
                         queue.push(ParseStep {
                             depth,
                             language: ParseStepLanguage::Loaded { language },
@@ -1288,7 +1391,7 @@ fn get_injections(
                             mode: ParseMode::Single,
                         });
                     }
-                } else {
+                } else if max_injection_bytes.map_or(true, |limit| content_len <= limit) {
                     queue.push(ParseStep {
                         depth,
                         language: ParseStepLanguage::Pending {
@@ -1307,6 +1410,11 @@ fn get_injections(
         included_ranges.sort_unstable_by(|a, b| {
             Ord::cmp(&a.start_byte, &b.start_byte).then_with(|| Ord::cmp(&a.end_byte, &b.end_byte))
         });
+        // Combined injections are parsed as a single region, so it's their total size
+        // that's limited. An oversized combination is parsed as if nothing was injected.
+        if !language.allows_injection(included_ranges_len(&included_ranges), max_injection_bytes) {
+            included_ranges.clear();
+        }
         queue.push(ParseStep {
             depth,
             language: ParseStepLanguage::Loaded { language },
@@ -1320,6 +1428,27 @@ fn get_injections(
     }
 }
 
+/// Returns the total number of bytes in the given included ranges.
+fn included_ranges_len(ranges: &[tree_sitter::Range]) -> usize {
+    ranges
+        .iter()
+        .map(|range| range.end_byte - range.start_byte)
+        .sum()
+}
+
+/// Extracts the name of an injected language from the text of a language capture, such
+/// as a fenced code block's info string, where the name can be followed by attributes,
+/// as in `rust,ignore` or `{.python title="example.py"}`.
+pub(crate) fn language_name_from_capture(text: &str) -> &str {
+    let text = text.trim_start();
+    let text = text.strip_prefix('{').unwrap_or(text).trim_start();
+    let text = text.strip_prefix('.').unwrap_or(text);
+    let end = text
+        .find(|c: char| c.is_whitespace() || matches!(c, ',' | '{' | '}'))
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
 /// Updates the given list of included `ranges`, removing any ranges that intersect
 /// `removed_ranges`, and inserting the given `new_ranges`.
 ///
@@ -1484,6 +1613,12 @@ impl<'a> SyntaxLayer<'a> {
             .root_node_with_offset(self.offset.0, self.offset.1)
     }
 
+    /// Returns the depth of this layer, where the buffer's own language is at a depth of
+    /// zero and the languages injected into it are deeper.
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
     pub(crate) fn override_id(&self, offset: usize, text: &text::BufferSnapshot) -> Option<u32> {
         let text = TextProvider(text.as_rope());
         let config = self.language.grammar.as_ref()?.override_config.as_ref()?;
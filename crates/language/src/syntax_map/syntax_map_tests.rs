@@ -254,6 +254,319 @@ fn test_dynamic_language_injection(cx: &mut AppContext) {
     assert!(!syntax_map.contains_unknown_injections());
 }
 
+#[gpui::test]
+fn test_injection_language_from_captured_text(cx: &mut AppContext) {
+    let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    let markdown = Arc::new(markdown_lang());
+    let rust = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_injection_query(
+            r#"
+            (macro_invocation
+                macro: (identifier) @injection.language
+                (token_tree) @injection.content)
+            "#,
+        )
+        .unwrap(),
+    );
+    registry.add(markdown.clone());
+    registry.add(rust.clone());
+    registry.add(Arc::new(ruby_lang()));
+
+    // Info strings can contain attributes after the language name.
+    let buffer = Buffer::new(
+        0,
+        BufferId::new(1).unwrap(),
+        r#"
+            ```rust,ignore
+            fn foo() {}
+            ```
+
+            ```unknown
+            foo
+            ```
+        "#
+        .unindent(),
+    );
+    let mut syntax_map = SyntaxMap::new();
+    syntax_map.set_language_registry(registry.clone());
+    syntax_map.reparse(markdown.clone(), &buffer);
+    let layer_languages = syntax_map
+        .layers(&buffer)
+        .iter()
+        .map(|layer| layer.language.name())
+        .collect::<Vec<_>>();
+    assert_eq!(layer_languages, [markdown.name(), rust.name()]);
+    // Unknown languages are left un-injected until they're loaded.
+    assert!(syntax_map.contains_unknown_injections());
+
+    // Macros can name the language of their contents.
+    let buffer = Buffer::new(
+        0,
+        BufferId::new(2).unwrap(),
+        "fn a() { ruby!(b.c); println!(\"d\"); }".to_string(),
+    );
+    let mut syntax_map = SyntaxMap::new();
+    syntax_map.set_language_registry(registry.clone());
+    syntax_map.reparse(rust.clone(), &buffer);
+    let layer_languages = syntax_map
+        .layers(&buffer)
+        .iter()
+        .map(|layer| layer.language.name())
+        .collect::<Vec<_>>();
+    assert_eq!(layer_languages, [rust.name(), "Ruby".into()]);
+
+    assert_eq!(language_name_from_capture("rust,ignore"), "rust");
+    assert_eq!(
+        language_name_from_capture("{.python title=\"example.py\"}"),
+        "python"
+    );
+    assert_eq!(language_name_from_capture("  sql  "), "sql");
+    assert_eq!(language_name_from_capture("{}"), "");
+}
+
+#[gpui::test]
+fn test_max_injection_bytes(cx: &mut AppContext) {
+    let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    let markdown = Arc::new(markdown_lang());
+    let rust = Arc::new(rust_lang());
+    registry.add(markdown.clone());
+    registry.add(rust.clone());
+
+    let buffer = Buffer::new(
+        0,
+        BufferId::new(1).unwrap(),
+        r#"
+            ```rust
+            fn a() {}
+            ```
+
+            ```rust
+            fn b() { let c = "a much longer code block"; }
+            ```
+        "#
+        .unindent(),
+    );
+    let layer_languages = |registry: &Arc<LanguageRegistry>| {
+        let mut syntax_map = SyntaxMap::new();
+        syntax_map.set_language_registry(registry.clone());
+        syntax_map.reparse(markdown.clone(), &buffer);
+        syntax_map
+            .layers(&buffer)
+            .iter()
+            .map(|layer| (layer.language.name(), layer.node().start_position().row))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        layer_languages(&registry),
+        [(markdown.name(), 0), (rust.name(), 1), (rust.name(), 5)]
+    );
+
+    // The larger code block is left as Markdown.
+    registry.set_max_injection_bytes(Some(20));
+    assert_eq!(
+        layer_languages(&registry),
+        [(markdown.name(), 0), (rust.name(), 1)]
+    );
+}
+
+#[gpui::test]
+fn test_max_combined_injection_bytes(cx: &mut AppContext) {
+    let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    let erb = Arc::new(erb_lang());
+    registry.add(erb.clone());
+    registry.add(Arc::new(ruby_lang()));
+
+    let mut buffer = Buffer::new(
+        0,
+        BufferId::new(1).unwrap(),
+        "<% aaaa %><% bbbb %>".to_string(),
+    );
+    let mut syntax_map = SyntaxMap::new();
+    syntax_map.set_language_registry(registry.clone());
+    let layer_languages = |syntax_map: &SyntaxMap, buffer: &Buffer| {
+        syntax_map
+            .layers(buffer)
+            .iter()
+            .filter(|layer| layer.node().end_byte() > layer.node().start_byte())
+            .map(|layer| layer.language.name())
+            .collect::<Vec<_>>()
+    };
+
+    // Each region of code is small enough, but together they're too large.
+    registry.set_max_injection_bytes(Some(7));
+    syntax_map.reparse(erb.clone(), &buffer);
+    assert_eq!(layer_languages(&syntax_map, &buffer), [erb.name()]);
+
+    registry.set_max_injection_bytes(Some(12));
+    let mut syntax_map = SyntaxMap::new();
+    syntax_map.set_language_registry(registry.clone());
+    syntax_map.reparse(erb.clone(), &buffer);
+    assert_eq!(
+        layer_languages(&syntax_map, &buffer),
+        [erb.name(), "Ruby".into()]
+    );
+
+    // Growing one of the regions makes the combination too large.
+    buffer.edit([(5..5, "aaaaa")]);
+    syntax_map.interpolate(&buffer);
+    syntax_map.reparse(erb.clone(), &buffer);
+    assert_eq!(layer_languages(&syntax_map, &buffer), [erb.name()]);
+}
+
+#[gpui::test]
+fn test_local_predicates_in_moved_injection(cx: &mut AppContext) {
+    let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    let markdown = Arc::new(markdown_lang());
+    let rust = Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    )
+    .with_highlights_query("((identifier) @local (#is? local))")
+    .unwrap()
+    .with_locals_query(
+        r#"
+        (block) @local.scope
+        (let_declaration pattern: (identifier) @local.definition)
+        "#,
+    )
+    .unwrap();
+    registry.add(markdown.clone());
+    registry.add(Arc::new(rust));
+
+    let mut buffer = Buffer::new(
+        0,
+        BufferId::new(1).unwrap(),
+        r#"
+            Text
+
+            ```rust
+            fn a() { let b = 1; b + c }
+            ```
+        "#
+        .unindent(),
+    );
+    let mut syntax_map = SyntaxMap::new();
+    syntax_map.set_language_registry(registry.clone());
+    syntax_map.reparse(markdown.clone(), &buffer);
+    assert_capture_ranges(
+        &syntax_map,
+        &buffer,
+        &["local"],
+        r#"
+            Text
+
+            ```rust
+            fn a() { let «b» = 1; «b» + c }
+            ```
+        "#,
+    );
+
+    // The injection's local definitions are still found after it moves.
+    let range = range_for_text(&buffer, "Text");
+    buffer.edit([(range, "Some longer text")]);
+    syntax_map.interpolate(&buffer);
+    syntax_map.reparse(markdown.clone(), &buffer);
+    assert_capture_ranges(
+        &syntax_map,
+        &buffer,
+        &["local"],
+        r#"
+            Some longer text
+
+            ```rust
+            fn a() { let «b» = 1; «b» + c }
+            ```
+        "#,
+    );
+}
+
+#[gpui::test]
+fn test_local_predicates_after_edits(cx: &mut AppContext) {
+    let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    let rust = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_highlights_query("((identifier) @local (#is? local))")
+        .unwrap()
+        .with_locals_query(
+            r#"
+            (function_item) @local.scope
+            (block) @local.scope
+            (parameter pattern: (identifier) @local.definition)
+            (let_declaration pattern: (identifier) @local.definition)
+            "#,
+        )
+        .unwrap(),
+    );
+    registry.add(rust.clone());
+
+    let local_ranges = |syntax_map: &SyntaxMap, buffer: &BufferSnapshot| {
+        let captures = syntax_map.captures(0..buffer.len(), buffer, |grammar| {
+            grammar.highlights_query.as_deref()
+        });
+        captures
+            .map(|capture| capture.node.byte_range())
+            .collect::<Vec<_>>()
+    };
+
+    let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), Default::default());
+    let mut syntax_map = SyntaxMap::new();
+    syntax_map.set_language_registry(registry.clone());
+    syntax_map.reparse(rust.clone(), &buffer);
+    for (i, marked_string) in [
+        "fn a(b: u32) { let c = b; c + d }",
+        "fn a(b: u32) { let c = b; «let d = c; »c + d }",
+        "fn a(b: u32) { let c = b; «{ »let d = c; «} »c + d }",
+        "fn a(b: u32) { let «e» = b; { let d = «e»; } c + d }",
+        "fn a(«c»: u32) { let e = b; { let d = e; } c + d }",
+        "fn a(c: u32) { let e = b; «»let d = e; «»c + d }",
+        "fn a(c: u32) { «»let d = e; c + d }",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        buffer.edit_via_marked_text(marked_string);
+
+        // The locals are found both before and after the tree is reparsed, and are
+        // updated from those of the previous tree.
+        syntax_map.interpolate(&buffer);
+        local_ranges(&syntax_map, &buffer);
+        syntax_map.reparse(rust.clone(), &buffer);
+
+        let mut reference_syntax_map = SyntaxMap::new();
+        reference_syntax_map.set_language_registry(registry.clone());
+        reference_syntax_map.reparse(rust.clone(), &buffer);
+        assert_eq!(
+            local_ranges(&syntax_map, &buffer),
+            local_ranges(&reference_syntax_map, &buffer),
+            "different locals at step {i}"
+        );
+    }
+
+    assert_capture_ranges(
+        &syntax_map,
+        &buffer,
+        &["local"],
+        "fn a(«c»: u32) { let «d» = e; «c» + «d» }",
+    );
+}
+
 #[gpui::test]
 fn test_typing_multiple_new_injections(cx: &mut AppContext) {
     let (buffer, syntax_map) = test_edit_sequence(
@@ -575,6 +888,55 @@ fn test_combined_injections_simple(cx: &mut AppContext) {
     );
 }
 
+#[gpui::test]
+fn test_combined_injections_with_standard_names(cx: &mut AppContext) {
+    let ruby = Arc::new(ruby_lang());
+    let erb = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "ERB".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_embedded_template::language()),
+        )
+        .with_highlights_query(r#"["<%" "%>"] @keyword"#)
+        .unwrap()
+        .with_injection_query(
+            r#"
+                (
+                    (code) @injection.content
+                    (#set! injection.language "ruby")
+                    (#set! injection.combined)
+                )
+            "#,
+        )
+        .unwrap(),
+    );
+    let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+    registry.add(ruby);
+    registry.add(erb.clone());
+
+    let text = "<% if @one %>\n<div>\n<% end %>\n".to_string();
+    let buffer = Buffer::new(0, BufferId::new(1).unwrap(), text);
+    let mut syntax_map = SyntaxMap::new();
+    syntax_map.set_language_registry(registry);
+    syntax_map.reparse(erb, &buffer);
+
+    // The fragments are parsed together, as a single `if` expression.
+    let layers = syntax_map.layers(&buffer);
+    assert_eq!(layers.len(), 2);
+    let ruby_tree = layers[1].node().to_sexp();
+    assert!(ruby_tree.contains("(if condition: (instance_variable)"));
+    assert!(!ruby_tree.contains("ERROR"));
+
+    assert_capture_ranges(
+        &syntax_map,
+        &buffer,
+        &["keyword", "ivar"],
+        "«<%» «if» «@one» «%>»\n<div>\n«<%» «end» «%>»\n",
+    );
+}
+
 #[gpui::test]
 fn test_combined_injections_empty_ranges(cx: &mut AppContext) {
     test_edit_sequence(
@@ -1333,12 +1695,12 @@ fn assert_capture_ranges(
 ) {
     let mut actual_ranges = Vec::<Range<usize>>::new();
     let captures = syntax_map.captures(0..buffer.len(), buffer, |grammar| {
-        grammar.highlights_query.as_ref()
+        grammar.highlights_query.as_deref()
     });
     let queries = captures
         .grammars()
         .iter()
-        .map(|grammar| grammar.highlights_query.as_ref().unwrap())
+        .map(|grammar| grammar.highlights_query.as_deref().unwrap())
         .collect::<Vec<_>>();
     for capture in captures {
         let name = &queries[capture.grammar_index].capture_names()[capture.index as usize];
@@ -0,0 +1,221 @@
+use crate::{code_stats::range_contains, Language, ParseOutcome};
+use collections::HashMap;
+use std::{num::NonZeroU32, ops::Range};
+use text::Rope;
+use tree_sitter::Tree;
+
+/// A whitespace problem found by [`Language::whitespace_issues`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WhitespaceIssue {
+    /// Whitespace at the end of a line.
+    TrailingWhitespace(Range<usize>),
+    /// A line's indentation, when it contains tabs in a language indented with spaces,
+    /// or spaces before tabs in a language indented with tabs.
+    MixedIndentation(Range<usize>),
+}
+
+/// The unit of indentation used by a text, as found by [`Language::detect_indentation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetectedIndent {
+    Tabs,
+    Spaces(NonZeroU32),
+}
+
+impl Language {
+    /// Finds the trailing whitespace and mixed indentation in the given source, which
+    /// must have been parsed into `tree` with this language's grammar.
+    ///
+    /// Whether the language is indented with tabs or spaces is determined by its
+    /// `hard_tabs` setting. Whitespace within the nodes captured as `@string` or
+    /// `@comment` in the language's overrides query is ignored.
+    pub fn whitespace_issues(&self, tree: &Tree, source: &Rope) -> Vec<WhitespaceIssue> {
+        let text = source.to_string();
+        let ignored_ranges = self.scope_ranges(tree, &text, &["string", "comment"]);
+        let hard_tabs = self.config.hard_tabs.unwrap_or(false);
+
+        let mut issues = Vec::new();
+        let mut line_start = 0;
+        for line in text.split('\n') {
+            let content = line.trim_end_matches([' ', '\t']);
+            if content.len() < line.len() {
+                let start = line_start + content.len();
+                if !range_contains(&ignored_ranges, start) {
+                    issues.push(WhitespaceIssue::TrailingWhitespace(
+                        start..line_start + line.len(),
+                    ));
+                }
+            }
+
+            let indent_len = content.len() - content.trim_start_matches([' ', '\t']).len();
+            let indent = &content[..indent_len];
+            let is_mixed = if hard_tabs {
+                indent.trim_start_matches('\t').contains('\t')
+            } else {
+                indent.contains('\t')
+            };
+            if is_mixed && !range_contains(&ignored_ranges, line_start) {
+                issues.push(WhitespaceIssue::MixedIndentation(
+                    line_start..line_start + indent_len,
+                ));
+            }
+
+            line_start += line.len() + 1;
+        }
+        issues
+    }
+
+    /// Detects the unit of indentation used by the given text from the leading whitespace
+    /// of its lines, so that it can be preferred over the language's `hard_tabs` and
+    /// `tab_size` settings when they disagree with the text.
+    ///
+    /// The text is indented with tabs if most of its indented lines start with a tab.
+    /// Otherwise, the number of spaces is the most common difference between the
+    /// indentation of consecutive lines. Lines that start within nodes captured as
+    /// `@string` or `@comment` in the language's overrides query are ignored. Returns
+    /// `None` if the text has no indented lines.
+    pub fn detect_indentation(&self, source: &str) -> Option<DetectedIndent> {
+        let ignored_ranges = match self.grammar.as_ref() {
+            Some(grammar) if self.parse_outcome(source.len(), None) == ParseOutcome::Parsed => {
+                let tree = grammar.parse_text(&Rope::from(source), None);
+                self.scope_ranges(&tree, source, &["string", "comment"])
+            }
+            _ => Vec::new(),
+        };
+
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        let mut indent_deltas = HashMap::<usize, usize>::default();
+        let mut previous_indent = 0;
+        let mut line_start = 0;
+        for line in source.split('\n') {
+            let is_ignored = line_start > 0 && range_contains(&ignored_ranges, line_start - 1);
+            line_start += line.len() + 1;
+            if is_ignored || line.trim().is_empty() {
+                continue;
+            }
+
+            let indent = if line.starts_with('\t') {
+                tab_lines += 1;
+                0
+            } else {
+                let indent = line.len() - line.trim_start_matches(' ').len();
+                if indent > 0 {
+                    space_lines += 1;
+                }
+                indent
+            };
+            let delta = indent.abs_diff(previous_indent);
+            if delta > 0 {
+                *indent_deltas.entry(delta).or_default() += 1;
+            }
+            previous_indent = indent;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            None
+        } else if tab_lines >= space_lines {
+            Some(DetectedIndent::Tabs)
+        } else {
+            // Among equally common deltas, the smallest one is the unit.
+            let (delta, _) = indent_deltas
+                .into_iter()
+                .max_by_key(|(delta, count)| (*count, std::cmp::Reverse(*delta)))?;
+            Some(DetectedIndent::Spaces(NonZeroU32::new(delta as u32)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LanguageConfig;
+    use tree_sitter::Parser;
+
+    #[test]
+    fn test_whitespace_issues() {
+        let language = |hard_tabs| {
+            Language::new(
+                LanguageConfig {
+                    hard_tabs: Some(hard_tabs),
+                    ..Default::default()
+                },
+                Some(tree_sitter_rust::language()),
+            )
+            .with_override_query(
+                r#"
+                [(line_comment) (block_comment)] @comment
+                (string_literal) @string
+                "#,
+            )
+            .unwrap()
+        };
+        let parse = |source: &str| {
+            let mut parser = Parser::new();
+            parser.set_language(&tree_sitter_rust::language()).unwrap();
+            parser.parse(source, None).unwrap()
+        };
+
+        // Whitespace within strings and comments is ignored.
+        let source = "fn a() {  \n    \tb(); // c  \n    let d = \"e  \n\tf\";\n}\n";
+        assert_eq!(
+            language(false).whitespace_issues(&parse(source), &Rope::from(source)),
+            &[
+                WhitespaceIssue::TrailingWhitespace(8..10),
+                WhitespaceIssue::MixedIndentation(11..16),
+            ]
+        );
+
+        // With hard tabs, only spaces before tabs are mixed, as spaces can follow tabs
+        // for alignment.
+        let source = "fn a() {\n\t  b();\n  \tc();\n}\n";
+        assert_eq!(
+            language(true).whitespace_issues(&parse(source), &Rope::from(source)),
+            &[WhitespaceIssue::MixedIndentation(17..20)]
+        );
+    }
+
+    #[test]
+    fn test_detect_indentation() {
+        let language = Language::new(
+            LanguageConfig::default(),
+            Some(tree_sitter_rust::language()),
+        )
+        .with_override_query(
+            r#"
+            [(line_comment) (block_comment)] @comment
+            (string_literal) @string
+            "#,
+        )
+        .unwrap();
+
+        // A file that's mostly indented with tabs.
+        let source = "fn a() {\n\tif b {\n\t\tc();\n\t}\n    d();\n}\n";
+        assert_eq!(
+            language.detect_indentation(source),
+            Some(DetectedIndent::Tabs)
+        );
+
+        // A file indented with two spaces, whose differently indented comments and
+        // strings are ignored.
+        let source = concat!(
+            "fn a() {\n",
+            "  /*\n",
+            "      b\n",
+            "      c\n",
+            "          d\n",
+            "  */\n",
+            "  if e {\n",
+            "    f(\"\n",
+            "        g\n",
+            "            h\");\n",
+            "  }\n",
+            "}\n",
+        );
+        assert_eq!(
+            language.detect_indentation(source),
+            Some(DetectedIndent::Spaces(NonZeroU32::new(2).unwrap()))
+        );
+
+        assert_eq!(language.detect_indentation("fn a() {}\n"), None);
+    }
+}
@@ -0,0 +1,9 @@
+fn main() {
+    // The directory that the language configs and queries are embedded from. Forks can
+    // point this at their own bundle, and tests at a fixtures directory, by building with
+    // e.g. `ZED_LANGUAGES_DIR=tests/fixtures/languages`. Relative paths are resolved
+    // against this crate's directory.
+    println!("cargo:rerun-if-env-changed=ZED_LANGUAGES_DIR");
+    let languages_dir = std::env::var("ZED_LANGUAGES_DIR").unwrap_or_else(|_| "src/".into());
+    println!("cargo:rustc-env=ZED_LANGUAGES_DIR={languages_dir}");
+}
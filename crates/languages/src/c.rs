@@ -332,10 +332,14 @@ async fn get_cached_server_binary(container_dir: PathBuf) -> Option<LanguageServ
 
 #[cfg(test)]
 mod tests {
-    use gpui::{BorrowAppContext, Context, TestAppContext};
-    use language::{language_settings::AllLanguageSettings, AutoindentMode, Buffer};
+    use gpui::{BorrowAppContext, Context, Task, TestAppContext};
+    use language::{
+        language_settings::AllLanguageSettings, AutoindentMode, Buffer, LanguageRegistry,
+    };
     use settings::SettingsStore;
-    use std::num::NonZeroU32;
+    use std::{num::NonZeroU32, path::Path, sync::Arc};
+    use text::Rope;
+    use unindent::Unindent as _;
 
     #[gpui::test]
     async fn test_c_autoindent(cx: &mut TestAppContext) {
@@ -376,4 +380,73 @@ mod tests {
             buffer
         });
     }
+
+    #[gpui::test]
+    async fn test_header_detection(cx: &mut TestAppContext) {
+        let languages = Arc::new(LanguageRegistry::new(Task::ready(()), cx.executor()));
+        languages.register_native_grammars([
+            ("c", tree_sitter_c::language()),
+            ("cpp", tree_sitter_cpp::language()),
+        ]);
+        for name in ["c", "cpp"] {
+            let config = crate::load_config(name);
+            languages.register_language(
+                config.name.clone(),
+                config.id.clone(),
+                config.grammar.clone(),
+                config.matcher.clone(),
+                move || Ok((config.clone(), Default::default(), None)),
+            );
+        }
+
+        let c_header = r#"
+            #ifndef POINT_H
+            #define POINT_H
+            struct point { int x, y; };
+            #endif
+        "#
+        .unindent();
+        let cpp_header = r#"
+            #pragma once
+            #include <vector>
+            namespace geometry {
+            class Polygon {
+            public:
+                std::vector<int> points;
+            };
+            }
+        "#
+        .unindent();
+        let template_header = "template <typename T>\nT max(T a, T b);\n";
+
+        for (content, expected_language) in [
+            (Some(c_header.as_str()), "C"),
+            (Some(cpp_header.as_str()), "C++"),
+            (Some(template_header), "C++"),
+            // Without any C++ features to go by, headers are assumed to be C.
+            (Some("int add(int a, int b);\n"), "C"),
+            (Some(""), "C"),
+            (None, "C"),
+        ] {
+            let content = content.map(Rope::from);
+            let detection = languages
+                .explain_detection(Path::new("src/point.h"), content.as_ref())
+                .await
+                .unwrap();
+            assert_eq!(
+                detection.language.unwrap().name().as_ref(),
+                expected_language,
+                "{content:?}"
+            );
+        }
+
+        // Source files aren't affected by their content.
+        let language = languages
+            .explain_detection(Path::new("src/point.c"), Some(&Rope::from(cpp_header)))
+            .await
+            .unwrap()
+            .language
+            .unwrap();
+        assert_eq!(language.name().as_ref(), "C");
+    }
 }
@@ -0,0 +1,17 @@
+(comment) @comment
+
+[
+  (addition)
+  (new_file)
+] @diff.plus
+
+[
+  (deletion)
+  (old_file)
+] @diff.minus
+
+(location) @attribute
+
+(commit) @constant
+
+(command) @keyword
@@ -0,0 +1,60 @@
+[
+  "FROM"
+  "AS"
+  "RUN"
+  "CMD"
+  "LABEL"
+  "EXPOSE"
+  "ENV"
+  "ADD"
+  "COPY"
+  "ENTRYPOINT"
+  "VOLUME"
+  "USER"
+  "WORKDIR"
+  "ARG"
+  "ONBUILD"
+  "STOPSIGNAL"
+  "HEALTHCHECK"
+  "SHELL"
+  "MAINTAINER"
+  "CROSS_BUILD"
+] @keyword
+
+[
+  ":"
+  "@"
+] @operator
+
+(comment) @comment
+
+(image_spec
+  (image_tag
+    ":" @punctuation.special)
+  (image_digest
+    "@" @punctuation.special))
+
+(image_name) @type
+(image_alias) @type
+
+(param) @attribute
+
+(path) @string.special
+
+(double_quoted_string) @string
+
+(string_array
+  [
+    "["
+    "]"
+  ] @punctuation.bracket)
+
+(expansion
+  [
+    "$"
+    "{"
+    "}"
+  ] @punctuation.special)
+
+((variable) @constant
+  (#match? @constant "^[A-Z][A-Z_0-9]*$"))
@@ -7,9 +7,9 @@ use python::PythonContextProvider;
 use rust_embed::RustEmbed;
 use settings::SettingsStore;
 use smol::stream::StreamExt;
-use std::{str, sync::Arc};
+use std::{borrow::Cow, str, sync::Arc};
 use typescript::typescript_task_context;
-use util::{asset_str, ResultExt};
+use util::ResultExt;
 
 use crate::{bash::bash_task_context, go::GoContextProvider, rust::RustContextProvider};
 
@@ -25,8 +25,10 @@ mod typescript;
 mod vtsls;
 mod yaml;
 
+/// The language configs and queries, embedded from `src/` unless the `ZED_LANGUAGES_DIR`
+/// environment variable names another directory at build time (see `build.rs`).
 #[derive(RustEmbed)]
-#[folder = "src/"]
+#[folder = "$ZED_LANGUAGES_DIR"]
 #[exclude = "*.rs"]
 struct LanguageDir;
 
@@ -40,6 +42,8 @@ pub fn init(
         ("c", tree_sitter_c::language()),
         ("cpp", tree_sitter_cpp::language()),
         ("css", tree_sitter_css::language()),
+        ("diff", diff_grammar()),
+        ("dockerfile", tree_sitter_dockerfile::language()),
         ("go", tree_sitter_go::language()),
         ("gomod", tree_sitter_gomod::language()),
         ("gowork", tree_sitter_gowork::language()),
@@ -60,9 +64,10 @@ pub fn init(
             let config = load_config($name);
             languages.register_language(
                 config.name.clone(),
+                config.id.clone(),
                 config.grammar.clone(),
                 config.matcher.clone(),
-                move || Ok((config.clone(), load_queries($name), None)),
+                move || Ok((config.clone(), load_queries($name, &config), None)),
             );
         };
         ($name:literal, $adapters:expr) => {
@@ -74,9 +79,10 @@ pub fn init(
             }
             languages.register_language(
                 config.name.clone(),
+                config.id.clone(),
                 config.grammar.clone(),
                 config.matcher.clone(),
-                move || Ok((config.clone(), load_queries($name), None)),
+                move || Ok((config.clone(), load_queries($name, &config), None)),
             );
         };
         ($name:literal, $adapters:expr, $context_provider:expr) => {
@@ -88,12 +94,13 @@ pub fn init(
             }
             languages.register_language(
                 config.name.clone(),
+                config.id.clone(),
                 config.grammar.clone(),
                 config.matcher.clone(),
                 move || {
                     Ok((
                         config.clone(),
-                        load_queries($name),
+                        load_queries($name, &config),
                         Some(Arc::new($context_provider)),
                     ))
                 },
@@ -107,6 +114,8 @@ pub fn init(
         "css",
         vec![Arc::new(css::CssLspAdapter::new(node_runtime.clone())),]
     );
+    language!("diff");
+    language!("dockerfile");
     language!("go", vec![Arc::new(go::GoLspAdapter)], GoContextProvider);
     language!("gomod", vec![Arc::new(go::GoLspAdapter)], GoContextProvider);
     language!(
@@ -254,11 +263,22 @@ pub fn init(
     .detach();
 }
 
+/// Returns the Diff grammar. Its crate targets a newer tree-sitter, which exposes the
+/// grammar's C function rather than a [`tree_sitter::Language`].
+fn diff_grammar() -> tree_sitter::Language {
+    let language_fn = tree_sitter_diff::LANGUAGE.into_raw();
+    // SAFETY: the function is generated by the tree-sitter CLI and returns a pointer to
+    // a static `TSLanguage`, which `tree_sitter::Language` transparently wraps.
+    unsafe { std::mem::transmute::<*const (), tree_sitter::Language>(language_fn()) }
+}
+
 #[cfg(any(test, feature = "test-support"))]
 pub fn language(name: &str, grammar: tree_sitter::Language) -> Arc<Language> {
+    let config = load_config(name);
+    let queries = load_queries(name, &config);
     Arc::new(
-        Language::new(load_config(name), Some(grammar))
-            .with_queries(load_queries(name))
+        Language::new(config, Some(grammar))
+            .with_queries(queries)
             .unwrap(),
     )
 }
@@ -277,16 +297,21 @@ fn load_config(name: &str) -> LanguageConfig {
         .unwrap()
 }
 
-fn load_queries(name: &str) -> LanguageQueries {
+fn load_queries(name: &str, config: &LanguageConfig) -> LanguageQueries {
     let mut result = LanguageQueries::default();
     for path in LanguageDir::iter() {
         if let Some(remainder) = path.strip_prefix(name).and_then(|p| p.strip_prefix('/')) {
             if !remainder.ends_with(".scm") {
                 continue;
             }
-            for (name, query) in QUERY_FILENAME_PREFIXES {
-                if remainder.starts_with(name) {
-                    let contents = asset_str::<LanguageDir>(path.as_ref());
+            for (query_name, query) in QUERY_FILENAME_PREFIXES {
+                if *query_name == "highlights" && !config.highlight_queries.is_empty() {
+                    continue;
+                }
+                if remainder.starts_with(query_name) {
+                    let contents =
+                        load_query_source(name, remainder, &mut Vec::new(), &query_source)
+                            .unwrap_or_default();
                     match query(&mut result) {
                         None => *query(&mut result) = Some(contents),
                         Some(r) => r.to_mut().push_str(contents.as_ref()),
@@ -295,5 +320,237 @@ fn load_queries(name: &str) -> LanguageQueries {
             }
         }
     }
+
+    for file_name in &config.highlight_queries {
+        let Some(contents) = load_query_source(name, file_name, &mut Vec::new(), &query_source)
+        else {
+            log::error!("highlight query {name}/{file_name} not found");
+            continue;
+        };
+        match &mut result.highlights {
+            None => result.highlights = Some(contents),
+            Some(highlights) => {
+                let highlights = highlights.to_mut();
+                highlights.push('\n');
+                highlights.push_str(&contents);
+            }
+        }
+    }
     result
 }
+
+/// Loads the query file with the given name for a language, expanding its
+/// `; inherits: <language>, ...` directive by prepending the file of the same
+/// name from each of the inherited languages.
+///
+/// Query files are read with `read`, which returns `None` for missing files.
+/// Returns `None` if the language's own file is missing. Inherited files that are
+/// missing, or that would be inherited cyclically, are skipped.
+fn load_query_source(
+    language: &str,
+    file_name: &str,
+    ancestors: &mut Vec<String>,
+    read: &dyn Fn(&str) -> Option<Cow<'static, str>>,
+) -> Option<Cow<'static, str>> {
+    let contents = read(&format!("{language}/{file_name}"))?;
+    let inherited = query_inherits(&contents);
+    if inherited.is_empty() {
+        return Some(contents);
+    }
+
+    ancestors.push(language.to_string());
+    let mut result = String::new();
+    for parent in inherited {
+        if ancestors.iter().any(|ancestor| ancestor == parent) {
+            log::error!("query {language}/{file_name} has an inheritance cycle through {parent}");
+            continue;
+        }
+        let Some(parent_contents) = load_query_source(parent, file_name, ancestors, read) else {
+            log::error!(
+                "query {language}/{file_name} inherits from missing query {parent}/{file_name}"
+            );
+            continue;
+        };
+        result.push_str(&parent_contents);
+        result.push('\n');
+    }
+    ancestors.pop();
+    result.push_str(&contents);
+    Some(result.into())
+}
+
+/// Returns the decoded contents of the embedded query file at the given path, or
+/// `None` if there's no such file. Files that can't be decoded are logged and
+/// treated as empty queries.
+fn query_source(path: &str) -> Option<Cow<'static, str>> {
+    let decoded = match LanguageDir::get(path)?.data {
+        Cow::Borrowed(bytes) => decode_source(bytes),
+        Cow::Owned(bytes) => decode_source(&bytes).map(|source| Cow::Owned(source.into_owned())),
+    };
+    Some(
+        decoded
+            .with_context(|| format!("failed to decode query {path:?}"))
+            .log_err()
+            .unwrap_or_default(),
+    )
+}
+
+/// Returns the languages named in the `; inherits:` directive at the start of a query.
+fn query_inherits(query: &str) -> Vec<&str> {
+    let mut inherited = Vec::new();
+    for line in query.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(comment) = line.strip_prefix(';') else {
+            break;
+        };
+        if let Some(languages) = comment
+            .trim_start_matches(';')
+            .trim()
+            .strip_prefix("inherits:")
+        {
+            inherited.extend(
+                languages
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|language| !language.is_empty()),
+            );
+        }
+    }
+    inherited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::{HighlightStyle, TestAppContext};
+    use std::path::Path;
+    use text::Rope;
+    use theme::SyntaxTheme;
+
+    #[test]
+    fn test_query_inherits() {
+        assert_eq!(
+            query_inherits("; inherits: javascript, jsx\n\n(identifier) @variable\n"),
+            vec!["javascript", "jsx"]
+        );
+        assert_eq!(
+            query_inherits("; Comment\n;; inherits: c\n(identifier) @variable\n"),
+            vec!["c"]
+        );
+        assert!(query_inherits("(identifier) @variable\n; inherits: c\n").is_empty());
+        assert!(query_inherits("").is_empty());
+    }
+
+    #[test]
+    fn test_load_query_source_with_inheritance() {
+        let files = [
+            ("c/highlights.scm", "(identifier) @variable"),
+            ("cpp/highlights.scm", "; inherits: c\n\"this\" @keyword"),
+            // Missing parents and languages that inherit from themselves are skipped.
+            (
+                "objc/highlights.scm",
+                "; inherits: cpp, swift, objc\n\"@interface\" @keyword",
+            ),
+            ("a/highlights.scm", "; inherits: b\n(a) @a"),
+            ("b/highlights.scm", "; inherits: a\n(b) @b"),
+        ];
+        let read = |path: &str| {
+            files
+                .iter()
+                .find(|(file_path, _)| *file_path == path)
+                .map(|(_, contents)| Cow::Borrowed(*contents))
+        };
+        let load =
+            |language: &str| load_query_source(language, "highlights.scm", &mut Vec::new(), &read);
+
+        assert_eq!(load("c").unwrap(), "(identifier) @variable");
+        assert_eq!(
+            load("cpp").unwrap(),
+            "(identifier) @variable\n; inherits: c\n\"this\" @keyword"
+        );
+        assert_eq!(
+            load("objc").unwrap(),
+            concat!(
+                "(identifier) @variable\n; inherits: c\n\"this\" @keyword\n",
+                "; inherits: cpp, swift, objc\n\"@interface\" @keyword"
+            )
+        );
+
+        // Cycles are broken where they close.
+        assert_eq!(
+            load("a").unwrap(),
+            "; inherits: a\n(b) @b\n; inherits: b\n(a) @a"
+        );
+        assert_eq!(load("missing"), None);
+    }
+
+    #[test]
+    fn test_load_queries_with_highlight_queries() {
+        let config = LanguageConfig {
+            highlight_queries: vec![
+                "injections.scm".into(),
+                "missing.scm".into(),
+                "highlights.scm".into(),
+            ],
+            ..load_config("rust")
+        };
+        let queries = load_queries("rust", &config);
+        let injections = query_source("rust/injections.scm").unwrap();
+        let highlights = query_source("rust/highlights.scm").unwrap();
+        // The listed files are joined in order, skipping missing ones.
+        assert_eq!(
+            queries.highlights.as_deref(),
+            Some(format!("{injections}\n{highlights}").as_str())
+        );
+        assert_eq!(queries.injections.as_deref(), Some(injections.as_ref()));
+    }
+
+    #[gpui::test]
+    async fn test_diff_language(cx: &mut TestAppContext) {
+        let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+        languages.register_native_grammars([("diff", diff_grammar())]);
+        let config = load_config("diff");
+        languages.register_language(
+            config.name.clone(),
+            config.id.clone(),
+            config.grammar.clone(),
+            config.matcher.clone(),
+            move || Ok((config.clone(), load_queries("diff", &config), None)),
+        );
+
+        let language = languages
+            .language_for_file_path(Path::new("changes.patch"))
+            .await
+            .unwrap();
+        assert_eq!(language.name().as_ref(), "Diff");
+
+        // Added and removed lines are highlighted with the theme's backgrounds.
+        let removed = HighlightStyle {
+            background_color: Some(gpui::red()),
+            ..Default::default()
+        };
+        let added = HighlightStyle {
+            background_color: Some(gpui::green()),
+            ..Default::default()
+        };
+        let theme = SyntaxTheme {
+            highlights: vec![("diff.minus".into(), removed), ("diff.plus".into(), added)],
+            ..Default::default()
+        };
+        language.set_theme(&theme);
+
+        let text = "@@ -1 +1 @@\n-a\n+b\n";
+        let styles = language.highlight_styles(&Rope::from(text), &theme);
+        let style_at = |offset| {
+            styles
+                .iter()
+                .find(|(range, _)| range.contains(&offset))
+                .map(|(_, style)| *style)
+        };
+        assert_eq!(style_at(text.find("-a").unwrap()), Some(removed));
+        assert_eq!(style_at(text.find("+b").unwrap()), Some(added));
+    }
+}
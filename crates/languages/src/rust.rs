@@ -901,6 +901,61 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_rust_comment_prefixes() {
+        let language = language("rust", tree_sitter_rust::language());
+        assert_eq!(
+            language.documentation_comment().map(AsRef::as_ref),
+            Some("/// ")
+        );
+        // Toggling comments inserts regular comments, rather than documentation comments.
+        assert_eq!(
+            language
+                .default_scope()
+                .toggle_comment_prefix()
+                .map(AsRef::as_ref),
+            Some("// ")
+        );
+
+        let language = Arc::new(Language::new(
+            LanguageConfig {
+                line_comments: vec!["/// ".into(), "// ".into()],
+                documentation_comment: Some("/// ".into()),
+                ..Default::default()
+            },
+            None,
+        ));
+        assert_eq!(
+            language
+                .default_scope()
+                .toggle_comment_prefix()
+                .map(AsRef::as_ref),
+            Some("// ")
+        );
+    }
+
+    #[test]
+    fn test_rust_highlight_capture_indices() {
+        let language = language("rust", tree_sitter_rust::language());
+        let index = language.highlight_capture_index("keyword").unwrap();
+        assert_eq!(language.highlight_capture_name(index), Some("keyword"));
+        assert_eq!(language.highlight_capture_index("not-a-capture"), None);
+        assert_eq!(language.highlight_capture_name(u32::MAX), None);
+    }
+
+    #[test]
+    fn test_rust_highlight_query_source() {
+        let language = language("rust", tree_sitter_rust::language());
+        assert_eq!(
+            language.highlight_query_source(),
+            crate::query_source("rust/highlights.scm").as_deref()
+        );
+        assert_eq!(
+            Language::new(LanguageConfig::default(), None).highlight_query_source(),
+            None
+        );
+    }
+
     #[test]
     fn test_package_name_from_pkgid() {
         for (input, expected) in [
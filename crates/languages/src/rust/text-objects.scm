@@ -0,0 +1,37 @@
+(function_item
+  body: (_) @function.inner) @function.outer
+
+(function_signature_item) @function.outer
+
+(closure_expression
+  body: (_) @function.inner) @function.outer
+
+(parameters
+  (_) @parameter.inner)
+
+(closure_parameters
+  (_) @parameter.inner)
+
+(type_parameters
+  (_) @parameter.inner)
+
+(arguments
+  (_) @parameter.inner)
+
+(block) @block.outer
+
+[
+  (struct_item
+    body: (_) @class.inner)
+  (enum_item
+    body: (_) @class.inner)
+  (trait_item
+    body: (_) @class.inner)
+  (impl_item
+    body: (_) @class.inner)
+] @class.outer
+
+[
+  (line_comment)
+  (block_comment)
+] @comment.outer
@@ -3684,6 +3684,9 @@ impl MultiBufferSnapshot {
                         text: item.text,
                         highlight_ranges: item.highlight_ranges,
                         name_ranges: item.name_ranges,
+                        kind: item.kind,
+                        start_point: item.start_point,
+                        end_point: item.end_point,
                     })
                 })
                 .collect(),
@@ -3713,6 +3716,9 @@ impl MultiBufferSnapshot {
                         text: item.text,
                         highlight_ranges: item.highlight_ranges,
                         name_ranges: item.name_ranges,
+                        kind: item.kind,
+                        start_point: item.start_point,
+                        end_point: item.end_point,
                     })
                 })
                 .collect(),
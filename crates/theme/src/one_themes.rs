@@ -1,10 +1,10 @@
 use std::sync::Arc;
 
-use gpui::{hsla, FontStyle, FontWeight, HighlightStyle, WindowBackgroundAppearance};
+use gpui::{hsla, FontStyle, FontWeight, HighlightStyle, Hsla, WindowBackgroundAppearance};
 
 use crate::{
-    default_color_scales, AccentColors, Appearance, PlayerColors, StatusColors, SyntaxTheme,
-    SystemColors, Theme, ThemeColors, ThemeFamily, ThemeStyles,
+    default_color_scales, AccentColors, Appearance, DiagnosticStyle, PlayerColors, StatusColors,
+    SyntaxTheme, SystemColors, Theme, ThemeColors, ThemeFamily, ThemeStyles,
 };
 
 // Note: This theme family is not the one you see in Zed at the moment.
@@ -196,6 +196,20 @@ pub(crate) fn one_dark() -> Theme {
                     ("comment.doc".into(), gray.into()),
                     ("constant".into(), yellow.into()),
                     ("constructor".into(), blue.into()),
+                    (
+                        "diff.minus".into(),
+                        HighlightStyle {
+                            background_color: Some(Hsla { a: 0.2, ..red }),
+                            ..HighlightStyle::default()
+                        },
+                    ),
+                    (
+                        "diff.plus".into(),
+                        HighlightStyle {
+                            background_color: Some(Hsla { a: 0.2, ..green }),
+                            ..HighlightStyle::default()
+                        },
+                    ),
                     ("embedded".into(), HighlightStyle::default()),
                     (
                         "emphasis".into(),
@@ -251,6 +265,11 @@ pub(crate) fn one_dark() -> Theme {
                     ("variable.special".into(), red.into()),
                     ("variant".into(), HighlightStyle::default()),
                 ],
+                diagnostic_style: DiagnosticStyle {
+                    error: Some(red),
+                    warning: Some(yellow),
+                },
+                ..Default::default()
             }),
         },
     }
@@ -1,20 +1,24 @@
 use std::sync::Arc;
-use std::{fmt::Debug, path::Path};
+use std::time::Duration;
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{anyhow, Context, Result};
-use collections::HashMap;
+use anyhow::{anyhow, bail, Context, Result};
+use collections::{HashMap, HashSet};
 use derive_more::{Deref, DerefMut};
 use fs::Fs;
 use futures::StreamExt;
-use gpui::{AppContext, AssetSource, Global, HighlightStyle, SharedString};
+use gpui::{AppContext, AssetSource, Global, HighlightStyle, SharedString, Task};
 use parking_lot::RwLock;
 use refineable::Refineable;
-use util::ResultExt;
+use util::{merge_non_null_json_value_into, ResultExt};
 
 use crate::{
-    try_parse_color, AccentColors, Appearance, AppearanceContent, PlayerColors, StatusColors,
-    SyntaxTheme, SystemColors, Theme, ThemeColors, ThemeContent, ThemeFamily, ThemeFamilyContent,
-    ThemeStyles,
+    try_parse_color, AccentColors, Appearance, AppearanceContent, DiagnosticStyle, PlayerColors,
+    StatusColors, SyntaxTheme, SystemColors, Theme, ThemeColors, ThemeContent, ThemeFamily,
+    ThemeFamilyContent, ThemeSettings, ThemeStyleContent, ThemeStyles,
 };
 
 #[derive(Debug, Clone)]
@@ -36,6 +40,9 @@ impl Global for GlobalThemeRegistry {}
 
 struct ThemeRegistryState {
     themes: HashMap<SharedString, Arc<Theme>>,
+    /// The unresolved contents of the user themes, used to resolve themes that
+    /// inherit from a `parent`.
+    theme_contents: HashMap<SharedString, ThemeContent>,
 }
 
 pub struct ThemeRegistry {
@@ -65,6 +72,7 @@ impl ThemeRegistry {
         let registry = Self {
             state: RwLock::new(ThemeRegistryState {
                 themes: HashMap::default(),
+                theme_contents: HashMap::default(),
             }),
             assets,
         };
@@ -99,7 +107,49 @@ impl ThemeRegistry {
         }
     }
 
+    /// Adds the given user themes to the registry, resolving their inheritance.
+    ///
+    /// Themes are loaded one file at a time, so the themes that inherit from the given
+    /// ones are resolved again too. This includes those that were added before their
+    /// parents, and those whose parents are being reloaded.
     pub fn insert_user_themes(&self, themes: impl IntoIterator<Item = ThemeContent>) {
+        let themes = {
+            let mut state = self.state.write();
+            let mut names = HashSet::default();
+            for theme in themes {
+                let name = SharedString::from(theme.name.clone());
+                state.theme_contents.insert(name.clone(), theme);
+                names.insert(name);
+            }
+            loop {
+                let dependents = state
+                    .theme_contents
+                    .iter()
+                    .filter(|(name, theme)| {
+                        !names.contains(*name)
+                            && theme
+                                .parent
+                                .as_ref()
+                                .map_or(false, |parent| names.contains(parent.as_str()))
+                    })
+                    .map(|(name, _)| name.clone())
+                    .collect::<Vec<_>>();
+                if dependents.is_empty() {
+                    break;
+                }
+                names.extend(dependents);
+            }
+            names
+                .iter()
+                .filter_map(|name| state.theme_contents.get(name).cloned())
+                .collect::<Vec<_>>()
+        };
+
+        let themes = themes
+            .into_iter()
+            .filter_map(|theme| self.resolve_theme_content(theme).log_err())
+            .collect::<Vec<_>>();
+
         self.insert_themes(themes.into_iter().map(|user_theme| {
             let mut theme_colors = match user_theme.appearance {
                 AppearanceContent::Light => ThemeColors::light(),
@@ -148,8 +198,16 @@ impl ThemeRegistry {
                     )
                 })
                 .collect::<Vec<_>>();
-            let syntax_theme =
-                SyntaxTheme::merge(Arc::new(SyntaxTheme::default()), syntax_highlights);
+            let base_syntax_theme = SyntaxTheme {
+                node_kind_styles: user_theme.style.node_kind_highlight_styles(),
+                injected_opacity: user_theme.style.injected_opacity,
+                diagnostic_style: DiagnosticStyle {
+                    error: Some(status_colors.error),
+                    warning: Some(status_colors.warning),
+                },
+                ..Default::default()
+            };
+            let syntax_theme = SyntaxTheme::merge(Arc::new(base_syntax_theme), syntax_highlights);
 
             let window_background_appearance = user_theme
                 .style
@@ -177,16 +235,66 @@ impl ThemeRegistry {
         }));
     }
 
+    /// Resolves the inheritance chain of the given theme.
+    ///
+    /// The returned theme's style is the style of its root ancestor, with the style of
+    /// each descendant applied on top of it in turn, ending with the given theme's own.
+    pub fn resolve_theme_content(&self, theme: ThemeContent) -> Result<ThemeContent> {
+        if theme.parent.is_none() {
+            return Ok(theme);
+        }
+
+        let state = self.state.read();
+        let mut visited = HashSet::default();
+        visited.insert(theme.name.clone());
+        let mut ancestors = Vec::new();
+        let mut parent_name = theme.parent.clone();
+        while let Some(name) = parent_name {
+            if !visited.insert(name.clone()) {
+                bail!(
+                    "theme \"{}\" has an inheritance cycle through \"{}\"",
+                    theme.name,
+                    name
+                );
+            }
+            let parent = state.theme_contents.get(name.as_str()).ok_or_else(|| {
+                anyhow!(
+                    "parent theme \"{}\" of theme \"{}\" not found",
+                    name,
+                    theme.name
+                )
+            })?;
+            parent_name = parent.parent.clone();
+            ancestors.push(parent);
+        }
+
+        let mut style = serde_json::Value::Null;
+        for ancestor in ancestors.iter().rev() {
+            merge_style_into(&ancestor.style, &mut style)?;
+        }
+        merge_style_into(&theme.style, &mut style)?;
+
+        Ok(ThemeContent {
+            style: serde_json::from_value(style)?,
+            ..theme
+        })
+    }
+
     /// Removes the themes with the given names from the registry.
     pub fn remove_user_themes(&self, themes_to_remove: &[SharedString]) {
-        self.state
-            .write()
+        let mut state = self.state.write();
+        state
             .themes
-            .retain(|name, _| !themes_to_remove.contains(name))
+            .retain(|name, _| !themes_to_remove.contains(name));
+        state
+            .theme_contents
+            .retain(|name, _| !themes_to_remove.contains(name));
     }
 
     pub fn clear(&mut self) {
-        self.state.write().themes.clear();
+        let mut state = self.state.write();
+        state.themes.clear();
+        state.theme_contents.clear();
     }
 
     pub fn list_names(&self, _staff: bool) -> Vec<SharedString> {
@@ -290,6 +398,54 @@ impl ThemeRegistry {
 
         Ok(())
     }
+
+    /// Loads the user theme from the specified path, and reloads it and the current
+    /// theme whenever the file changes, so that edits to the theme can be previewed live.
+    ///
+    /// Errors loading the theme are passed to `on_error`, and the file continues to be
+    /// watched. Dropping the returned task stops watching the file.
+    pub fn watch_theme_file(
+        self: &Arc<Self>,
+        theme_path: PathBuf,
+        fs: Arc<dyn Fs>,
+        on_error: impl Fn(anyhow::Error) + 'static,
+        cx: &mut AppContext,
+    ) -> Task<()> {
+        let this = self.clone();
+        cx.spawn(|cx| async move {
+            let (mut events, _watcher) = fs.watch(&theme_path, Duration::from_millis(100)).await;
+            let mut changed = true;
+            loop {
+                if changed && fs.metadata(&theme_path).await.ok().flatten().is_some() {
+                    match this.load_user_theme(&theme_path, fs.clone()).await {
+                        Ok(()) => {
+                            if cx.update(ThemeSettings::reload_current_theme).is_err() {
+                                break;
+                            }
+                        }
+                        Err(error) => on_error(error),
+                    }
+                }
+
+                let Some(paths) = events.next().await else {
+                    break;
+                };
+                changed = !paths.is_empty();
+            }
+        })
+    }
+}
+
+/// Merges the given style into the `target` JSON value, skipping unset properties.
+fn merge_style_into(style: &ThemeStyleContent, target: &mut serde_json::Value) -> Result<()> {
+    let mut style = serde_json::to_value(style)?;
+    // An empty list of accents or players means that none were specified, so
+    // we don't want it to replace the ones being inherited.
+    if let serde_json::Value::Object(properties) = &mut style {
+        properties.retain(|_, value| value.as_array().map_or(true, |array| !array.is_empty()));
+    }
+    merge_non_null_json_value_into(style, target);
+    Ok(())
 }
 
 impl Default for ThemeRegistry {
@@ -297,3 +453,188 @@ impl Default for ThemeRegistry {
         Self::new(Box::new(()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::FakeFs;
+    use gpui::TestAppContext;
+    use serde_json::json;
+    use settings::SettingsStore;
+
+    fn theme_content(value: serde_json::Value) -> ThemeContent {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_theme_inheritance() {
+        let registry = ThemeRegistry::default();
+        registry.insert_user_themes([
+            theme_content(json!({
+                "name": "Child",
+                "appearance": "dark",
+                "parent": "Base",
+                "style": {
+                    "syntax": {
+                        "keyword": { "color": "#00ff00ff" }
+                    }
+                }
+            })),
+            theme_content(json!({
+                "name": "Base",
+                "appearance": "dark",
+                "style": {
+                    "syntax": {
+                        "keyword": { "color": "#ff0000ff", "font_weight": 700 },
+                        "string": { "color": "#0000ffff" }
+                    }
+                }
+            })),
+        ]);
+
+        let base = registry.get("Base").unwrap();
+        let child = registry.get("Child").unwrap();
+        assert_eq!(
+            base.syntax().get("keyword").color,
+            Some(try_parse_color("#ff0000ff").unwrap())
+        );
+        assert_eq!(
+            child.syntax().get("keyword").color,
+            Some(try_parse_color("#00ff00ff").unwrap())
+        );
+        assert_eq!(
+            child.syntax().get("keyword").font_weight,
+            base.syntax().get("keyword").font_weight
+        );
+        assert_eq!(
+            child.syntax().get("string").color,
+            Some(try_parse_color("#0000ffff").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_theme_inheritance_across_insertions() {
+        let registry = ThemeRegistry::default();
+        let base = |keyword_color: &str| {
+            theme_content(json!({
+                "name": "Base",
+                "appearance": "dark",
+                "style": {
+                    "syntax": {
+                        "keyword": { "color": keyword_color }
+                    }
+                }
+            }))
+        };
+        let keyword_color = |name: &str| registry.get(name).unwrap().syntax().color("keyword");
+
+        // A child that's inserted before its parent is resolved once the parent is.
+        registry.insert_user_themes([theme_content(json!({
+            "name": "Child",
+            "appearance": "dark",
+            "parent": "Base",
+            "style": {}
+        }))]);
+        assert!(registry.get("Child").is_err());
+        registry.insert_user_themes([base("#ff0000ff")]);
+        assert_eq!(keyword_color("Child"), keyword_color("Base"));
+
+        // Reloading the parent resolves the child again.
+        let red = keyword_color("Child");
+        registry.insert_user_themes([base("#0000ffff")]);
+        assert_ne!(keyword_color("Child"), red);
+        assert_eq!(keyword_color("Child"), keyword_color("Base"));
+    }
+
+    #[test]
+    fn test_theme_inheritance_cycle() {
+        let registry = ThemeRegistry::default();
+        let a = theme_content(json!({
+            "name": "A",
+            "appearance": "light",
+            "parent": "B",
+            "style": {}
+        }));
+        let b = theme_content(json!({
+            "name": "B",
+            "appearance": "light",
+            "parent": "A",
+            "style": {}
+        }));
+        registry.insert_user_themes([a.clone(), b]);
+
+        assert!(registry.get("A").is_err());
+        assert!(registry.get("B").is_err());
+        let error = registry.resolve_theme_content(a).unwrap_err();
+        assert!(error.to_string().contains("inheritance cycle"));
+    }
+
+    #[gpui::test]
+    async fn test_watch_theme_file(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            crate::init(crate::LoadThemes::JustBase, cx);
+            crate::SystemAppearance::init(cx);
+        });
+        let theme_family = |keyword_color: &str| {
+            json!({
+                "name": "Live",
+                "author": "Test",
+                "themes": [{
+                    "name": "Live Theme",
+                    "appearance": "dark",
+                    "style": {
+                        "syntax": {
+                            "keyword": { "color": keyword_color }
+                        }
+                    }
+                }]
+            })
+            .to_string()
+        };
+
+        let fs = FakeFs::new(cx.executor());
+        let theme_path = PathBuf::from("/themes/live.json");
+        fs.insert_file(&theme_path, theme_family("#ff0000ff").into_bytes())
+            .await;
+
+        let registry = cx.update(ThemeRegistry::global);
+        let errors = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let _watch = cx.update(|cx| {
+            let errors = errors.clone();
+            registry.watch_theme_file(
+                theme_path.clone(),
+                fs.clone(),
+                move |error| errors.lock().push(error.to_string()),
+                cx,
+            )
+        });
+        cx.run_until_parked();
+        let keyword_color = || {
+            registry
+                .get("Live Theme")
+                .unwrap()
+                .syntax()
+                .color("keyword")
+        };
+        let red = keyword_color();
+
+        fs.insert_file(&theme_path, theme_family("#0000ffff").into_bytes())
+            .await;
+        cx.run_until_parked();
+        let blue = keyword_color();
+        assert_ne!(blue, red);
+
+        // An invalid theme is reported, and the previous version is kept.
+        fs.insert_file(&theme_path, b"{ \"name\": ".to_vec()).await;
+        cx.run_until_parked();
+        assert_eq!(keyword_color(), blue);
+        assert_eq!(errors.lock().len(), 1);
+
+        fs.insert_file(&theme_path, theme_family("#ff0000ff").into_bytes())
+            .await;
+        cx.run_until_parked();
+        assert_eq!(keyword_color(), red);
+    }
+}
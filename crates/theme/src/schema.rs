@@ -65,6 +65,11 @@ pub struct ThemeFamilyContent {
 pub struct ThemeContent {
     pub name: String,
     pub appearance: AppearanceContent,
+    /// The name of a theme that this theme inherits from.
+    ///
+    /// The parent's style is applied first, and then this theme's style is applied on top of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
     pub style: ThemeStyleContent,
 }
 
@@ -90,6 +95,15 @@ pub struct ThemeStyleContent {
     /// The styles for syntax nodes.
     #[serde(default)]
     pub syntax: IndexMap<String, HighlightStyleContent>,
+
+    /// The styles for syntax tree nodes of particular kinds, such as `line_comment`,
+    /// used for languages that don't have a highlights query.
+    #[serde(default)]
+    pub node_kind_styles: IndexMap<String, HighlightStyleContent>,
+
+    /// The opacity of the colors of syntax highlights within injected languages.
+    #[serde(rename = "syntax.injected_opacity")]
+    pub injected_opacity: Option<f32>,
 }
 
 impl ThemeStyleContent {
@@ -107,34 +121,45 @@ impl ThemeStyleContent {
 
     /// Returns the syntax style overrides in the [`ThemeContent`].
     pub fn syntax_overrides(&self) -> Vec<(String, HighlightStyle)> {
-        self.syntax
-            .iter()
-            .map(|(key, style)| {
-                (
-                    key.clone(),
-                    HighlightStyle {
-                        color: style
-                            .color
-                            .as_ref()
-                            .and_then(|color| try_parse_color(color).ok()),
-                        background_color: style
-                            .background_color
-                            .as_ref()
-                            .and_then(|color| try_parse_color(color).ok()),
-                        font_style: style
-                            .font_style
-                            .map(|font_style| FontStyle::from(font_style)),
-                        font_weight: style
-                            .font_weight
-                            .map(|font_weight| FontWeight::from(font_weight)),
-                        ..Default::default()
-                    },
-                )
-            })
-            .collect()
+        highlight_styles(&self.syntax)
+    }
+
+    /// Returns the styles for syntax tree node kinds in the [`ThemeContent`].
+    pub fn node_kind_highlight_styles(&self) -> Vec<(String, HighlightStyle)> {
+        highlight_styles(&self.node_kind_styles)
     }
 }
 
+fn highlight_styles(
+    styles: &IndexMap<String, HighlightStyleContent>,
+) -> Vec<(String, HighlightStyle)> {
+    styles
+        .iter()
+        .map(|(key, style)| {
+            (
+                key.clone(),
+                HighlightStyle {
+                    color: style
+                        .color
+                        .as_ref()
+                        .and_then(|color| try_parse_color(color).ok()),
+                    background_color: style
+                        .background_color
+                        .as_ref()
+                        .and_then(|color| try_parse_color(color).ok()),
+                    font_style: style
+                        .font_style
+                        .map(|font_style| FontStyle::from(font_style)),
+                    font_weight: style
+                        .font_weight
+                        .map(|font_weight| FontWeight::from(font_weight)),
+                    ..Default::default()
+                },
+            )
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct ThemeColorsContent {
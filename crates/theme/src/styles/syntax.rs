@@ -1,10 +1,52 @@
 use std::sync::Arc;
 
-use gpui::{HighlightStyle, Hsla};
+use gpui::{HighlightStyle, Hsla, UnderlineStyle};
 
-#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct SyntaxTheme {
     pub highlights: Vec<(String, HighlightStyle)>,
+    /// Styles for syntax tree nodes of the given kinds, such as `line_comment`, which
+    /// are used to highlight languages that don't have a highlights query.
+    pub node_kind_styles: Vec<(String, HighlightStyle)>,
+    /// The opacity of the colors of highlights within injected languages, such as SQL
+    /// in a Rust string, which fades them toward the background. Defaults to `1.0`,
+    /// which leaves them unchanged.
+    pub injected_opacity: Option<f32>,
+    /// Styles for highlights nested within other highlights, keyed by scope selectors
+    /// like `meta.attribute string.special`, which apply to `string.special` highlights
+    /// within `meta.attribute` highlights.
+    pub selectors: Vec<(String, HighlightStyle)>,
+    /// The colors of the underlines beneath syntax errors.
+    pub diagnostic_style: DiagnosticStyle,
+}
+
+/// The colors of the underlines beneath problems in the syntax of the text, which
+/// default to the theme's status colors.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct DiagnosticStyle {
+    pub error: Option<Hsla>,
+    pub warning: Option<Hsla>,
+}
+
+impl DiagnosticStyle {
+    /// Returns the underline beneath errors, if the theme has a color for them.
+    pub fn error_underline(&self) -> Option<UnderlineStyle> {
+        self.error.map(diagnostic_underline)
+    }
+
+    /// Returns the underline beneath warnings, if the theme has a color for them.
+    pub fn warning_underline(&self) -> Option<UnderlineStyle> {
+        self.warning.map(diagnostic_underline)
+    }
+}
+
+/// Returns a wavy underline like the ones beneath language server diagnostics.
+fn diagnostic_underline(color: Hsla) -> UnderlineStyle {
+    UnderlineStyle {
+        color: Some(color),
+        thickness: 1.0.into(),
+        wavy: true,
+    }
 }
 
 impl SyntaxTheme {
@@ -30,6 +72,10 @@ impl SyntaxTheme {
                 .into_iter()
                 .map(|(key, style)| (key.to_owned(), style))
                 .collect(),
+            node_kind_styles: Vec::new(),
+            injected_opacity: None,
+            selectors: Vec::new(),
+            diagnostic_style: DiagnosticStyle::default(),
         }
     }
 
@@ -44,21 +90,77 @@ impl SyntaxTheme {
         self.get(name).color.unwrap_or_default()
     }
 
+    /// Returns the style for syntax tree nodes of the given kind, if there is one.
+    pub fn node_kind_style(&self, kind: &str) -> Option<HighlightStyle> {
+        self.node_kind_styles
+            .iter()
+            .find_map(|(node_kind, style)| (node_kind == kind).then_some(*style))
+    }
+
+    /// Returns the given style adjusted for text at the given depth of language
+    /// injection, where zero is the top-level language.
+    pub fn injected_style(&self, mut style: HighlightStyle, depth: usize) -> HighlightStyle {
+        if depth > 0 {
+            let opacity = self.injected_opacity.unwrap_or(1.).clamp(0., 1.);
+            if let Some(color) = style.color.as_mut() {
+                color.a *= opacity;
+            }
+        }
+        style
+    }
+
+    /// Returns the style of the most specific scope selector that matches the given
+    /// highlight names, which go from the outermost highlight to the innermost.
+    ///
+    /// A selector matches when its last scope matches the innermost highlight, and each
+    /// of its other scopes matches an enclosing highlight, in order. Like highlight
+    /// names, each scope also matches the names that it's a dot-separated prefix of.
+    pub fn selector_style(&self, scopes: &[&str]) -> Option<HighlightStyle> {
+        let (innermost_scope, enclosing_scopes) = scopes.split_last()?;
+        self.selectors
+            .iter()
+            .filter_map(|(selector, style)| {
+                let mut selector_scopes = selector.split_whitespace().rev();
+                let innermost_selector_scope = selector_scopes.next()?;
+                if !scope_matches(innermost_selector_scope, innermost_scope) {
+                    return None;
+                }
+                let mut enclosing_scopes = enclosing_scopes.iter().rev();
+                let mut selector_len = 1;
+                for selector_scope in selector_scopes {
+                    enclosing_scopes.find(|scope| scope_matches(selector_scope, scope))?;
+                    selector_len += 1;
+                }
+                let specificity = (selector_len, innermost_selector_scope.split('.').count());
+                Some((specificity, *style))
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, style)| style)
+    }
+
     pub fn highlight_id(&self, name: &str) -> Option<u32> {
         let ix = self.highlights.iter().position(|entry| entry.0 == name)?;
         Some(ix as u32)
     }
 
     /// Returns a new [`Arc<SyntaxTheme>`] with the given syntax styles merged in.
+    ///
+    /// Styles whose names contain whitespace are merged into the scope selectors.
     pub fn merge(base: Arc<Self>, user_syntax_styles: Vec<(String, HighlightStyle)>) -> Arc<Self> {
         if user_syntax_styles.is_empty() {
             return base;
         }
 
         let mut merged_highlights = base.highlights.clone();
+        let mut merged_selectors = base.selectors.clone();
 
         for (name, highlight) in user_syntax_styles {
-            if let Some((_, existing_highlight)) = merged_highlights
+            let merged_styles = if name.contains(char::is_whitespace) {
+                &mut merged_selectors
+            } else {
+                &mut merged_highlights
+            };
+            if let Some((_, existing_highlight)) = merged_styles
                 .iter_mut()
                 .find(|(existing_name, _)| existing_name == &name)
             {
@@ -75,16 +177,26 @@ impl SyntaxTheme {
                     highlight.strikethrough.or(existing_highlight.strikethrough);
                 existing_highlight.fade_out = highlight.fade_out.or(existing_highlight.fade_out);
             } else {
-                merged_highlights.push((name, highlight));
+                merged_styles.push((name, highlight));
             }
         }
 
         Arc::new(Self {
             highlights: merged_highlights,
+            node_kind_styles: base.node_kind_styles.clone(),
+            injected_opacity: base.injected_opacity,
+            selectors: merged_selectors,
+            diagnostic_style: base.diagnostic_style,
         })
     }
 }
 
+fn scope_matches(selector_scope: &str, scope: &str) -> bool {
+    scope
+        .strip_prefix(selector_scope)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+}
+
 #[cfg(test)]
 mod tests {
     use gpui::FontStyle;
@@ -192,4 +304,35 @@ mod tests {
             ]))
         );
     }
+
+    #[test]
+    fn test_syntax_theme_selectors() {
+        let syntax_theme = SyntaxTheme::merge(
+            Arc::new(SyntaxTheme::new_test([("string", gpui::red())])),
+            vec![
+                ("meta.attribute string".to_string(), gpui::green().into()),
+                ("meta string.special".to_string(), gpui::blue().into()),
+            ],
+        );
+        assert_eq!(
+            syntax_theme.highlights,
+            SyntaxTheme::new_test([("string", gpui::red())]).highlights
+        );
+        assert_eq!(syntax_theme.selectors.len(), 2);
+
+        let color = |scopes: &[&str]| {
+            syntax_theme
+                .selector_style(scopes)
+                .and_then(|style| style.color)
+        };
+        assert_eq!(color(&["string.special"]), None);
+        assert_eq!(color(&["meta.attribute", "string"]), Some(gpui::green()));
+        // The selector whose innermost scope is more specific wins.
+        assert_eq!(
+            color(&["meta.attribute", "punctuation", "string.special"]),
+            Some(gpui::blue())
+        );
+        assert_eq!(color(&["meta.attribute", "strings"]), None);
+        assert_eq!(color(&["string", "meta.attribute"]), None);
+    }
 }
@@ -55,6 +55,7 @@ impl VsCodeThemeConverter {
         Ok(ThemeContent {
             name: self.theme_metadata.name,
             appearance,
+            parent: None,
             style: ThemeStyleContent {
                 window_background_appearance: Some(theme::WindowBackgroundContent::Opaque),
                 accents: Vec::new(), //TODO can we read this from the theme?
@@ -62,6 +63,8 @@ impl VsCodeThemeConverter {
                 status: status_colors,
                 players: Vec::new(),
                 syntax: syntax_theme,
+                node_kind_styles: IndexMap::default(),
+                injected_opacity: None,
             },
         })
     }
@@ -41,6 +41,7 @@ pub fn add_surrounds(text: Arc<str>, target: SurroundsType, cx: &mut WindowConte
                         end: text.to_string(),
                         close: true,
                         newline: false,
+                        surround: true,
                     },
                 };
                 let surround = pair.end != *text;
@@ -228,6 +229,7 @@ pub fn change_surrounds(text: Arc<str>, target: Object, cx: &mut WindowContext)
                             end: text.to_string(),
                             close: true,
                             newline: false,
+                            surround: true,
                         },
                     };
                     let surround = pair.end != *text;
@@ -389,54 +391,63 @@ fn all_support_surround_pair() -> Vec<BracketPair> {
             end: "}".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "'".into(),
             end: "'".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "`".into(),
             end: "`".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "\"".into(),
             end: "\"".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "(".into(),
             end: ")".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "|".into(),
             end: "|".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "[".into(),
             end: "]".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "{".into(),
             end: "}".into(),
             close: true,
             newline: false,
+            surround: true,
         },
         BracketPair {
             start: "<".into(),
             end: ">".into(),
             close: true,
             newline: false,
+            surround: true,
         },
     ];
 }
@@ -462,48 +473,56 @@ fn object_to_bracket_pair(object: Object) -> Option<BracketPair> {
             end: "'".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         Object::BackQuotes => Some(BracketPair {
             start: "`".to_string(),
             end: "`".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         Object::DoubleQuotes => Some(BracketPair {
             start: "\"".to_string(),
             end: "\"".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         Object::VerticalBars => Some(BracketPair {
             start: "|".to_string(),
             end: "|".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         Object::Parentheses => Some(BracketPair {
             start: "(".to_string(),
             end: ")".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         Object::SquareBrackets => Some(BracketPair {
             start: "[".to_string(),
             end: "]".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         Object::CurlyBrackets => Some(BracketPair {
             start: "{".to_string(),
             end: "}".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         Object::AngleBrackets => Some(BracketPair {
             start: "<".to_string(),
             end: ">".to_string(),
             close: true,
             newline: false,
+            surround: true,
         }),
         _ => None,
     }